@@ -0,0 +1,280 @@
+use std::f64::consts::PI;
+
+use crate::{
+    color::Color,
+    lighting::{Material, ShadingModel},
+    microfacet::Microfacet,
+    object::Object,
+    sampling::{cosine_sample_hemisphere, direction_around},
+    tuple::Tuple,
+};
+
+/// A direction chosen by `sample`, with the BSDF value and solid angle density
+/// for that direction.
+#[derive(Clone, Copy, Debug)]
+pub struct BsdfSample {
+    pub direction: Tuple,
+    pub value: Color,
+    pub pdf: f64,
+}
+
+/// Reflectance of a material at a surface point, used by the stochastic
+/// integrators. The Phong materials are treated as a Lambertian lobe plus a
+/// normalized Phong or Blinn-Phong specular lobe.
+pub struct Bsdf {
+    lobes: Lobes,
+    normal: Tuple,
+}
+
+enum Lobes {
+    Phong {
+        albedo: Color,
+        specular: f64,
+        shininess: f64,
+        half_vector: bool,
+    },
+    Microfacet(Microfacet),
+}
+
+impl Bsdf {
+    pub fn new(object: &Object, position: &Tuple, normal: &Tuple) -> Bsdf {
+        let material = object.material;
+        let base_color = material.pattern.color_at(object, position);
+        let lobes = match material.microfacet(base_color) {
+            Some(microfacet) => Lobes::Microfacet(microfacet),
+            None => {
+                let half_vector = material.shading == ShadingModel::BlinnPhong;
+                Lobes::Phong {
+                    albedo: base_color.scalar_mul(material.diffuse),
+                    specular: material.specular,
+                    shininess: if half_vector {
+                        material.shininess * Material::BLINN_PHONG_SHININESS_SCALE
+                    } else {
+                        material.shininess
+                    },
+                    half_vector,
+                }
+            }
+        };
+
+        Bsdf {
+            lobes,
+            normal: *normal,
+        }
+    }
+
+    /// BSDF value for light arriving from `wi` and leaving towards `wo`.
+    pub fn eval(&self, wo: &Tuple, wi: &Tuple) -> Color {
+        if wi.dot(&self.normal) <= 0.0 || wo.dot(&self.normal) <= 0.0 {
+            return Color::BLACK;
+        }
+
+        match &self.lobes {
+            Lobes::Microfacet(microfacet) => microfacet.eval(&self.normal, wo, wi),
+            Lobes::Phong {
+                albedo,
+                specular,
+                shininess,
+                half_vector,
+            } => {
+                let diffuse = albedo.scalar_mul(1.0 / PI);
+                let cos_alpha = self.specular_cosine(wo, wi, *half_vector);
+                if cos_alpha <= 0.0 {
+                    return diffuse;
+                }
+
+                let normalization = if *half_vector {
+                    (shininess + 8.0) / (8.0 * PI)
+                } else {
+                    (shininess + 2.0) / (2.0 * PI)
+                };
+                let specular = specular * normalization * cos_alpha.powf(*shininess);
+                diffuse.add(&Color::new(specular, specular, specular))
+            }
+        }
+    }
+
+    pub fn pdf(&self, wo: &Tuple, wi: &Tuple) -> f64 {
+        let cos_theta = wi.dot(&self.normal);
+        if cos_theta <= 0.0 {
+            return 0.0;
+        }
+
+        match &self.lobes {
+            Lobes::Microfacet(microfacet) => microfacet.pdf(&self.normal, wo, wi),
+            Lobes::Phong {
+                shininess,
+                half_vector,
+                ..
+            } => {
+                let specular_probability = self.specular_probability();
+                let cos_alpha = self.specular_cosine(wo, wi, *half_vector).max(0.0);
+                let mut specular_pdf = (shininess + 1.0) / (2.0 * PI) * cos_alpha.powf(*shininess);
+                if *half_vector {
+                    // Convert from the density of half vectors to that of `wi`
+                    let half = wo.add(wi).normalize();
+                    specular_pdf /= 4.0 * wo.dot(&half).max(f64::EPSILON);
+                }
+
+                (1.0 - specular_probability) * cos_theta / PI + specular_probability * specular_pdf
+            }
+        }
+    }
+
+    /// Picks an incoming direction for light leaving towards `wo`, choosing
+    /// between the diffuse and specular lobes by their relative strength.
+    pub fn sample(&self, wo: &Tuple, u1: f64, u2: f64, u3: f64) -> Option<BsdfSample> {
+        let direction = match &self.lobes {
+            Lobes::Microfacet(microfacet) => {
+                microfacet.sample_direction(&self.normal, wo, u1, u2, u3)
+            }
+            Lobes::Phong {
+                shininess,
+                half_vector,
+                ..
+            } => {
+                if u1 < self.specular_probability() {
+                    let cos_alpha = u2.powf(1.0 / (shininess + 1.0));
+                    if *half_vector {
+                        let half = direction_around(&self.normal, cos_alpha, 2.0 * PI * u3);
+                        wo.negate().reflect(&half)
+                    } else {
+                        direction_around(&self.mirror(wo), cos_alpha, 2.0 * PI * u3)
+                    }
+                } else {
+                    cosine_sample_hemisphere(&self.normal, u2, u3)
+                }
+            }
+        };
+
+        let pdf = self.pdf(wo, &direction);
+        if pdf <= 0.0 {
+            return None;
+        }
+
+        Some(BsdfSample {
+            direction,
+            value: self.eval(wo, &direction),
+            pdf,
+        })
+    }
+
+    fn mirror(&self, wo: &Tuple) -> Tuple {
+        wo.negate().reflect(&self.normal)
+    }
+
+    /// Cosine of the angle the Phong lobes raise to their exponent: between
+    /// `wi` and the mirror direction, or between the half vector and normal.
+    fn specular_cosine(&self, wo: &Tuple, wi: &Tuple, half_vector: bool) -> f64 {
+        if half_vector {
+            wo.add(wi).normalize().dot(&self.normal)
+        } else {
+            self.mirror(wo).dot(wi)
+        }
+    }
+
+    fn specular_probability(&self) -> f64 {
+        match &self.lobes {
+            Lobes::Phong {
+                albedo, specular, ..
+            } => {
+                let diffuse = albedo.luminance();
+                if diffuse + specular <= 0.0 {
+                    0.0
+                } else {
+                    specular / (diffuse + specular)
+                }
+            }
+            Lobes::Microfacet(_) => 1.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+
+    use crate::{
+        color::Color,
+        lighting::{Material, ShadingModel},
+        matrix4::Matrix4,
+        object::{Object, ObjectType},
+        sampling::Rng,
+        tuple::Tuple,
+    };
+
+    use super::Bsdf;
+
+    const POINT: Tuple = Tuple::point(0.0, 0.0, 0.0);
+    const NORMAL: Tuple = Tuple::vector(0.0, 0.0, -1.0);
+
+    fn matte() -> Object {
+        Object {
+            object_type: ObjectType::Sphere,
+            material: Material {
+                specular: 0.0,
+                ..Material::DEFAULT
+            },
+            transform: Matrix4::IDENTITY,
+            ..Object::SPHERE
+        }
+    }
+
+    #[test]
+    fn lambertian_eval() {
+        let bsdf = Bsdf::new(&matte(), &POINT, &NORMAL);
+        let wo = Tuple::vector(0.0, 0.0, -1.0);
+        let wi = Tuple::vector(0.0, 1.0, -1.0).normalize();
+        let expected = Material::DEFAULT_DIFFUSE / PI;
+        assert_abs_diff_eq!(
+            bsdf.eval(&wo, &wi),
+            Color::new(expected, expected, expected)
+        );
+        assert_abs_diff_eq!(bsdf.eval(&wo, &wo.negate()), Color::BLACK);
+    }
+
+    #[test]
+    fn lambertian_sample_pdf() {
+        let bsdf = Bsdf::new(&matte(), &POINT, &NORMAL);
+        let wo = Tuple::vector(0.0, 0.0, -1.0);
+        let sample = bsdf.sample(&wo, 0.5, 0.3, 0.7).unwrap();
+        assert_abs_diff_eq!(sample.pdf, sample.direction.dot(&NORMAL) / PI);
+    }
+
+    #[test]
+    fn sampled_pdf_matches_pdf() {
+        let metal = Object {
+            material: Material {
+                shading: ShadingModel::Microfacet {
+                    metallic: 1.0,
+                    roughness: 0.4,
+                },
+                ..Material::DEFAULT
+            },
+            ..Object::SPHERE
+        };
+        let wo = Tuple::vector(0.0, 1.0, -1.0).normalize();
+        let mut rng = Rng::new(3);
+        let blinn = Object {
+            material: Material {
+                shading: ShadingModel::BlinnPhong,
+                shininess: 20.0,
+                ..Material::DEFAULT
+            },
+            ..Object::SPHERE
+        };
+        for bsdf in [
+            Bsdf::new(&Object::SPHERE, &POINT, &NORMAL),
+            Bsdf::new(&metal, &POINT, &NORMAL),
+            Bsdf::new(&blinn, &POINT, &NORMAL),
+        ] {
+            for _ in 0..50 {
+                if let Some(sample) =
+                    bsdf.sample(&wo, rng.next_f64(), rng.next_f64(), rng.next_f64())
+                {
+                    assert_abs_diff_eq!(sample.pdf, bsdf.pdf(&wo, &sample.direction));
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,318 @@
+//! Saving the state of a render so it can be resumed after being stopped.
+//!
+//! A checkpoint holds the running totals and sample counts of every pixel.
+//! Every sample of every pixel has its own random sequence, derived from the
+//! pixel and the sample number, so there is no other random state to keep:
+//! resuming with the same scene and camera traces exactly the samples that
+//! were missing and produces the same image as an uninterrupted render.
+//!
+//! The file starts with the 8-byte signature `RRCKPT02`, followed by the
+//! width, height and samples per pixel of the render as little-endian `u32`s,
+//! then for every pixel, row by row, the red, green, blue, alpha and squared
+//! luminance totals as little-endian `f64`s and the number of samples as a
+//! `u32`.
+
+use std::{
+    error::Error,
+    fmt,
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+use crate::{
+    color::Color,
+    render::{Accumulator, PixelTotal},
+    world::Camera,
+};
+
+const SIGNATURE: &[u8; 8] = b"RRCKPT02";
+
+/// Why a checkpoint could not be read or used.
+#[derive(Debug)]
+pub enum CheckpointError {
+    Io(io::Error),
+    /// The data is not a checkpoint, or is cut short.
+    Malformed(String),
+    /// The checkpoint is of a render with a different size or number of
+    /// samples than the camera.
+    Mismatch(String),
+}
+
+impl fmt::Display for CheckpointError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CheckpointError::Io(error) => write!(f, "{}", error),
+            CheckpointError::Malformed(message) => write!(f, "malformed checkpoint: {}", message),
+            CheckpointError::Mismatch(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl Error for CheckpointError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            CheckpointError::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for CheckpointError {
+    fn from(error: io::Error) -> CheckpointError {
+        if error.kind() == io::ErrorKind::UnexpectedEof {
+            CheckpointError::Malformed("unexpected end of data".to_string())
+        } else {
+            CheckpointError::Io(error)
+        }
+    }
+}
+
+/// The state of a render with `samples_per_pixel` samples for every pixel
+/// once finished.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Checkpoint {
+    pub(crate) samples_per_pixel: u32,
+    pub(crate) accumulator: Accumulator,
+}
+
+impl Checkpoint {
+    /// Whether the checkpoint can be resumed with `camera`. The scene and the
+    /// camera's view are not recorded, and are up to the caller to keep the
+    /// same. No pixel may have more samples than the render takes.
+    pub fn check(&self, camera: &Camera) -> Result<(), CheckpointError> {
+        let (width, height) = (self.accumulator.width(), self.accumulator.height());
+        if (width, height, self.samples_per_pixel)
+            != (camera.hsize(), camera.vsize(), camera.samples_per_pixel())
+        {
+            return Err(CheckpointError::Mismatch(format!(
+                "checkpoint is of a {}x{} render with {} samples per pixel, not {}x{} with {}",
+                width,
+                height,
+                self.samples_per_pixel,
+                camera.hsize(),
+                camera.vsize(),
+                camera.samples_per_pixel()
+            )));
+        }
+        let pixels = (0..height).flat_map(|y| (0..width).map(move |x| (x, y)));
+        for (x, y) in pixels {
+            let samples = self.accumulator.samples_at(x, y);
+            if samples > self.samples_per_pixel {
+                return Err(CheckpointError::Mismatch(format!(
+                    "checkpoint has {} samples of pixel {},{}, more than the {} per pixel of the render",
+                    samples, x, y, self.samples_per_pixel
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let accumulator = &self.accumulator;
+        writer.write_all(SIGNATURE)?;
+        for value in [
+            accumulator.width() as u32,
+            accumulator.height() as u32,
+            self.samples_per_pixel,
+        ] {
+            writer.write_all(&value.to_le_bytes())?;
+        }
+        for y in 0..accumulator.height() {
+            for x in 0..accumulator.width() {
+                let total = accumulator.total_at(x, y);
+                let color = total.color;
+                for value in [
+                    color.red,
+                    color.green,
+                    color.blue,
+                    total.alpha,
+                    total.luminance_squares,
+                ] {
+                    writer.write_all(&value.to_le_bytes())?;
+                }
+                writer.write_all(&total.samples.to_le_bytes())?;
+            }
+        }
+        writer.flush()
+    }
+
+    pub fn read<R: Read>(reader: &mut R) -> Result<Checkpoint, CheckpointError> {
+        let mut signature = [0; 8];
+        reader.read_exact(&mut signature)?;
+        if &signature != SIGNATURE {
+            return Err(CheckpointError::Malformed("missing signature".to_string()));
+        }
+
+        let width = read_u32(reader)?;
+        let height = read_u32(reader)?;
+        let samples_per_pixel = read_u32(reader)?;
+        let size = i32::try_from(width).ok().zip(i32::try_from(height).ok());
+        let (width, height) = match size {
+            Some((width, height)) if width.checked_mul(height).is_some() => (width, height),
+            _ => {
+                return Err(CheckpointError::Malformed(format!(
+                    "image size {}x{} is too large",
+                    width, height
+                )))
+            }
+        };
+
+        // Read before allocating the accumulator, so a bad header cannot ask
+        // for more memory than the data there is
+        let mut totals = Vec::new();
+        for _ in 0..width * height {
+            let red = read_f64(reader)?;
+            let green = read_f64(reader)?;
+            let blue = read_f64(reader)?;
+            totals.push(PixelTotal {
+                color: Color::new(red, green, blue),
+                alpha: read_f64(reader)?,
+                luminance_squares: read_f64(reader)?,
+                samples: read_u32(reader)?,
+            });
+        }
+        let mut accumulator = Accumulator::new(width, height);
+        for (i, total) in (0..).zip(totals) {
+            accumulator.set_total(i % width, i / width, total);
+        }
+        Ok(Checkpoint {
+            samples_per_pixel,
+            accumulator,
+        })
+    }
+
+    /// Writes the checkpoint to a temporary file next to `path` first, so an
+    /// interruption while saving leaves the previous checkpoint intact.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let path = path.as_ref();
+        let mut temporary = path.as_os_str().to_owned();
+        temporary.push(".tmp");
+        self.write(&mut BufWriter::new(File::create(&temporary)?))?;
+        fs::rename(&temporary, path)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Checkpoint, CheckpointError> {
+        Checkpoint::read(&mut BufReader::new(File::open(path)?))
+    }
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_f64<R: Read>(reader: &mut R) -> io::Result<f64> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(f64::from_le_bytes(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+
+    use crate::{
+        color::Color,
+        matrix4::Matrix4,
+        render::{Accumulator, PixelTotal},
+        world::Camera,
+    };
+
+    use super::{Checkpoint, CheckpointError};
+
+    #[test]
+    fn checkpoint_round_trip() {
+        let mut accumulator = Accumulator::new(3, 2);
+        let total = PixelTotal::ZERO
+            .add((Color::new(0.1, 1.0 / 3.0, 2.5e-9), 0.75))
+            .add((Color::new(4.0, 5.0, 6.0), 0.5));
+        accumulator.set_total(0, 0, total);
+        accumulator.set_total(2, 1, total.add((Color::WHITE, 1.0)));
+        let checkpoint = Checkpoint {
+            samples_per_pixel: 4,
+            accumulator,
+        };
+
+        let mut bytes = Vec::new();
+        checkpoint.write(&mut bytes).unwrap();
+        assert_eq!(bytes.len(), 8 + 12 + 6 * 44);
+        assert_eq!(Checkpoint::read(&mut bytes.as_slice()).unwrap(), checkpoint);
+    }
+
+    #[test]
+    fn malformed_checkpoints() {
+        let error = |bytes: &[u8]| match Checkpoint::read(&mut &bytes[..]) {
+            Err(CheckpointError::Malformed(message)) => message,
+            _ => panic!("expected a malformed checkpoint"),
+        };
+        assert_eq!(error(b"P6\n1 1\n255\n"), "missing signature");
+
+        let mut bytes = Vec::new();
+        Checkpoint {
+            samples_per_pixel: 1,
+            accumulator: Accumulator::new(2, 2),
+        }
+        .write(&mut bytes)
+        .unwrap();
+        assert_eq!(error(&bytes[..bytes.len() - 1]), "unexpected end of data");
+
+        let mut huge = b"RRCKPT02".to_vec();
+        for value in [u32::MAX, 2, 1] {
+            huge.extend(value.to_le_bytes());
+        }
+        assert_eq!(error(&huge), "image size 4294967295x2 is too large");
+
+        // Fits an i32 but would need 94 GB, with no pixel data to back it
+        let mut header = b"RRCKPT02".to_vec();
+        for value in [46340, 46340, 1] {
+            header.extend(u32::to_le_bytes(value));
+        }
+        assert_eq!(error(&header), "unexpected end of data");
+    }
+
+    #[test]
+    fn checkpoint_must_match_camera() {
+        let checkpoint = Checkpoint {
+            samples_per_pixel: 4,
+            accumulator: Accumulator::new(20, 10),
+        };
+        let camera = Camera::new(20, 10, PI / 2.0, Matrix4::IDENTITY).with_samples_per_pixel(4);
+        assert!(checkpoint.check(&camera).is_ok());
+        assert_eq!(
+            checkpoint
+                .check(&camera.with_samples_per_pixel(8))
+                .unwrap_err()
+                .to_string(),
+            "checkpoint is of a 20x10 render with 4 samples per pixel, not 20x10 with 8"
+        );
+    }
+
+    #[test]
+    fn checkpoint_samples_must_not_exceed_samples_per_pixel() {
+        let mut accumulator = Accumulator::new(3, 2);
+        let sample = (Color::WHITE, 1.0);
+        let total = PixelTotal::ZERO.add(sample).add(sample);
+        accumulator.set_total(1, 0, total);
+        let checkpoint = Checkpoint {
+            samples_per_pixel: 2,
+            accumulator,
+        };
+        let camera = Camera::new(3, 2, PI / 2.0, Matrix4::IDENTITY).with_samples_per_pixel(2);
+        assert!(checkpoint.check(&camera).is_ok());
+
+        let mut checkpoint = checkpoint;
+        checkpoint.accumulator.set_total(2, 1, total.add(sample));
+        let mut bytes = Vec::new();
+        checkpoint.write(&mut bytes).unwrap();
+        let checkpoint = Checkpoint::read(&mut bytes.as_slice()).unwrap();
+        match checkpoint.check(&camera) {
+            Err(CheckpointError::Mismatch(message)) => assert_eq!(
+                message,
+                "checkpoint has 3 samples of pixel 2,1, more than the 2 per pixel of the render"
+            ),
+            _ => panic!("expected a mismatched checkpoint"),
+        }
+    }
+}
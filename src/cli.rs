@@ -0,0 +1,708 @@
+//! Command-line interface of the renderer binary.
+
+use std::{
+    fmt, fs,
+    io::{self, IsTerminal, Write},
+    path::{Path, PathBuf},
+    process::ExitCode,
+    thread,
+    time::{Duration, Instant},
+};
+
+use rusty_ray::{
+    load_scene, save_hdr, save_pfm, save_png, save_ppm, Budget, Camera, Canvas, Checkpoint,
+    CheckpointError, ColorType, Integrator, PngOptions, PpmFormat, Progress, Region, RenderControl,
+    SceneError, SnapshotInterval,
+};
+
+pub const USAGE: &str = "\
+Usage: rusty-ray [OPTIONS] <SCENE>
+
+Renders a YAML scene file to an image.
+
+Options:
+  -o, --output <PATH>        Image to write, in the format given by its
+                             extension: .png, .ppm, .pam, .hdr or .pfm
+                             [default: the scene's name with .png]
+      --width <PIXELS>       Image width; keeps the scene's aspect ratio
+                             unless --height is also given
+      --height <PIXELS>      Image height; keeps the scene's aspect ratio
+                             unless --width is also given
+      --fov <DEGREES>        Field of view
+  -s, --samples <COUNT>      Samples per pixel [default: the scene's, or
+                             65536 with --time-limit or --noise]
+  -j, --threads <COUNT>      Render threads [default: one per core]
+      --integrator <NAME>    whitted or path-tracer
+      --max-depth <COUNT>    Bounces followed by the path tracer
+      --region <X,Y,WIDTH,HEIGHT>
+                             Render only this rectangle of the image, in
+                             pixels from the top left, and write it cropped
+      --time-limit <SECONDS> Stop adding samples after this long
+      --noise <LEVEL>        Stop adding samples once the estimated noise,
+                             the RMS standard error of pixel luminance, is
+                             at most this
+      --snapshot-every <SECONDS>
+                             Render progressively, writing the image so far
+                             to the output this often
+      --checkpoint <PATH>    Save the state of the render here, to carry on
+                             from it if the same command is run again after
+                             being stopped; removed once the image is written
+      --checkpoint-every <SECONDS>
+                             How often to save the checkpoint [default: 60]
+  -h, --help                 Print this help
+
+Exits with 0 on success, 1 if the scene or checkpoint cannot be loaded, 2
+for invalid arguments and 3 if the image cannot be written.
+";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OutputFormat {
+    Png,
+    Ppm,
+    Pam,
+    Hdr,
+    Pfm,
+}
+
+impl OutputFormat {
+    /// The format named by the extension of `path`, ignoring case.
+    pub fn from_path(path: &Path) -> Option<OutputFormat> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "png" => Some(OutputFormat::Png),
+            "ppm" => Some(OutputFormat::Ppm),
+            "pam" => Some(OutputFormat::Pam),
+            "hdr" => Some(OutputFormat::Hdr),
+            "pfm" => Some(OutputFormat::Pfm),
+            _ => None,
+        }
+    }
+}
+
+/// Settings from the command line. Those left as `None` come from the scene.
+#[derive(Debug, PartialEq)]
+pub struct Options {
+    pub scene: PathBuf,
+    pub output: PathBuf,
+    pub format: OutputFormat,
+    pub width: Option<i32>,
+    pub height: Option<i32>,
+    /// In radians, although given in degrees on the command line.
+    pub field_of_view: Option<f64>,
+    pub samples: Option<u32>,
+    pub threads: Option<usize>,
+    pub integrator: Option<Integrator>,
+    pub max_depth: Option<u32>,
+    /// Rectangle of the image to render and write, after resizing.
+    pub region: Option<Region>,
+    pub time_limit: Option<Duration>,
+    /// Target for `Accumulator::noise`.
+    pub noise: Option<f64>,
+    /// Renders progressively, rewriting the output this often.
+    pub snapshot_interval: Option<Duration>,
+    pub checkpoint: Option<PathBuf>,
+    pub checkpoint_interval: Option<Duration>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Help,
+    Render(Box<Options>),
+}
+
+/// Arguments that do not make sense.
+#[derive(Debug, PartialEq)]
+pub struct UsageError(pub String);
+
+impl fmt::Display for UsageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+fn positive<T: std::str::FromStr + PartialOrd + Default>(
+    option: &str,
+    value: &str,
+) -> Result<T, UsageError> {
+    value
+        .parse::<T>()
+        .ok()
+        .filter(|n| *n > T::default())
+        .ok_or_else(|| {
+            UsageError(format!(
+                "{} needs a positive number, found `{}`",
+                option, value
+            ))
+        })
+}
+
+fn seconds(option: &str, value: &str) -> Result<Duration, UsageError> {
+    let seconds: f64 = positive(option, value)?;
+    Duration::try_from_secs_f64(seconds)
+        .map_err(|_| UsageError(format!("{} is out of range, found `{}`", option, value)))
+}
+
+fn region(value: &str) -> Result<Region, UsageError> {
+    let numbers: Vec<i32> = value
+        .split(',')
+        .map(|number| number.trim().parse().ok().filter(|n| *n >= 0))
+        .collect::<Option<_>>()
+        .unwrap_or_default();
+    match numbers[..] {
+        [x, y, width, height] if width > 0 && height > 0 => Ok(Region {
+            x,
+            y,
+            width,
+            height,
+        }),
+        _ => Err(UsageError(format!(
+            "--region needs X,Y,WIDTH,HEIGHT with a positive size, found `{}`",
+            value
+        ))),
+    }
+}
+
+/// Parses the arguments after the program name.
+pub fn parse_args<I: IntoIterator<Item = String>>(args: I) -> Result<Command, UsageError> {
+    let mut args = args.into_iter();
+    let mut scene = None;
+    let mut output = None;
+    let mut options = Options {
+        scene: PathBuf::new(),
+        output: PathBuf::new(),
+        format: OutputFormat::Png,
+        width: None,
+        height: None,
+        field_of_view: None,
+        samples: None,
+        threads: None,
+        integrator: None,
+        max_depth: None,
+        region: None,
+        time_limit: None,
+        noise: None,
+        snapshot_interval: None,
+        checkpoint: None,
+        checkpoint_interval: None,
+    };
+
+    while let Some(arg) = args.next() {
+        if !arg.starts_with('-') || arg == "-" {
+            if scene.replace(PathBuf::from(&arg)).is_some() {
+                return Err(UsageError(format!("unexpected argument `{}`", arg)));
+            }
+            continue;
+        }
+
+        // Values may follow as the next argument or after `=`
+        let (option, inline_value) = match arg.split_once('=') {
+            Some((option, value)) if option.starts_with("--") => {
+                (option.to_string(), Some(value.to_string()))
+            }
+            _ => (arg.clone(), None),
+        };
+        if option == "-h" || option == "--help" {
+            return Ok(Command::Help);
+        }
+        let value = match inline_value.or_else(|| args.next()) {
+            Some(value) => value,
+            None => return Err(UsageError(format!("{} needs a value", option))),
+        };
+
+        match option.as_str() {
+            "-o" | "--output" => output = Some(PathBuf::from(value)),
+            "--width" => options.width = Some(positive(&option, &value)?),
+            "--height" => options.height = Some(positive(&option, &value)?),
+            "--fov" => {
+                let degrees: f64 = positive(&option, &value)?;
+                if degrees >= 180.0 {
+                    return Err(UsageError(format!(
+                        "--fov must be less than 180 degrees, found `{}`",
+                        value
+                    )));
+                }
+                options.field_of_view = Some(degrees.to_radians());
+            }
+            "-s" | "--samples" => options.samples = Some(positive(&option, &value)?),
+            "-j" | "--threads" => options.threads = Some(positive(&option, &value)?),
+            "--max-depth" => options.max_depth = Some(positive(&option, &value)?),
+            "--region" => options.region = Some(region(&value)?),
+            "--time-limit" => options.time_limit = Some(seconds(&option, &value)?),
+            "--noise" => options.noise = Some(positive(&option, &value)?),
+            "--snapshot-every" => options.snapshot_interval = Some(seconds(&option, &value)?),
+            "--checkpoint" => options.checkpoint = Some(PathBuf::from(value)),
+            "--checkpoint-every" => options.checkpoint_interval = Some(seconds(&option, &value)?),
+            "--integrator" => {
+                options.integrator = Some(match value.as_str() {
+                    "whitted" => Integrator::Whitted,
+                    "path-tracer" => Integrator::PathTracer {
+                        max_depth: Integrator::DEFAULT_MAX_DEPTH,
+                    },
+                    _ => {
+                        return Err(UsageError(format!(
+                            "--integrator must be whitted or path-tracer, found `{}`",
+                            value
+                        )))
+                    }
+                })
+            }
+            _ => return Err(UsageError(format!("unknown option `{}`", option))),
+        }
+    }
+
+    if options.checkpoint_interval.is_some() && options.checkpoint.is_none() {
+        return Err(UsageError(
+            "--checkpoint-every needs a --checkpoint file".to_string(),
+        ));
+    }
+    options.scene = scene.ok_or_else(|| UsageError("no scene file given".to_string()))?;
+    options.output = output.unwrap_or_else(|| {
+        let name = options.scene.file_stem().unwrap_or_default();
+        Path::new(name).with_extension("png")
+    });
+    options.format = OutputFormat::from_path(&options.output).ok_or_else(|| {
+        UsageError(format!(
+            "cannot tell the image format of `{}`; use .png, .ppm, .pam, .hdr or .pfm",
+            options.output.display()
+        ))
+    })?;
+    Ok(Command::Render(Box::new(options)))
+}
+
+impl Options {
+    pub const DEFAULT_CHECKPOINT_INTERVAL: Duration = Duration::from_secs(60);
+    /// Samples per pixel for a budgeted render when `--samples` is not
+    /// given, high enough that the budget is what stops it.
+    pub const BUDGET_SAMPLES: u32 = 65536;
+
+    /// The limits from `--time-limit` and `--noise`, if either was given.
+    pub fn budget(&self) -> Option<Budget> {
+        let budget = Budget {
+            time: self.time_limit,
+            noise: self.noise,
+        };
+        (budget != Budget::default()).then_some(budget)
+    }
+
+    /// Applies the command-line overrides to the scene's camera.
+    pub fn configure(&self, mut camera: Camera) -> Result<Camera, UsageError> {
+        let aspect = f64::from(camera.hsize()) / f64::from(camera.vsize());
+        let scaled = |size: f64| (size.round() as i32).max(1);
+        let size = match (self.width, self.height) {
+            (Some(width), Some(height)) => Some((width, height)),
+            (Some(width), None) => Some((width, scaled(f64::from(width) / aspect))),
+            (None, Some(height)) => Some((scaled(f64::from(height) * aspect), height)),
+            (None, None) => None,
+        };
+        if let Some((width, height)) = size {
+            if width.checked_mul(height).is_none() {
+                return Err(UsageError(format!(
+                    "an image of {}x{} pixels is too large",
+                    width, height
+                )));
+            }
+            camera = camera.with_size(width, height);
+        }
+        if let Some(field_of_view) = self.field_of_view {
+            camera = camera.with_field_of_view(field_of_view);
+        }
+        match (self.samples, self.budget()) {
+            (Some(samples), _) => camera = camera.with_samples_per_pixel(samples),
+            (None, Some(_)) => camera = camera.with_samples_per_pixel(Options::BUDGET_SAMPLES),
+            (None, None) => {}
+        }
+
+        // Keep the scene's depth when only the integrator is chosen here
+        let integrator = match (self.integrator, camera.integrator()) {
+            (Some(Integrator::PathTracer { .. }), Integrator::PathTracer { max_depth }) => {
+                Integrator::PathTracer { max_depth }
+            }
+            (Some(integrator), _) => integrator,
+            (None, integrator) => integrator,
+        };
+        let integrator = match (integrator, self.max_depth) {
+            (Integrator::PathTracer { .. }, Some(max_depth)) => {
+                Integrator::PathTracer { max_depth }
+            }
+            (Integrator::Whitted, Some(_)) => {
+                return Err(UsageError(
+                    "--max-depth needs the path tracer; add --integrator path-tracer".to_string(),
+                ))
+            }
+            (integrator, None) => integrator,
+        };
+        camera = camera.with_integrator(integrator);
+
+        let threads = self
+            .threads
+            .unwrap_or_else(|| thread::available_parallelism().map_or(1, |threads| threads.get()));
+        Ok(camera.with_threads(threads))
+    }
+
+    /// The part of `--region` inside the camera's image.
+    pub fn clipped_region(&self, camera: &Camera) -> Result<Option<Region>, UsageError> {
+        let Some(region) = self.region else {
+            return Ok(None);
+        };
+        let clipped = region.clip(camera.hsize(), camera.vsize());
+        if clipped.width == 0 || clipped.height == 0 {
+            return Err(UsageError(format!(
+                "--region is outside the {}x{} image",
+                camera.hsize(),
+                camera.vsize()
+            )));
+        }
+        Ok(Some(clipped))
+    }
+}
+
+fn save(canvas: &Canvas, options: &Options, transparent: bool) -> io::Result<()> {
+    let path = &options.output;
+    match options.format {
+        OutputFormat::Png => {
+            let color_type = if transparent {
+                ColorType::Rgba
+            } else {
+                ColorType::Rgb
+            };
+            let png_options = PngOptions {
+                color_type,
+                ..PngOptions::DEFAULT
+            };
+            save_png(canvas, path, &png_options)
+        }
+        OutputFormat::Ppm => save_ppm(canvas, path, PpmFormat::Binary),
+        OutputFormat::Pam => fs::write(path, canvas.generate_pam()),
+        OutputFormat::Hdr => save_hdr(canvas, path),
+        OutputFormat::Pfm => save_pfm(canvas, path),
+    }
+}
+
+/// Why rendering from the command line failed.
+pub enum Failure {
+    Usage(UsageError),
+    Scene(SceneError),
+    Output(PathBuf, io::Error),
+    Checkpoint(PathBuf, CheckpointError),
+}
+
+impl Failure {
+    pub fn exit_code(&self) -> ExitCode {
+        match self {
+            Failure::Scene(_) | Failure::Checkpoint(_, _) => ExitCode::from(1),
+            Failure::Usage(_) => ExitCode::from(2),
+            Failure::Output(_, _) => ExitCode::from(3),
+        }
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Failure::Usage(error) => write!(f, "{}", error),
+            Failure::Scene(error) => write!(f, "{}", error),
+            Failure::Output(path, error) => {
+                write!(f, "cannot write `{}`: {}", path.display(), error)
+            }
+            Failure::Checkpoint(path, error) => {
+                write!(f, "cannot resume from `{}`: {}", path.display(), error)
+            }
+        }
+    }
+}
+
+/// Overwrites the current terminal line with the progress of the render.
+fn print_progress(progress: &Progress) {
+    let eta = match progress.eta() {
+        Some(eta) => format!(", {}s left", eta.as_secs()),
+        None => String::new(),
+    };
+    let mut stderr = io::stderr().lock();
+    let _ = write!(
+        stderr,
+        "\r\x1b[KRendering: {:.0}%{eta}",
+        progress.fraction() * 100.0
+    );
+    let _ = stderr.flush();
+}
+
+/// Loads, renders and saves the scene, reporting the time taken on stderr.
+pub fn run(options: &Options) -> Result<(), Failure> {
+    let scene = load_scene(&options.scene).map_err(Failure::Scene)?;
+    let camera = options.configure(scene.camera).map_err(Failure::Usage)?;
+    let region = options.clipped_region(&camera).map_err(Failure::Usage)?;
+    let transparent = camera.transparent_background();
+    let terminal = io::stderr().is_terminal();
+    let save_image = |canvas: &Canvas| match region {
+        Some(region) => save(&region.crop(canvas), options, transparent),
+        None => save(canvas, options, transparent),
+    };
+
+    let mut control = RenderControl::new();
+    if terminal {
+        control = control.on_progress(print_progress);
+    }
+    if let Some(region) = region {
+        control = control.region(region);
+    }
+    if let Some(budget) = options.budget() {
+        control = control.budget(budget);
+    }
+    if let Some(interval) = options.snapshot_interval {
+        let snapshot = |canvas: &Canvas| {
+            if let Err(error) = save_image(canvas) {
+                let failure = Failure::Output(options.output.clone(), error);
+                eprintln!("\r\x1b[Kwarning: {}", failure);
+            }
+        };
+        control = control
+            .progressive(true)
+            .on_snapshot(SnapshotInterval::Time(interval), snapshot);
+    }
+    if let Some(path) = &options.checkpoint {
+        if path.exists() {
+            let checkpoint = Checkpoint::load(path)
+                .and_then(|checkpoint| checkpoint.check(&camera).map(|()| checkpoint))
+                .map_err(|error| Failure::Checkpoint(path.clone(), error))?;
+            eprintln!("Resuming from {}", path.display());
+            control = control.resume(checkpoint);
+        }
+        let interval = options
+            .checkpoint_interval
+            .unwrap_or(Options::DEFAULT_CHECKPOINT_INTERVAL);
+        let save_checkpoint = move |checkpoint: &Checkpoint| {
+            if let Err(error) = checkpoint.save(path) {
+                let failure = Failure::Output(path.clone(), error);
+                eprintln!("\r\x1b[Kwarning: {}", failure);
+            }
+        };
+        control = control.on_checkpoint(SnapshotInterval::Time(interval), save_checkpoint);
+    }
+
+    let start = Instant::now();
+    let output = camera
+        .render_with(&scene.world, &control)
+        .map_err(|error| {
+            let path = options.checkpoint.clone().unwrap_or_default();
+            Failure::Checkpoint(path, error)
+        })?;
+    let canvas = output.canvas;
+    if terminal {
+        eprint!("\r\x1b[K");
+    }
+    save_image(&canvas).map_err(|error| Failure::Output(options.output.clone(), error))?;
+    let (width, height) = region.map_or((canvas.width, canvas.height), |region| {
+        (region.width, region.height)
+    });
+    let achieved = match options.budget() {
+        Some(_) => format!(
+            ", {:.1} samples per pixel, noise {:.4}",
+            output.samples_per_pixel, output.noise
+        ),
+        None => String::new(),
+    };
+    eprintln!(
+        "Rendered {} ({}x{}) in {:.1}s{}",
+        options.output.display(),
+        width,
+        height,
+        start.elapsed().as_secs_f64(),
+        achieved
+    );
+    if let Some(path) = &options.checkpoint {
+        fs::remove_file(path).or_else(|error| match error.kind() {
+            io::ErrorKind::NotFound => Ok(()),
+            _ => Err(Failure::Output(path.clone(), error)),
+        })?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{f64::consts::PI, path::PathBuf, time::Duration};
+
+    use rusty_ray::{Budget, Camera, Integrator, Matrix4, Region};
+
+    use super::{parse_args, Command, Options, OutputFormat, UsageError};
+
+    fn parse(args: &[&str]) -> Result<Command, UsageError> {
+        parse_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    fn options(args: &[&str]) -> Options {
+        match parse(args) {
+            Ok(Command::Render(options)) => *options,
+            _ => panic!("expected render options"),
+        }
+    }
+
+    #[test]
+    fn parse_options() {
+        let parsed = options(&[
+            "scenes/room.yaml",
+            "-o",
+            "out/room.HDR",
+            "--width=320",
+            "--fov",
+            "90",
+            "-s",
+            "16",
+            "-j",
+            "2",
+            "--integrator",
+            "path-tracer",
+            "--max-depth",
+            "5",
+            "--snapshot-every=2.5",
+            "--checkpoint",
+            "room.ckpt",
+            "--checkpoint-every",
+            "300",
+            "--region",
+            "10,20,64,48",
+            "--time-limit",
+            "90",
+            "--noise=0.01",
+        ]);
+        assert_eq!(parsed.scene, PathBuf::from("scenes/room.yaml"));
+        assert_eq!(parsed.output, PathBuf::from("out/room.HDR"));
+        assert_eq!(parsed.format, OutputFormat::Hdr);
+        assert_eq!((parsed.width, parsed.height), (Some(320), None));
+        assert_abs_diff_eq!(parsed.field_of_view.unwrap(), PI / 2.0);
+        assert_eq!(parsed.samples, Some(16));
+        assert_eq!(parsed.threads, Some(2));
+        assert_eq!(parsed.max_depth, Some(5));
+        assert_eq!(parsed.snapshot_interval, Some(Duration::from_millis(2500)));
+        assert_eq!(
+            parsed.region,
+            Some(Region {
+                x: 10,
+                y: 20,
+                width: 64,
+                height: 48
+            })
+        );
+        assert_eq!(parsed.checkpoint, Some(PathBuf::from("room.ckpt")));
+        assert_eq!(parsed.checkpoint_interval, Some(Duration::from_secs(300)));
+        assert_eq!(
+            parsed.budget(),
+            Some(Budget {
+                time: Some(Duration::from_secs(90)),
+                noise: Some(0.01)
+            })
+        );
+
+        let defaults = options(&["scenes/room.yaml"]);
+        assert_eq!(defaults.output, PathBuf::from("room.png"));
+        assert_eq!(defaults.format, OutputFormat::Png);
+        assert_eq!(defaults.snapshot_interval, None);
+        assert_eq!(defaults.budget(), None);
+
+        assert_eq!(parse(&["--help"]), Ok(Command::Help));
+    }
+
+    #[test]
+    fn parse_errors() {
+        let error = |args: &[&str]| parse(args).unwrap_err().to_string();
+        assert_eq!(error(&[]), "no scene file given");
+        assert_eq!(error(&["a.yaml", "b.yaml"]), "unexpected argument `b.yaml`");
+        assert_eq!(
+            error(&["a.yaml", "--bogus", "1"]),
+            "unknown option `--bogus`"
+        );
+        assert_eq!(error(&["a.yaml", "--samples"]), "--samples needs a value");
+        assert_eq!(
+            error(&["a.yaml", "--width", "-3"]),
+            "--width needs a positive number, found `-3`"
+        );
+        assert_eq!(
+            error(&["a.yaml", "--region", "1,2,0,4"]),
+            "--region needs X,Y,WIDTH,HEIGHT with a positive size, found `1,2,0,4`"
+        );
+        assert_eq!(
+            error(&["a.yaml", "--region", "1,2,3"]),
+            "--region needs X,Y,WIDTH,HEIGHT with a positive size, found `1,2,3`"
+        );
+        assert_eq!(
+            error(&["a.yaml", "--checkpoint-every", "10"]),
+            "--checkpoint-every needs a --checkpoint file"
+        );
+        assert_eq!(
+            error(&["a.yaml", "--noise", "0"]),
+            "--noise needs a positive number, found `0`"
+        );
+        assert_eq!(
+            error(&["a.yaml", "-o", "a.jpg"]),
+            "cannot tell the image format of `a.jpg`; use .png, .ppm, .pam, .hdr or .pfm"
+        );
+    }
+
+    #[test]
+    fn configure_camera() {
+        let camera = Camera::new(200, 100, PI / 2.0, Matrix4::IDENTITY);
+
+        let configured = options(&["a.yaml", "--height", "50", "-j", "3"])
+            .configure(camera)
+            .unwrap();
+        assert_eq!((configured.hsize(), configured.vsize()), (100, 50));
+        assert_eq!(configured.integrator(), Integrator::Whitted);
+
+        let camera = Camera::new(200, 100, PI / 2.0, Matrix4::IDENTITY).with_samples_per_pixel(4);
+        let budgeted = options(&["a.yaml", "--time-limit", "10"])
+            .configure(camera)
+            .unwrap();
+        assert_eq!(budgeted.samples_per_pixel(), Options::BUDGET_SAMPLES);
+
+        let camera = Camera::new(200, 100, PI / 2.0, Matrix4::IDENTITY);
+        assert!(options(&["a.yaml", "--max-depth", "4"])
+            .configure(camera)
+            .is_err());
+
+        let camera = Camera::new(200, 100, PI / 2.0, Matrix4::IDENTITY)
+            .with_integrator(Integrator::PathTracer { max_depth: 2 });
+        let kept = options(&["a.yaml", "--integrator", "path-tracer"])
+            .configure(camera)
+            .unwrap();
+        assert_eq!(kept.integrator(), Integrator::PathTracer { max_depth: 2 });
+
+        let camera = Camera::new(200, 100, PI / 2.0, Matrix4::IDENTITY);
+        let clipped = options(&["a.yaml", "--region", "150,50,100,100"])
+            .clipped_region(&camera)
+            .unwrap();
+        assert_eq!(
+            clipped,
+            Some(Region {
+                x: 150,
+                y: 50,
+                width: 50,
+                height: 50
+            })
+        );
+        let outside = options(&["a.yaml", "--region", "200,0,10,10"]).clipped_region(&camera);
+        assert_eq!(
+            outside.unwrap_err().to_string(),
+            "--region is outside the 200x100 image"
+        );
+    }
+
+    #[test]
+    fn configure_rejects_oversized_images() {
+        let camera = || Camera::new(200, 100, PI / 2.0, Matrix4::IDENTITY);
+        assert_eq!(
+            parse(&["a.yaml", "--width", "3000000000"])
+                .unwrap_err()
+                .to_string(),
+            "--width needs a positive number, found `3000000000`"
+        );
+        let error = |args: &[&str]| options(args).configure(camera()).err().unwrap().to_string();
+        assert_eq!(
+            error(&["a.yaml", "--width", "100000", "--height", "100000"]),
+            "an image of 100000x100000 pixels is too large"
+        );
+        assert_eq!(
+            error(&["a.yaml", "--width", "2000000000"]),
+            "an image of 2000000000x1000000000 pixels is too large"
+        );
+    }
+}
@@ -1,57 +1,17 @@
 use approx::AbsDiffEq;
 
+use crate::{
+    ppm::{write_ppm, PpmFormat},
+    tonemap::OutputTransform,
+};
+
 pub struct Canvas {
     pixels: Vec<Color>,
+    alpha: Vec<f64>,
     pub width: i32,
     pub height: i32,
 }
 
-struct PPMBuilder {
-    result: String,
-    current_line: String
-}
-
-impl PPMBuilder {
-    fn new(width: i32, height: i32) -> PPMBuilder {
-        PPMBuilder { result: format!("P3\n{} {}\n255\n", width, height), current_line: String::new() }
-    }
-
-    fn add_color(&mut self, color: &Color) {
-        fn component(f: f64) -> i32 {
-            ((f * 255.0).round() as i32).max(0).min(255)
-        }
-
-        self.add_component(component(color.red));
-        self.add_component(component(color.green));
-        self.add_component(component(color.blue));
-    }
-
-    fn add_component(&mut self, component: i32) {
-        if self.current_line.is_empty() {
-            self.current_line.push_str(&format!("{}", component));
-        }
-        else {
-            let new_line_length = self.current_line.chars().count() + format!(" {}", component).chars().count();
-
-            if new_line_length > 70 {
-                self.force_next_line();
-                self.current_line.push_str(&format!("{}", component));
-            }
-            else {
-                self.current_line.push_str(&format!(" {}", component));
-            }
-        }
-    }
-
-    fn force_next_line(&mut self) {
-        if !self.current_line.is_empty() {
-            self.result.push_str(&self.current_line);
-            self.result.push_str("\n");
-            self.current_line = String::new();
-        }
-    }
-}
-
 impl Canvas {
     pub fn new(width: i32, height: i32) -> Canvas {
         let mut pixels = Vec::with_capacity((width * height).try_into().unwrap());
@@ -61,10 +21,12 @@ impl Canvas {
                 pixels.push(Color::BLACK);
             }
         }
+        let alpha = vec![1.0; pixels.len()];
         Canvas {
-            pixels: pixels,
-            width: width,
-            height: height,
+            pixels,
+            alpha,
+            width,
+            height,
         }
     }
 
@@ -81,19 +43,75 @@ impl Canvas {
             .map(|c| { *c = *color; })
     }
 
+    /// Coverage of the pixel, from 0.0 (transparent) to 1.0 (opaque). Pixel
+    /// colours are premultiplied by it.
+    pub fn alpha_at(&self, x: i32, y: i32) -> Option<f64> {
+        let index: usize = (x + (y * self.width)).try_into().ok()?;
+        self.alpha.get(index).copied()
+    }
+
+    pub fn write_alpha(&mut self, x: i32, y: i32, alpha: f64) -> Option<()> {
+        (x + (y * self.width))
+            .try_into()
+            .ok()
+            .and_then(|index: usize| self.alpha.get_mut(index))
+            .map(|a| {
+                *a = alpha;
+            })
+    }
+
+    /// Plain `P3` PPM image of the canvas. Prefer `ppm::write_ppm` for large
+    /// images, which streams a binary file without building a `String`.
     pub fn generate_ppm(&self) -> String {
-        let mut ppm_bulder = PPMBuilder::new(self.width, self.height);
+        let ppm = write_ppm(self, Vec::new(), PpmFormat::Ascii).expect("writing to a Vec failed");
+        String::from_utf8(ppm).expect("plain PPM is ASCII")
+    }
+
+    /// Colour of a pixel divided by its alpha, as stored by formats with a
+    /// straight (not premultiplied) alpha channel.
+    pub fn unpremultiplied_pixel_at(&self, x: i32, y: i32) -> Option<Color> {
+        let color = self.pixel_at(x, y)?;
+        let alpha = self.alpha_at(x, y)?;
+        if alpha <= 0.0 {
+            Some(Color::BLACK)
+        } else {
+            Some(color.scalar_mul(1.0 / alpha))
+        }
+    }
+
+    /// Encodes the canvas as an 8-bit Netpbm PAM image with an alpha channel.
+    pub fn generate_pam(&self) -> Vec<u8> {
+        self.generate_pam_with_transform(OutputTransform::DEFAULT)
+    }
+
+    /// Encodes the canvas as a PAM image, converting its colours with
+    /// `transform`.
+    pub fn generate_pam_with_transform(&self, transform: OutputTransform) -> Vec<u8> {
+        fn component(f: f64) -> u8 {
+            (f * 255.0).round().clamp(0.0, 255.0) as u8
+        }
+
+        let mut result = format!(
+            "P7\nWIDTH {}\nHEIGHT {}\nDEPTH 4\nMAXVAL 255\nTUPLTYPE RGB_ALPHA\nENDHDR\n",
+            self.width, self.height
+        )
+        .into_bytes();
 
         for y in 0..self.height {
             for x in 0..self.width {
-                self.pixel_at(x, y).map(|p| {
-                    ppm_bulder.add_color(p);
-                });
+                let pixel = (self.unpremultiplied_pixel_at(x, y), self.alpha_at(x, y));
+                if let (Some(c), Some(alpha)) = pixel {
+                    let c = transform.apply(&c);
+                    result.extend_from_slice(&[
+                        component(c.red),
+                        component(c.green),
+                        component(c.blue),
+                        component(alpha),
+                    ]);
+                }
             }
-            ppm_bulder.force_next_line();
         }
-        ppm_bulder.force_next_line();
-        ppm_bulder.result
+        result
     }
 }
 
@@ -147,7 +165,6 @@ impl Color {
         }
     }
 
-    #[allow(dead_code)]
     pub fn subtract(&self, other: &Color) -> Color {
         Color {
             red: self.red - other.red,
@@ -171,10 +188,17 @@ impl Color {
             blue: self.blue * other.blue,
         }
     }
+
+    /// Perceived brightness using the Rec. 709 weights.
+    pub fn luminance(&self) -> f64 {
+        0.2126 * self.red + 0.7152 * self.green + 0.0722 * self.blue
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::tonemap::OutputTransform;
+
     use super::{Canvas, Color};
 
     #[test]
@@ -205,6 +229,12 @@ mod tests {
         assert_abs_diff_eq!(c1.mul(&c2), Color::new(0.9, 0.2, 0.04))
     }
 
+    #[test]
+    fn test_color_luminance() {
+        assert_abs_diff_eq!(Color::WHITE.luminance(), 1.0);
+        assert_abs_diff_eq!(Color::new(0.0, 1.0, 0.0).luminance(), 0.7152);
+    }
+
     #[test]
     fn test_canvas_new() {
         let c = Canvas::new(2, 2);
@@ -215,6 +245,38 @@ mod tests {
         assert_eq!(c.pixel_at(2, 1), None);
     }
 
+    #[test]
+    fn test_canvas_alpha() {
+        let mut c = Canvas::new(2, 2);
+        assert_eq!(c.alpha_at(1, 1), Some(1.0));
+
+        c.write_alpha(1, 1, 0.25);
+        assert_eq!(c.alpha_at(1, 1), Some(0.25));
+        assert_eq!(c.alpha_at(0, 1), Some(1.0));
+        assert_eq!(c.alpha_at(2, 1), None);
+    }
+
+    #[test]
+    fn test_canvas_generate_pam() {
+        let mut c = Canvas::new(2, 1);
+        c.write_pixel(0, 0, &Color::new(0.5, 0.25, 0.0));
+        c.write_alpha(0, 0, 0.5);
+        c.write_pixel(1, 0, &Color::BLACK);
+        c.write_alpha(1, 0, 0.0);
+
+        let header = "P7\nWIDTH 2\nHEIGHT 1\nDEPTH 4\nMAXVAL 255\nTUPLTYPE RGB_ALPHA\nENDHDR\n";
+        let pam = c.generate_pam();
+        assert_eq!(&pam[..header.len()], header.as_bytes());
+        assert_eq!(&pam[header.len()..], &[255, 128, 0, 128, 0, 0, 0, 0]);
+
+        let transform = OutputTransform {
+            exposure: -1.0,
+            ..OutputTransform::DEFAULT
+        };
+        let pam = c.generate_pam_with_transform(transform);
+        assert_eq!(&pam[header.len()..], &[128, 64, 0, 128, 0, 0, 0, 0]);
+    }
+
     #[test]
     fn test_canvas_write_pixel() {
         let mut c = Canvas::new(2, 2);
@@ -0,0 +1,551 @@
+/// Longest back-reference the format allows.
+const MAX_MATCH: usize = 258;
+const MIN_MATCH: usize = 3;
+const WINDOW_SIZE: usize = 32768;
+
+/// How many earlier positions with the same hash are tried for each match.
+const MAX_CHAIN: usize = 64;
+const HASH_BITS: u32 = 15;
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+/// Packs bits least significant first, as deflate streams are read.
+struct BitWriter {
+    bytes: Vec<u8>,
+    buffer: u64,
+    count: u32,
+}
+
+impl BitWriter {
+    fn new() -> BitWriter {
+        BitWriter {
+            bytes: Vec::new(),
+            buffer: 0,
+            count: 0,
+        }
+    }
+
+    fn write_bits(&mut self, value: u32, count: u32) {
+        self.buffer |= u64::from(value) << self.count;
+        self.count += count;
+        while self.count >= 8 {
+            self.bytes.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    /// Huffman codes are defined most significant bit first.
+    fn write_code(&mut self, code: u32, length: u32) {
+        self.write_bits(code.reverse_bits() >> (32 - length), length);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.bytes.push(self.buffer as u8);
+        }
+        self.bytes
+    }
+}
+
+/// Writes a literal or length symbol with the fixed Huffman code.
+fn write_fixed_symbol(writer: &mut BitWriter, symbol: u16) {
+    let symbol = u32::from(symbol);
+    match symbol {
+        0..=143 => writer.write_code(0x30 + symbol, 8),
+        144..=255 => writer.write_code(0x190 + symbol - 144, 9),
+        256..=279 => writer.write_code(symbol - 256, 7),
+        _ => writer.write_code(0xC0 + symbol - 280, 8),
+    }
+}
+
+fn write_match(writer: &mut BitWriter, length: usize, distance: usize) {
+    let index = LENGTH_BASE
+        .iter()
+        .rposition(|base| usize::from(*base) <= length)
+        .unwrap();
+    write_fixed_symbol(writer, 257 + index as u16);
+    let extra = LENGTH_EXTRA[index];
+    if extra > 0 {
+        writer.write_bits(
+            (length - usize::from(LENGTH_BASE[index])) as u32,
+            u32::from(extra),
+        );
+    }
+
+    let index = DISTANCE_BASE
+        .iter()
+        .rposition(|base| usize::from(*base) <= distance)
+        .unwrap();
+    writer.write_code(index as u32, 5);
+    let extra = DISTANCE_EXTRA[index];
+    if extra > 0 {
+        writer.write_bits(
+            (distance - usize::from(DISTANCE_BASE[index])) as u32,
+            u32::from(extra),
+        );
+    }
+}
+
+fn hash(data: &[u8], position: usize) -> usize {
+    let value = u32::from(data[position]) << 16
+        | u32::from(data[position + 1]) << 8
+        | u32::from(data[position + 2]);
+    (value.wrapping_mul(0x9E37_79B1) >> (32 - HASH_BITS)) as usize
+}
+
+/// Compresses `data` into a raw deflate stream: one block using the fixed
+/// Huffman codes, with matches found through hash chains.
+pub fn deflate(data: &[u8]) -> Vec<u8> {
+    let mut writer = BitWriter::new();
+    writer.write_bits(1, 1); // final block
+    writer.write_bits(1, 2); // fixed Huffman codes
+
+    let mut head = vec![usize::MAX; 1 << HASH_BITS];
+    let mut previous = vec![usize::MAX; data.len()];
+    let insert = |position: usize, head: &mut Vec<usize>, previous: &mut Vec<usize>| {
+        if position + MIN_MATCH <= data.len() {
+            let h = hash(data, position);
+            previous[position] = head[h];
+            head[h] = position;
+        }
+    };
+
+    let mut position = 0;
+    while position < data.len() {
+        let mut best_length = 0;
+        let mut best_distance = 0;
+
+        if position + MIN_MATCH <= data.len() {
+            let max_length = MAX_MATCH.min(data.len() - position);
+            let mut candidate = head[hash(data, position)];
+            let mut chain = 0;
+            while candidate != usize::MAX
+                && position - candidate <= WINDOW_SIZE
+                && chain < MAX_CHAIN
+            {
+                let length = data[candidate..]
+                    .iter()
+                    .zip(&data[position..position + max_length])
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                if length > best_length {
+                    best_length = length;
+                    best_distance = position - candidate;
+                    if length == max_length {
+                        break;
+                    }
+                }
+                candidate = previous[candidate];
+                chain += 1;
+            }
+        }
+
+        if best_length >= MIN_MATCH {
+            write_match(&mut writer, best_length, best_distance);
+            for p in position..position + best_length {
+                insert(p, &mut head, &mut previous);
+            }
+            position += best_length;
+        } else {
+            write_fixed_symbol(&mut writer, u16::from(data[position]));
+            insert(position, &mut head, &mut previous);
+            position += 1;
+        }
+    }
+
+    write_fixed_symbol(&mut writer, 256);
+    writer.finish()
+}
+
+pub fn adler32(data: &[u8]) -> u32 {
+    const MODULUS: u32 = 65521;
+    let (mut a, mut b) = (1u32, 0u32);
+    // Sums stay below 2^32 for chunks of this size before reducing
+    for chunk in data.chunks(5552) {
+        for byte in chunk {
+            a += u32::from(*byte);
+            b += a;
+        }
+        a %= MODULUS;
+        b %= MODULUS;
+    }
+    b << 16 | a
+}
+
+/// Wraps a deflate stream of `data` in the zlib header and checksum.
+pub fn zlib_compress(data: &[u8]) -> Vec<u8> {
+    // 32K window, deflate, default level; the header is a multiple of 31
+    let mut result = vec![0x78, 0x9C];
+    result.extend(deflate(data));
+    result.extend_from_slice(&adler32(data).to_be_bytes());
+    result
+}
+
+/// Reads bits least significant first.
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+    buffer: u32,
+    count: u32,
+}
+
+impl BitReader<'_> {
+    fn new(data: &[u8]) -> BitReader<'_> {
+        BitReader {
+            data,
+            position: 0,
+            buffer: 0,
+            count: 0,
+        }
+    }
+
+    fn read_bits(&mut self, count: u32) -> Result<u32, &'static str> {
+        while self.count < count {
+            let byte = *self
+                .data
+                .get(self.position)
+                .ok_or("compressed data is truncated")?;
+            self.position += 1;
+            self.buffer |= u32::from(byte) << self.count;
+            self.count += 8;
+        }
+        let value = self.buffer & ((1u64 << count) - 1) as u32;
+        self.buffer >>= count;
+        self.count -= count;
+        Ok(value)
+    }
+
+    /// Skips to the next byte boundary, as stored blocks begin there.
+    fn align(&mut self) {
+        self.buffer = 0;
+        self.count = 0;
+    }
+}
+
+/// Canonical Huffman code, stored as the number of codes of each length and
+/// the symbols in code order.
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Result<Huffman, &'static str> {
+        let mut counts = [0u16; 16];
+        for length in lengths {
+            counts[usize::from(*length)] += 1;
+        }
+        counts[0] = 0;
+
+        // Reject codes with more codes of some length than can exist
+        let mut left = 1i32;
+        for count in &counts[1..] {
+            left = left * 2 - i32::from(*count);
+            if left < 0 {
+                return Err("over-subscribed Huffman code");
+            }
+        }
+
+        let mut offsets = [0u16; 16];
+        for length in 1..15 {
+            offsets[length + 1] = offsets[length] + counts[length];
+        }
+        let mut symbols = vec![0u16; lengths.len()];
+        for (symbol, length) in lengths.iter().enumerate() {
+            if *length != 0 {
+                symbols[usize::from(offsets[usize::from(*length)])] = symbol as u16;
+                offsets[usize::from(*length)] += 1;
+            }
+        }
+
+        Ok(Huffman { counts, symbols })
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> Result<u16, &'static str> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for length in 1..16 {
+            code |= reader.read_bits(1)? as i32;
+            let count = i32::from(self.counts[length]);
+            if code - count < first {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err("invalid Huffman code")
+    }
+}
+
+fn fixed_codes() -> (Huffman, Huffman) {
+    let mut lengths = [0u8; 288];
+    for (symbol, length) in lengths.iter_mut().enumerate() {
+        *length = match symbol {
+            0..=143 => 8,
+            144..=255 => 9,
+            256..=279 => 7,
+            _ => 8,
+        };
+    }
+    (
+        Huffman::new(&lengths).unwrap(),
+        Huffman::new(&[5u8; 30]).unwrap(),
+    )
+}
+
+fn dynamic_codes(reader: &mut BitReader) -> Result<(Huffman, Huffman), &'static str> {
+    const ORDER: [usize; 19] = [
+        16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+    ];
+
+    let literal_count = reader.read_bits(5)? as usize + 257;
+    let distance_count = reader.read_bits(5)? as usize + 1;
+    let code_length_count = reader.read_bits(4)? as usize + 4;
+    if literal_count > 286 || distance_count > 30 {
+        return Err("too many Huffman codes");
+    }
+
+    let mut code_lengths = [0u8; 19];
+    for index in ORDER.iter().take(code_length_count) {
+        code_lengths[*index] = reader.read_bits(3)? as u8;
+    }
+    let code_length_code = Huffman::new(&code_lengths)?;
+
+    let mut lengths = vec![0u8; literal_count + distance_count];
+    let mut index = 0;
+    while index < lengths.len() {
+        let symbol = code_length_code.decode(reader)?;
+        let (value, repeat) = match symbol {
+            0..=15 => (symbol as u8, 1),
+            16 => {
+                if index == 0 {
+                    return Err("length repeat with no previous length");
+                }
+                (lengths[index - 1], 3 + reader.read_bits(2)? as usize)
+            }
+            17 => (0, 3 + reader.read_bits(3)? as usize),
+            _ => (0, 11 + reader.read_bits(7)? as usize),
+        };
+        if index + repeat > lengths.len() {
+            return Err("too many code lengths");
+        }
+        lengths[index..index + repeat].fill(value);
+        index += repeat;
+    }
+    if lengths[256] == 0 {
+        return Err("no end of block code");
+    }
+
+    Ok((
+        Huffman::new(&lengths[..literal_count])?,
+        Huffman::new(&lengths[literal_count..])?,
+    ))
+}
+
+fn inflate_block(
+    reader: &mut BitReader,
+    output: &mut Vec<u8>,
+    literals: &Huffman,
+    distances: &Huffman,
+) -> Result<(), &'static str> {
+    loop {
+        let symbol = literals.decode(reader)?;
+        match symbol {
+            0..=255 => output.push(symbol as u8),
+            256 => return Ok(()),
+            _ => {
+                let index = usize::from(symbol - 257);
+                if index >= LENGTH_BASE.len() {
+                    return Err("invalid length code");
+                }
+                let length = usize::from(LENGTH_BASE[index])
+                    + reader.read_bits(u32::from(LENGTH_EXTRA[index]))? as usize;
+
+                let index = usize::from(distances.decode(reader)?);
+                if index >= DISTANCE_BASE.len() {
+                    return Err("invalid distance code");
+                }
+                let distance = usize::from(DISTANCE_BASE[index])
+                    + reader.read_bits(u32::from(DISTANCE_EXTRA[index]))? as usize;
+                if distance > output.len() {
+                    return Err("distance too far back");
+                }
+
+                // Copies byte by byte, as a match may overlap its own output
+                let start = output.len() - distance;
+                for i in 0..length {
+                    output.push(output[start + i]);
+                }
+            }
+        }
+    }
+}
+
+/// Decompresses a raw deflate stream.
+pub fn inflate(data: &[u8]) -> Result<Vec<u8>, &'static str> {
+    let mut reader = BitReader::new(data);
+    let mut output = Vec::new();
+
+    loop {
+        let is_final = reader.read_bits(1)? == 1;
+        match reader.read_bits(2)? {
+            0 => {
+                reader.align();
+                let header = data
+                    .get(reader.position..reader.position + 4)
+                    .ok_or("compressed data is truncated")?;
+                let length = usize::from(u16::from_le_bytes([header[0], header[1]]));
+                let complement = u16::from_le_bytes([header[2], header[3]]);
+                if length as u16 != !complement {
+                    return Err("stored block length mismatch");
+                }
+                let start = reader.position + 4;
+                let block = data
+                    .get(start..start + length)
+                    .ok_or("compressed data is truncated")?;
+                output.extend_from_slice(block);
+                reader.position = start + length;
+            }
+            1 => {
+                let (literals, distances) = fixed_codes();
+                inflate_block(&mut reader, &mut output, &literals, &distances)?;
+            }
+            2 => {
+                let (literals, distances) = dynamic_codes(&mut reader)?;
+                inflate_block(&mut reader, &mut output, &literals, &distances)?;
+            }
+            _ => return Err("invalid block type"),
+        }
+        if is_final {
+            return Ok(output);
+        }
+    }
+}
+
+/// Decompresses a zlib stream, checking its header and checksum.
+pub fn zlib_decompress(data: &[u8]) -> Result<Vec<u8>, &'static str> {
+    if data.len() < 6 {
+        return Err("zlib stream is truncated");
+    }
+    if data[0] & 0x0F != 8 || !u16::from_be_bytes([data[0], data[1]]).is_multiple_of(31) {
+        return Err("bad zlib header");
+    }
+    if data[1] & 0x20 != 0 {
+        return Err("zlib preset dictionaries are not supported");
+    }
+
+    let output = inflate(&data[2..])?;
+    let checksum = u32::from_be_bytes(data[data.len() - 4..].try_into().unwrap());
+    if adler32(&output) != checksum {
+        return Err("zlib checksum mismatch");
+    }
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{adler32, deflate, inflate, zlib_compress, zlib_decompress, WINDOW_SIZE};
+
+    /// Bytes without repeats that the encoder could turn into matches.
+    fn noise(count: usize, seed: u32) -> Vec<u8> {
+        (0..count as u32)
+            .map(|i| (i.wrapping_add(seed).wrapping_mul(2_654_435_761) >> 13) as u8)
+            .collect()
+    }
+
+    #[test]
+    fn adler32_known_value() {
+        assert_eq!(adler32(b"Wikipedia"), 0x11E6_0398);
+        assert_eq!(adler32(b""), 1);
+    }
+
+    #[test]
+    fn zlib_header_and_checksum() {
+        let data = b"abcabcabcabcabcabc";
+        let compressed = zlib_compress(data);
+        assert_eq!(u16::from_be_bytes([compressed[0], compressed[1]]) % 31, 0);
+        assert_eq!(
+            compressed[compressed.len() - 4..],
+            adler32(data).to_be_bytes()
+        );
+        assert!(compressed.len() < data.len() + 6);
+    }
+
+    #[test]
+    fn compress_round_trip() {
+        let mut data = b"the quick brown fox jumps over the lazy dog; ".repeat(40);
+        data.extend((0..2000u32).map(|i| (i.wrapping_mul(2_654_435_761) >> 13) as u8));
+        data.extend(vec![7u8; 1000]);
+
+        let compressed = zlib_compress(&data);
+        assert!(compressed.len() < data.len());
+        assert_eq!(zlib_decompress(&compressed).unwrap(), data);
+    }
+
+    #[test]
+    fn deflate_round_trip_edge_cases() {
+        for data in [&b""[..], b"a", b"ab", b"abc", b"aaaa"] {
+            assert_eq!(inflate(&deflate(data)).unwrap(), data);
+        }
+
+        // Every match length, each repeating the bytes just before it
+        let mut lengths = Vec::new();
+        for length in 3..=258 {
+            let start = lengths.len();
+            lengths.extend(noise(length, length as u32));
+            lengths.extend_from_within(start..);
+        }
+        assert_eq!(inflate(&deflate(&lengths)).unwrap(), lengths);
+
+        // A repeat at the furthest distance the window allows
+        let pattern = noise(300, 1);
+        let mut far = pattern.clone();
+        far.extend(noise(WINDOW_SIZE - pattern.len(), 2));
+        far.extend(&pattern);
+        assert_eq!(inflate(&deflate(&far)).unwrap(), far);
+    }
+
+    #[test]
+    fn inflate_stored_block() {
+        let stream = [0x01, 0x03, 0x00, 0xFC, 0xFF, b'a', b'b', b'c'];
+        assert_eq!(inflate(&stream).unwrap(), b"abc");
+    }
+
+    #[test]
+    fn inflate_dynamic_block() {
+        // zlib.compress(b"ahgkgiegdhgdhkkigckhfefhckbgeifjidfekegi", 9)
+        let stream = [
+            0x78, 0xDA, 0x0D, 0xC2, 0xB9, 0x0D, 0x00, 0x20, 0x10, 0x03, 0xB0, 0x59, 0x81, 0xCB,
+            0x47, 0xF6, 0xEF, 0xC1, 0xF2, 0xB2, 0xAA, 0x40, 0xE3, 0xBF, 0x8D, 0x4E, 0x4D, 0xD0,
+            0xA7, 0x5B, 0x08, 0x6F, 0x86, 0x28, 0x94, 0x07, 0x4A, 0x0C, 0x10, 0x19,
+        ];
+        assert_eq!(
+            zlib_decompress(&stream).unwrap(),
+            b"ahgkgiegdhgdhkkigckhfefhckbgeifjidfekegi"
+        );
+    }
+
+    #[test]
+    fn zlib_rejects_bad_checksum() {
+        let mut compressed = zlib_compress(b"abc");
+        let last = compressed.len() - 1;
+        compressed[last] ^= 1;
+        assert!(zlib_decompress(&compressed).is_err());
+        assert!(zlib_decompress(&compressed[..4]).is_err());
+    }
+}
@@ -0,0 +1,270 @@
+use std::{f64::consts::PI, fs::File, io, io::BufReader, path::Path};
+
+use crate::{
+    color::{Canvas, Color},
+    hdr::read_hdr,
+    sampling::{Distribution1D, Rng},
+    tuple::Tuple,
+};
+
+/// A direction towards the environment, with the radiance arriving from it and
+/// the solid angle density it was chosen with.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct EnvironmentSample {
+    pub(crate) direction: Tuple,
+    pub(crate) radiance: Color,
+    pub(crate) pdf: f64,
+}
+
+/// Distant lighting surrounding the world, stored as an equirectangular image.
+/// The centre column of the image faces `-z` and the top row faces `+y`.
+pub struct EnvironmentMap {
+    image: Canvas,
+    rows: Distribution1D,
+    columns: Vec<Distribution1D>,
+    light_samples: Vec<EnvironmentSample>,
+}
+
+impl EnvironmentMap {
+    pub const DEFAULT_LIGHT_SAMPLES: usize = 32;
+    const LIGHT_SAMPLE_SEED: u64 = 0x5EED;
+
+    pub fn equirectangular(image: Canvas) -> EnvironmentMap {
+        // Weight each texel by how bright it is and how much of the sphere it covers
+        let columns: Vec<Distribution1D> = (0..image.height)
+            .map(|y| {
+                let sin_theta = (PI * (f64::from(y) + 0.5) / f64::from(image.height)).sin();
+                let weights = (0..image.width)
+                    .map(|x| image.pixel_at(x, y).unwrap().luminance() * sin_theta)
+                    .collect();
+                Distribution1D::new(weights)
+            })
+            .collect();
+        let rows = Distribution1D::new(columns.iter().map(|c| c.integral).collect());
+
+        EnvironmentMap {
+            image,
+            rows,
+            columns,
+            light_samples: Vec::new(),
+        }
+        .with_light_samples(EnvironmentMap::DEFAULT_LIGHT_SAMPLES)
+    }
+
+    /// Builds a map from six square faces ordered `+x, -x, +y, -y, +z, -z`,
+    /// using the OpenGL cube map face orientation.
+    pub fn cube_map(faces: [Canvas; 6]) -> EnvironmentMap {
+        let size = faces[0].width;
+        let mut image = Canvas::new(size * 4, size * 2);
+
+        for y in 0..image.height {
+            for x in 0..image.width {
+                let u = (f64::from(x) + 0.5) / f64::from(image.width);
+                let v = (f64::from(y) + 0.5) / f64::from(image.height);
+                let direction = EnvironmentMap::uv_to_direction(u, v);
+                let (face, fu, fv) = EnvironmentMap::cube_face(&direction);
+                let face = &faces[face];
+                let fx = ((fu * f64::from(face.width)) as i32).min(face.width - 1);
+                let fy = ((fv * f64::from(face.height)) as i32).min(face.height - 1);
+                image.write_pixel(x, y, face.pixel_at(fx, fy).unwrap());
+            }
+        }
+
+        EnvironmentMap::equirectangular(image)
+    }
+
+    pub fn from_hdr_file<P: AsRef<Path>>(path: P) -> io::Result<EnvironmentMap> {
+        let mut reader = BufReader::new(File::open(path)?);
+        Ok(EnvironmentMap::equirectangular(read_hdr(&mut reader)?))
+    }
+
+    /// Sets how many importance-sampled directions are used when the map
+    /// lights a diffuse surface.
+    pub fn with_light_samples(mut self, count: usize) -> EnvironmentMap {
+        let mut rng = Rng::new(EnvironmentMap::LIGHT_SAMPLE_SEED);
+        self.light_samples = (0..count)
+            .filter_map(|i| {
+                let u1 = (i as f64 + rng.next_f64()) / count as f64;
+                self.sample(u1, rng.next_f64())
+            })
+            .collect();
+        self
+    }
+
+    /// Fixed set of directions, distributed by brightness, shared by every
+    /// shading point so that deterministic renders stay noise free.
+    pub(crate) fn light_samples(&self) -> &[EnvironmentSample] {
+        &self.light_samples
+    }
+
+    pub(crate) fn radiance(&self, direction: &Tuple) -> Color {
+        let (u, v) = EnvironmentMap::direction_to_uv(direction);
+        let (x, y) = self.texel(u, v);
+        *self.image.pixel_at(x, y).unwrap()
+    }
+
+    /// Chooses a direction with probability proportional to its brightness.
+    pub(crate) fn sample(&self, u1: f64, u2: f64) -> Option<EnvironmentSample> {
+        let (v, row_pdf, row) = self.rows.sample_continuous(u1);
+        let (u, column_pdf, _) = self.columns[row].sample_continuous(u2);
+
+        let direction = EnvironmentMap::uv_to_direction(u, v);
+        let pdf = self.uv_pdf_to_solid_angle(row_pdf * column_pdf, v);
+        if pdf <= 0.0 {
+            return None;
+        }
+
+        Some(EnvironmentSample {
+            direction,
+            radiance: self.radiance(&direction),
+            pdf,
+        })
+    }
+
+    /// Solid angle density with which `sample` chooses `direction`.
+    pub(crate) fn pdf(&self, direction: &Tuple) -> f64 {
+        let (u, v) = EnvironmentMap::direction_to_uv(direction);
+        let (x, y) = self.texel(u, v);
+        let uv_pdf = self.rows.pdf(y as usize) * self.columns[y as usize].pdf(x as usize);
+        self.uv_pdf_to_solid_angle(uv_pdf, v)
+    }
+
+    fn uv_pdf_to_solid_angle(&self, uv_pdf: f64, v: f64) -> f64 {
+        let sin_theta = (v * PI).sin();
+        if sin_theta <= 0.0 {
+            0.0
+        } else {
+            uv_pdf / (2.0 * PI * PI * sin_theta)
+        }
+    }
+
+    fn texel(&self, u: f64, v: f64) -> (i32, i32) {
+        let x = ((u * f64::from(self.image.width)) as i32).clamp(0, self.image.width - 1);
+        let y = ((v * f64::from(self.image.height)) as i32).clamp(0, self.image.height - 1);
+        (x, y)
+    }
+
+    fn direction_to_uv(direction: &Tuple) -> (f64, f64) {
+        let d = direction.normalize();
+        let theta = d.y.clamp(-1.0, 1.0).acos();
+        let phi = d.x.atan2(-d.z);
+        (0.5 + phi / (2.0 * PI), theta / PI)
+    }
+
+    fn uv_to_direction(u: f64, v: f64) -> Tuple {
+        let phi = (u - 0.5) * 2.0 * PI;
+        let theta = v * PI;
+        Tuple::vector(
+            theta.sin() * phi.sin(),
+            theta.cos(),
+            -theta.sin() * phi.cos(),
+        )
+    }
+
+    fn cube_face(d: &Tuple) -> (usize, f64, f64) {
+        let (ax, ay, az) = (d.x.abs(), d.y.abs(), d.z.abs());
+        let (face, sc, tc, ma) = if ax >= ay && ax >= az {
+            if d.x > 0.0 {
+                (0, -d.z, -d.y, ax)
+            } else {
+                (1, d.z, -d.y, ax)
+            }
+        } else if ay >= az {
+            if d.y > 0.0 {
+                (2, d.x, d.z, ay)
+            } else {
+                (3, d.x, -d.z, ay)
+            }
+        } else if d.z > 0.0 {
+            (4, d.x, -d.y, az)
+        } else {
+            (5, -d.x, -d.y, az)
+        };
+        (face, (sc / ma + 1.0) / 2.0, (tc / ma + 1.0) / 2.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+
+    use crate::{
+        color::{Canvas, Color},
+        tuple::Tuple,
+    };
+
+    use super::EnvironmentMap;
+
+    fn filled(width: i32, height: i32, color: Color) -> Canvas {
+        let mut canvas = Canvas::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                canvas.write_pixel(x, y, &color);
+            }
+        }
+        canvas
+    }
+
+    #[test]
+    fn direction_uv_round_trip() {
+        let d = Tuple::vector(0.3, -0.5, 0.8).normalize();
+        let (u, v) = EnvironmentMap::direction_to_uv(&d);
+        assert_abs_diff_eq!(EnvironmentMap::uv_to_direction(u, v), d);
+    }
+
+    #[test]
+    fn centre_of_image_faces_negative_z() {
+        let mut image = Canvas::new(4, 2);
+        image.write_pixel(2, 0, &Color::WHITE);
+        let env = EnvironmentMap::equirectangular(image);
+        assert_abs_diff_eq!(env.radiance(&Tuple::vector(0.1, 0.5, -1.0)), Color::WHITE);
+        assert_abs_diff_eq!(env.radiance(&Tuple::vector(0.0, 0.0, 1.0)), Color::BLACK);
+    }
+
+    #[test]
+    fn samples_only_bright_texels() {
+        let mut image = Canvas::new(8, 4);
+        image.write_pixel(5, 1, &Color::new(4.0, 4.0, 4.0));
+        let env = EnvironmentMap::equirectangular(image);
+
+        for sample in env.light_samples() {
+            assert_abs_diff_eq!(sample.radiance, Color::new(4.0, 4.0, 4.0));
+            assert_abs_diff_eq!(sample.pdf, env.pdf(&sample.direction), epsilon = 1e-6);
+        }
+    }
+
+    #[test]
+    fn constant_environment_irradiance() {
+        let env =
+            EnvironmentMap::equirectangular(filled(32, 16, Color::WHITE)).with_light_samples(4096);
+        let normal = Tuple::vector(0.0, 1.0, 0.0);
+
+        let irradiance: f64 = env
+            .light_samples()
+            .iter()
+            .map(|s| s.direction.dot(&normal).max(0.0) * s.radiance.red / s.pdf)
+            .sum::<f64>()
+            / env.light_samples().len() as f64;
+        assert_abs_diff_eq!(irradiance, PI, epsilon = 0.05);
+    }
+
+    #[test]
+    fn cube_map_faces() {
+        let colors = [
+            Color::new(1.0, 0.0, 0.0),
+            Color::new(0.0, 1.0, 0.0),
+            Color::new(0.0, 0.0, 1.0),
+            Color::new(1.0, 1.0, 0.0),
+            Color::new(0.0, 1.0, 1.0),
+            Color::new(1.0, 0.0, 1.0),
+        ];
+        let env = EnvironmentMap::cube_map(colors.map(|c| filled(4, 4, c)));
+
+        assert_abs_diff_eq!(env.radiance(&Tuple::vector(1.0, 0.0, 0.0)), colors[0]);
+        assert_abs_diff_eq!(env.radiance(&Tuple::vector(-1.0, 0.0, 0.0)), colors[1]);
+        assert_abs_diff_eq!(env.radiance(&Tuple::vector(0.0, 1.0, 0.0)), colors[2]);
+        assert_abs_diff_eq!(env.radiance(&Tuple::vector(0.0, -1.0, 0.0)), colors[3]);
+        assert_abs_diff_eq!(env.radiance(&Tuple::vector(0.0, 0.0, 1.0)), colors[4]);
+        assert_abs_diff_eq!(env.radiance(&Tuple::vector(0.0, 0.0, -1.0)), colors[5]);
+    }
+}
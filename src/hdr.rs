@@ -0,0 +1,344 @@
+use std::{
+    fs::File,
+    io::{self, BufRead, BufWriter, Read, Write},
+    path::Path,
+};
+
+use crate::color::{Canvas, Color};
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+fn read_line<R: BufRead>(reader: &mut R) -> io::Result<String> {
+    let mut bytes = Vec::new();
+    reader.read_until(b'\n', &mut bytes)?;
+    if bytes.is_empty() {
+        return Err(invalid("unexpected end of Radiance header"));
+    }
+    Ok(String::from_utf8_lossy(&bytes).trim_end().to_string())
+}
+
+fn rgbe_to_color(rgbe: &[u8]) -> Color {
+    if rgbe[3] == 0 {
+        Color::BLACK
+    } else {
+        let f = 2f64.powi(i32::from(rgbe[3]) - (128 + 8));
+        Color::new(
+            f64::from(rgbe[0]) * f,
+            f64::from(rgbe[1]) * f,
+            f64::from(rgbe[2]) * f,
+        )
+    }
+}
+
+fn read_scanline<R: Read>(reader: &mut R, width: usize) -> io::Result<Vec<u8>> {
+    let mut head = [0u8; 4];
+    reader.read_exact(&mut head)?;
+
+    let is_rle =
+        (8..0x8000).contains(&width) && head[0] == 2 && head[1] == 2 && head[2] & 0x80 == 0;
+
+    if !is_rle {
+        // Grows as data arrives, so a bogus width cannot allocate much
+        let mut scanline = head.to_vec();
+        let length = width as u64 * 4;
+        reader.take(length - 4).read_to_end(&mut scanline)?;
+        if (scanline.len() as u64) < length {
+            return Err(invalid("unexpected end of Radiance pixel data"));
+        }
+        return Ok(scanline);
+    }
+
+    let mut scanline = vec![0u8; width * 4];
+
+    if (usize::from(head[2]) << 8 | usize::from(head[3])) != width {
+        return Err(invalid("Radiance scanline width mismatch"));
+    }
+
+    // Each channel is stored separately as runs or literal spans
+    for channel in 0..4 {
+        let mut x = 0;
+        while x < width {
+            let mut count = [0u8; 1];
+            reader.read_exact(&mut count)?;
+            let (count, is_run) = if count[0] > 128 {
+                (usize::from(count[0] - 128), true)
+            } else {
+                (usize::from(count[0]), false)
+            };
+            if count == 0 || x + count > width {
+                return Err(invalid("bad Radiance run length"));
+            }
+
+            if is_run {
+                let mut value = [0u8; 1];
+                reader.read_exact(&mut value)?;
+                for i in x..x + count {
+                    scanline[i * 4 + channel] = value[0];
+                }
+            } else {
+                let mut values = vec![0u8; count];
+                reader.read_exact(&mut values)?;
+                for (i, value) in values.iter().enumerate() {
+                    scanline[(x + i) * 4 + channel] = *value;
+                }
+            }
+            x += count;
+        }
+    }
+    Ok(scanline)
+}
+
+/// Reads a Radiance RGBE (`.hdr`) image in the standard `-Y h +X w`
+/// orientation, with either flat or run-length encoded scanlines.
+pub fn read_hdr<R: BufRead>(reader: &mut R) -> io::Result<Canvas> {
+    let magic = read_line(reader)?;
+    if !magic.starts_with("#?") {
+        return Err(invalid("missing Radiance signature"));
+    }
+
+    loop {
+        let line = read_line(reader)?;
+        if line.is_empty() {
+            break;
+        }
+        if let Some(format) = line.strip_prefix("FORMAT=") {
+            if format != "32-bit_rle_rgbe" {
+                return Err(invalid("unsupported Radiance pixel format"));
+            }
+        }
+    }
+
+    let resolution = read_line(reader)?;
+    let parts: Vec<&str> = resolution.split_whitespace().collect();
+    let (height, width) = match parts.as_slice() {
+        ["-Y", h, "+X", w] => (h.parse::<i32>(), w.parse::<i32>()),
+        _ => return Err(invalid("unsupported Radiance resolution line")),
+    };
+    let (height, width) = match (height, width) {
+        (Ok(h), Ok(w)) if h > 0 && w > 0 => (h, w),
+        _ => return Err(invalid("bad Radiance resolution")),
+    };
+    if width.checked_mul(height).is_none() {
+        return Err(invalid("Radiance image is too large"));
+    }
+
+    // Only allocate the canvas once the pixels have turned out to be there
+    let mut pixels = Vec::new();
+    for _ in 0..height {
+        let scanline = read_scanline(reader, width as usize)?;
+        pixels.extend(scanline.chunks_exact(4).map(rgbe_to_color));
+    }
+    let mut canvas = Canvas::new(width, height);
+    for (i, color) in pixels.iter().enumerate() {
+        let i = i as i32;
+        canvas.write_pixel(i % width, i / width, color);
+    }
+    Ok(canvas)
+}
+
+/// Shared-exponent encoding of a colour. Negative channels are written as
+/// zero.
+fn color_to_rgbe(color: &Color) -> [u8; 4] {
+    let (red, green, blue) = (
+        color.red.max(0.0),
+        color.green.max(0.0),
+        color.blue.max(0.0),
+    );
+    let brightest = red.max(green).max(blue);
+    if brightest < 1e-32 {
+        return [0; 4];
+    }
+
+    // Exponent with the brightest channel's mantissa in [0.5, 1)
+    let mut exponent = brightest.log2().floor() as i32 + 1;
+    if brightest / 2f64.powi(exponent) >= 1.0 {
+        exponent += 1;
+    }
+    let exponent = exponent.clamp(-128, 127);
+    let scale = 256.0 / 2f64.powi(exponent);
+    let mantissa = |channel: f64| (channel * scale).min(255.0) as u8;
+    [
+        mantissa(red),
+        mantissa(green),
+        mantissa(blue),
+        (exponent + 128) as u8,
+    ]
+}
+
+/// Run-length encodes one channel of a scanline, using runs for four or more
+/// repeated bytes and literal spans for everything else.
+fn write_channel<W: Write>(writer: &mut W, bytes: &[u8]) -> io::Result<()> {
+    const MIN_RUN: usize = 4;
+
+    let mut x = 0;
+    while x < bytes.len() {
+        // Find the next run long enough to be worth encoding
+        let mut run_start = x;
+        let mut run_length = 0;
+        while run_start < bytes.len() {
+            run_length = bytes[run_start..]
+                .iter()
+                .take(127)
+                .take_while(|b| **b == bytes[run_start])
+                .count();
+            if run_length >= MIN_RUN {
+                break;
+            }
+            run_start += run_length;
+        }
+        if run_length < MIN_RUN {
+            run_start = bytes.len();
+        }
+
+        while x < run_start {
+            let count = (run_start - x).min(128);
+            writer.write_all(&[count as u8])?;
+            writer.write_all(&bytes[x..x + count])?;
+            x += count;
+        }
+        if run_start < bytes.len() {
+            writer.write_all(&[128 + run_length as u8, bytes[run_start]])?;
+            x = run_start + run_length;
+        }
+    }
+    Ok(())
+}
+
+/// Writes the canvas as a run-length encoded Radiance RGBE image, keeping
+/// values above 1.0. Alpha is dropped, so transparent pixels come out black.
+pub fn write_hdr<W: Write>(canvas: &Canvas, writer: &mut W) -> io::Result<()> {
+    write!(
+        writer,
+        "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n",
+        canvas.height, canvas.width
+    )?;
+
+    let width = canvas.width as usize;
+    let rle = (8..0x8000).contains(&width);
+    for y in 0..canvas.height {
+        let pixels: Vec<[u8; 4]> = (0..canvas.width)
+            .map(|x| color_to_rgbe(canvas.pixel_at(x, y).unwrap_or(&Color::BLACK)))
+            .collect();
+        if rle {
+            writer.write_all(&[2, 2, (width >> 8) as u8, width as u8])?;
+            for channel in 0..4 {
+                let bytes: Vec<u8> = pixels.iter().map(|rgbe| rgbe[channel]).collect();
+                write_channel(writer, &bytes)?;
+            }
+        } else {
+            for rgbe in &pixels {
+                writer.write_all(rgbe)?;
+            }
+        }
+    }
+    writer.flush()
+}
+
+/// Writes the canvas to a Radiance `.hdr` file at `path`.
+pub fn save_hdr<P: AsRef<Path>>(canvas: &Canvas, path: P) -> io::Result<()> {
+    write_hdr(canvas, &mut BufWriter::new(File::create(path)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use crate::color::{Canvas, Color};
+
+    use super::{color_to_rgbe, read_hdr, rgbe_to_color, write_hdr};
+
+    #[test]
+    fn read_flat_hdr() {
+        let mut data = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 1 +X 2\n".to_vec();
+        data.extend_from_slice(&[128, 64, 0, 129, 128, 128, 128, 128]);
+
+        let canvas = read_hdr(&mut data.as_slice()).unwrap();
+        assert_eq!((canvas.width, canvas.height), (2, 1));
+        assert_abs_diff_eq!(*canvas.pixel_at(0, 0).unwrap(), Color::new(1.0, 0.5, 0.0));
+        assert_abs_diff_eq!(*canvas.pixel_at(1, 0).unwrap(), Color::new(0.5, 0.5, 0.5));
+    }
+
+    #[test]
+    fn read_rle_hdr() {
+        let mut data = b"#?RGBE\n\n-Y 1 +X 8\n".to_vec();
+        data.extend_from_slice(&[2, 2, 0, 8]);
+        data.extend_from_slice(&[136, 128]); // red: run of 8
+        data.extend_from_slice(&[4, 0, 0, 0, 0, 132, 128]); // green: 4 literals, run of 4
+        data.extend_from_slice(&[136, 0]); // blue: run of 8
+        data.extend_from_slice(&[136, 129]); // exponent: run of 8
+
+        let canvas = read_hdr(&mut data.as_slice()).unwrap();
+        assert_abs_diff_eq!(*canvas.pixel_at(0, 0).unwrap(), Color::new(1.0, 0.0, 0.0));
+        assert_abs_diff_eq!(*canvas.pixel_at(7, 0).unwrap(), Color::new(1.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn read_hdr_rejects_other_files() {
+        let data = b"P3\n1 1\n255\n0 0 0\n".to_vec();
+        assert!(read_hdr(&mut data.as_slice()).is_err());
+    }
+
+    #[test]
+    fn read_hdr_rejects_oversized_headers() {
+        let error = |header: &str| {
+            let mut data = format!("#?RADIANCE\n\n{}\n", header).into_bytes();
+            data.extend_from_slice(&[128, 64, 0, 129]);
+            let Err(error) = read_hdr(&mut data.as_slice()) else {
+                panic!("expected an error");
+            };
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+            error.to_string()
+        };
+        assert_eq!(error("-Y 100000 +X 100000"), "Radiance image is too large");
+        assert_eq!(
+            error("-Y 40000 +X 40000"),
+            "unexpected end of Radiance pixel data"
+        );
+    }
+
+    #[test]
+    fn rgbe_encoding() {
+        assert_eq!(color_to_rgbe(&Color::new(1.0, 0.5, 0.0)), [128, 64, 0, 129]);
+        assert_eq!(color_to_rgbe(&Color::BLACK), [0; 4]);
+        assert_eq!(color_to_rgbe(&Color::new(-1.0, 0.0, 0.0)), [0; 4]);
+
+        let bright = Color::new(300.0, 20.0, 0.125);
+        assert_abs_diff_eq!(
+            rgbe_to_color(&color_to_rgbe(&bright)),
+            bright,
+            epsilon = 300.0 / 128.0
+        );
+    }
+
+    #[test]
+    fn write_hdr_round_trip() {
+        // Wide enough for run-length encoding, with runs and literal spans
+        for width in [3, 300] {
+            let mut canvas = Canvas::new(width, 2);
+            for x in 0..width {
+                let value = if x < 100 {
+                    8.0
+                } else {
+                    f64::from(x % 7) * 0.25
+                };
+                canvas.write_pixel(x, 1, &Color::new(value, 0.5, 1.0));
+            }
+
+            let mut hdr = Vec::new();
+            write_hdr(&canvas, &mut hdr).unwrap();
+            let decoded = read_hdr(&mut hdr.as_slice()).unwrap();
+            assert_eq!((decoded.width, decoded.height), (width, 2));
+            for y in 0..2 {
+                for x in 0..width {
+                    assert_abs_diff_eq!(
+                        *decoded.pixel_at(x, y).unwrap(),
+                        *canvas.pixel_at(x, y).unwrap(),
+                        epsilon = 0.04
+                    );
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,166 @@
+use std::{
+    error::Error,
+    fmt,
+    fs::File,
+    io::{self, BufRead, BufReader},
+    path::Path,
+};
+
+use crate::{color::Canvas, hdr::read_hdr, png::read_png, ppm::read_ppm};
+
+/// Why an image could not be read.
+#[derive(Debug)]
+pub enum ImageError {
+    Io(io::Error),
+    /// The data does not start with the signature of a supported format.
+    UnknownFormat,
+    /// The data claims to be in `format` but does not follow it.
+    Malformed {
+        format: &'static str,
+        message: String,
+    },
+    /// Valid data using a feature of `format` this reader does not handle.
+    Unsupported {
+        format: &'static str,
+        message: String,
+    },
+}
+
+impl ImageError {
+    pub fn malformed(format: &'static str, message: impl Into<String>) -> ImageError {
+        ImageError::Malformed {
+            format,
+            message: message.into(),
+        }
+    }
+
+    pub fn unsupported(format: &'static str, message: impl Into<String>) -> ImageError {
+        ImageError::Unsupported {
+            format,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for ImageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImageError::Io(error) => write!(f, "{}", error),
+            ImageError::UnknownFormat => write!(f, "unrecognised image format"),
+            ImageError::Malformed { format, message } => {
+                write!(f, "malformed {} image: {}", format, message)
+            }
+            ImageError::Unsupported { format, message } => {
+                write!(f, "unsupported {} image: {}", format, message)
+            }
+        }
+    }
+}
+
+impl Error for ImageError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ImageError::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ImageError {
+    fn from(error: io::Error) -> ImageError {
+        ImageError::Io(error)
+    }
+}
+
+/// Reads a PPM, PNG or Radiance HDR image, recognised by its signature.
+pub fn read_image<R: BufRead>(reader: &mut R) -> Result<Canvas, ImageError> {
+    let signature = reader.fill_buf()?;
+    if signature.starts_with(&crate::png::SIGNATURE) {
+        read_png(reader)
+    } else if signature.starts_with(b"P3") || signature.starts_with(b"P6") {
+        read_ppm(reader)
+    } else if signature.starts_with(b"#?") {
+        read_hdr(reader).map_err(|error| match error.kind() {
+            io::ErrorKind::InvalidData => ImageError::malformed("Radiance HDR", error.to_string()),
+            _ => ImageError::Io(error),
+        })
+    } else {
+        Err(ImageError::UnknownFormat)
+    }
+}
+
+/// Reads the image file at `path`, whatever its supported format.
+pub fn load_image<P: AsRef<Path>>(path: P) -> Result<Canvas, ImageError> {
+    read_image(&mut BufReader::new(File::open(path)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        color::{Canvas, Color},
+        deflate::zlib_compress,
+        png::{crc32, write_png, PngOptions, SIGNATURE},
+    };
+
+    use super::{read_image, ImageError};
+
+    #[test]
+    fn read_image_detects_format() {
+        let mut canvas = Canvas::new(2, 1);
+        canvas.write_pixel(1, 0, &Color::new(1.0, 0.0, 0.0));
+        let mut png = Vec::new();
+        write_png(&canvas, &mut png, &PngOptions::DEFAULT).unwrap();
+
+        let from_png = read_image(&mut png.as_slice()).unwrap();
+        assert_abs_diff_eq!(*from_png.pixel_at(1, 0).unwrap(), Color::new(1.0, 0.0, 0.0));
+
+        let ppm = b"P3\n1 1\n255\n0 255 0\n";
+        let from_ppm = read_image(&mut ppm.as_slice()).unwrap();
+        assert_abs_diff_eq!(*from_ppm.pixel_at(0, 0).unwrap(), Color::new(0.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn read_image_rejects_unknown_data() {
+        assert!(matches!(
+            read_image(&mut b"GIF89a".as_slice()),
+            Err(ImageError::UnknownFormat)
+        ));
+    }
+
+    fn png_with_size(width: u32, height: u32, bit_depth: u8, color_type: u8) -> Vec<u8> {
+        let mut header = Vec::new();
+        header.extend(width.to_be_bytes());
+        header.extend(height.to_be_bytes());
+        header.extend([bit_depth, color_type, 0, 0, 0]);
+        let mut png = SIGNATURE.to_vec();
+        for (kind, data) in [
+            (b"IHDR", header),
+            (b"IDAT", zlib_compress(&[0; 64])),
+            (b"IEND", Vec::new()),
+        ] {
+            png.extend((data.len() as u32).to_be_bytes());
+            png.extend(kind);
+            png.extend(&data);
+            png.extend(crc32(&[kind, &data]).to_be_bytes());
+        }
+        png
+    }
+
+    #[test]
+    fn read_image_rejects_oversized_headers() {
+        let files = [
+            b"P6\n2147483647 2147483647\n65535\n\0\0\0\0".to_vec(),
+            b"P3\n40000 40000\n255\n0 0 0\n".to_vec(),
+            b"P6\n40000 40000\n255\n\0\0\0".to_vec(),
+            png_with_size(0x7fff_ffff, 0x7fff_ffff, 16, 6),
+            png_with_size(40000, 40000, 8, 2),
+            b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 100000 +X 100000\n".to_vec(),
+        ];
+        for file in files {
+            assert!(matches!(
+                read_image(&mut file.as_slice()),
+                Err(ImageError::Malformed { .. })
+            ));
+        }
+    }
+}
@@ -0,0 +1,304 @@
+use std::f64::consts::PI;
+
+use crate::{
+    bsdf::Bsdf,
+    color::Color,
+    ray::{Intersections, Ray},
+    sampling::Rng,
+    world::World,
+};
+
+/// How the camera turns a ray into a colour.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Integrator {
+    /// Direct lighting only, shading the closest hit of each ray.
+    Whitted,
+    /// Monte-Carlo path tracing with next-event estimation, following up to
+    /// `max_depth` bounces.
+    PathTracer { max_depth: u32 },
+}
+
+impl Integrator {
+    pub const DEFAULT_MAX_DEPTH: u32 = 8;
+    const ROULETTE_DEPTH: u32 = 3;
+
+    /// Colour seen by a camera ray, with the world's background behind it.
+    pub(crate) fn radiance(&self, world: &World, ray: &Ray, rng: &mut Rng) -> Color {
+        let (color, alpha) = self.trace(world, ray, rng);
+        world
+            .background(&ray.direction)
+            .scalar_mul(1.0 - alpha)
+            .add(&color)
+    }
+
+    /// Colour and alpha seen by a camera ray in front of a transparent
+    /// background, with the colour premultiplied by alpha. Misses are fully
+    /// transparent; shadow catchers come back black, with the darkening of
+    /// the shadows on them as alpha.
+    pub(crate) fn trace(&self, world: &World, ray: &Ray, rng: &mut Rng) -> (Color, f64) {
+        let intersections = world.intersect(ray);
+        let hit = match intersections.hit() {
+            Some(hit) => hit,
+            None => return (Color::BLACK, 0.0),
+        };
+        if hit.obj.material.shadow_catcher {
+            let comps = World::prepare_computations(hit, ray);
+            return (Color::BLACK, world.shadow_catcher_alpha(&comps));
+        }
+
+        let color = match self {
+            Integrator::Whitted => world.color_for_hit(Some(hit), ray),
+            Integrator::PathTracer { max_depth } => {
+                path_trace(world, ray, intersections, *max_depth, rng)
+            }
+        };
+        (color, 1.0)
+    }
+}
+
+/// Power heuristic weight for combining two sampling strategies.
+fn mis_weight(pdf: f64, other_pdf: f64) -> f64 {
+    let (a, b) = (pdf * pdf, other_pdf * other_pdf);
+    if a + b == 0.0 {
+        0.0
+    } else {
+        a / (a + b)
+    }
+}
+
+/// Follows a path from `camera_ray`, whose intersections have already been
+/// found.
+fn path_trace<'a>(
+    world: &'a World,
+    camera_ray: &Ray,
+    camera_intersections: Intersections<'a>,
+    max_depth: u32,
+    rng: &mut Rng,
+) -> Color {
+    let mut radiance = Color::BLACK;
+    let mut throughput = Color::WHITE;
+    let mut ray = camera_ray.with_kind(camera_ray.kind);
+    let mut intersections = camera_intersections;
+    let mut previous_pdf: Option<f64> = None;
+
+    for depth in 0..max_depth {
+        if depth > 0 {
+            intersections = world.intersect(&ray);
+        }
+        let hit = match intersections.hit() {
+            Some(hit) => hit,
+            None => {
+                if let Some(environment) = &world.environment {
+                    let weight = previous_pdf
+                        .map(|pdf| mis_weight(pdf, environment.pdf(&ray.direction)))
+                        .unwrap_or(1.0);
+                    let le = environment.radiance(&ray.direction);
+                    radiance = radiance.add(&throughput.mul(&le).scalar_mul(weight));
+                }
+                break;
+            }
+        };
+
+        let comps = World::prepare_computations(hit, &ray);
+
+        // Emitters are found by chance rather than sampled directly, so their
+        // light reaches the rest of the scene through BSDF sampled bounces
+        radiance = radiance.add(&throughput.mul(&comps.obj.material.emission));
+
+        let bsdf = Bsdf::new(comps.obj, &comps.point, &comps.normal);
+
+        // Next-event estimation towards the point lights. Intensities are
+        // scaled by PI so a Lambertian surface matches `lighting::lighting`.
+        for light in world
+            .lights
+            .iter()
+            .filter(|light| light.illuminates(comps.obj))
+        {
+            let light_transmission = world.light_reaching(light, &comps);
+            if light_transmission == Color::BLACK {
+                continue;
+            }
+            let wi = light.position.subtract(&comps.point).normalize();
+            let cos_theta = wi.dot(&comps.normal);
+            if cos_theta > 0.0 {
+                let f = bsdf.eval(&comps.eye, &wi);
+                radiance = radiance.add(
+                    &throughput
+                        .mul(&f)
+                        .mul(&light.intensity)
+                        .mul(&light_transmission)
+                        .scalar_mul(PI * cos_theta),
+                );
+            }
+        }
+
+        // Next-event estimation towards the environment
+        if let Some(environment) = &world.environment {
+            if let Some(sample) = environment.sample(rng.next_f64(), rng.next_f64()) {
+                let cos_theta = sample.direction.dot(&comps.normal);
+                let transmission = if comps.obj.visibility.receives_shadows {
+                    world.transmission(comps.over_point, sample.direction, f64::INFINITY)
+                } else {
+                    Color::WHITE
+                };
+                if cos_theta > 0.0 && transmission != Color::BLACK {
+                    let f = bsdf.eval(&comps.eye, &sample.direction);
+                    let weight = mis_weight(sample.pdf, bsdf.pdf(&comps.eye, &sample.direction));
+                    radiance = radiance.add(
+                        &throughput
+                            .mul(&f)
+                            .mul(&sample.radiance)
+                            .mul(&transmission)
+                            .scalar_mul(cos_theta * weight / sample.pdf),
+                    );
+                }
+            }
+        }
+
+        let sample = match bsdf.sample(&comps.eye, rng.next_f64(), rng.next_f64(), rng.next_f64()) {
+            Some(sample) => sample,
+            None => break,
+        };
+        let cos_theta = sample.direction.dot(&comps.normal);
+        throughput = throughput
+            .mul(&sample.value)
+            .scalar_mul(cos_theta / sample.pdf);
+
+        if depth >= Integrator::ROULETTE_DEPTH {
+            let survival = throughput
+                .red
+                .max(throughput.green)
+                .max(throughput.blue)
+                .min(0.95);
+            if rng.next_f64() >= survival {
+                break;
+            }
+            throughput = throughput.scalar_mul(1.0 / survival);
+        }
+
+        previous_pdf = Some(sample.pdf);
+        ray = Ray::secondary(comps.over_point, sample.direction);
+    }
+
+    radiance
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        color::{Canvas, Color},
+        environment::EnvironmentMap,
+        lighting::{LightLinks, Material, PointLight},
+        matrix4::Matrix4,
+        object::{Object, ObjectType},
+        ray::Ray,
+        sampling::Rng,
+        transform::translation,
+        tuple::Tuple,
+        world::World,
+    };
+
+    use super::Integrator;
+
+    const LIGHT: PointLight = PointLight {
+        position: Tuple::point(-10.0, 10.0, -10.0),
+        intensity: Color::WHITE,
+        links: LightLinks::ALL,
+    };
+
+    #[test]
+    fn whitted_matches_color_at() {
+        let world = World::new(LIGHT, vec![Object::SPHERE]);
+        let ray = Ray::new(Tuple::point(0.0, 0.0, -5.0), Tuple::vector(0.0, 0.0, 1.0));
+        let mut rng = Rng::new(0);
+        assert_abs_diff_eq!(
+            Integrator::Whitted.radiance(&world, &ray, &mut rng),
+            world.color_at(&ray)
+        );
+    }
+
+    #[test]
+    fn path_tracer_miss_is_black() {
+        let world = World::new(LIGHT, vec![Object::SPHERE]);
+        let ray = Ray::new(Tuple::point(0.0, 0.0, -5.0), Tuple::vector(0.0, 1.0, 0.0));
+        let integrator = Integrator::PathTracer { max_depth: 4 };
+        assert_abs_diff_eq!(
+            integrator.radiance(&world, &ray, &mut Rng::new(0)),
+            Color::BLACK
+        );
+    }
+
+    #[test]
+    fn path_tracer_emission() {
+        let dark = PointLight {
+            intensity: Color::BLACK,
+            ..LIGHT
+        };
+        let glowing = Object {
+            material: Material {
+                emission: Color::new(4.0, 4.0, 4.0),
+                ..Material::DEFAULT
+            },
+            transform: translation(0.0, 3.0, 0.0),
+            ..Object::SPHERE
+        };
+        let world = World::new(dark, vec![glowing, Object::PLANE]);
+        let integrator = Integrator::PathTracer { max_depth: 4 };
+
+        let ray = Ray::new(Tuple::point(0.0, 3.0, -5.0), Tuple::vector(0.0, 0.0, 1.0));
+        let c = integrator.radiance(&world, &ray, &mut Rng::new(1));
+        assert!(c.red >= 4.0);
+
+        // The floor below the emitter is lit only by it
+        let ray = Ray::new(
+            Tuple::point(0.0, 1.0, -1.0),
+            Tuple::vector(0.0, -1.0, 1.0).normalize(),
+        );
+        let mut rng = Rng::new(2);
+        let total = (0..500)
+            .map(|_| integrator.radiance(&world, &ray, &mut rng))
+            .fold(Color::BLACK, |acc, c| acc.add(&c));
+        assert!(total.red / 500.0 > 0.05);
+    }
+
+    #[test]
+    fn path_tracer_white_furnace() {
+        // A white diffuse sphere inside a uniform environment reflects it exactly
+        let mut image = Canvas::new(8, 4);
+        for y in 0..image.height {
+            for x in 0..image.width {
+                image.write_pixel(x, y, &Color::new(0.5, 0.5, 0.5));
+            }
+        }
+        let sphere = Object {
+            object_type: ObjectType::Sphere,
+            material: Material {
+                diffuse: 1.0,
+                specular: 0.0,
+                ..Material::DEFAULT
+            },
+            transform: Matrix4::IDENTITY,
+            ..Object::SPHERE
+        };
+        let dark = PointLight {
+            intensity: Color::BLACK,
+            ..LIGHT
+        };
+        let world =
+            World::new(dark, vec![sphere]).with_environment(EnvironmentMap::equirectangular(image));
+
+        let integrator = Integrator::PathTracer { max_depth: 2 };
+        let ray = Ray::new(Tuple::point(0.0, 0.0, -5.0), Tuple::vector(0.0, 0.0, 1.0));
+        let mut rng = Rng::new(11);
+        let samples = 2000;
+        let total = (0..samples)
+            .map(|_| integrator.radiance(&world, &ray, &mut rng))
+            .fold(Color::BLACK, |acc, c| acc.add(&c));
+
+        assert_abs_diff_eq!(
+            total.scalar_mul(1.0 / f64::from(samples)),
+            Color::new(0.5, 0.5, 0.5),
+            epsilon = 0.02
+        );
+    }
+}
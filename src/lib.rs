@@ -0,0 +1,122 @@
+//! A ray tracer following The Ray Tracer Challenge, with path tracing, image
+//! based lighting and a YAML scene format.
+//!
+//! Scenes can be built in code:
+//!
+//! ```
+//! use rusty_ray::{Camera, Color, Material, Matrix4, Object, ObjectType, PointLight, Tuple, World};
+//!
+//! let sphere = Object::builder(ObjectType::Sphere)
+//!     .material(Material::builder().color(Color::new(1.0, 0.2, 0.2)).build())
+//!     .transform(Matrix4::IDENTITY.scale(0.5, 0.5, 0.5).translate(0.0, 0.5, 0.0))
+//!     .build();
+//! let light = PointLight::builder(Tuple::point(-10.0, 10.0, -10.0)).build();
+//! let world = World::new(light, vec![sphere, Object::PLANE]);
+//!
+//! let canvas = Camera::builder(32, 16)
+//!     .look_at(
+//!         Tuple::point(0.0, 1.5, -5.0),
+//!         Tuple::point(0.0, 0.5, 0.0),
+//!         Tuple::vector(0.0, 1.0, 0.0),
+//!     )
+//!     .build()
+//!     .render(&world);
+//! assert_eq!((canvas.width, canvas.height), (32, 16));
+//! ```
+//!
+//! Struct literals starting from constants such as [`Object::SPHERE`] and
+//! [`Material::DEFAULT`] work too, for when every field is spelled out anyway.
+//!
+//! Scenes can also be loaded from files with [`load_scene`]. Rendered images
+//! are saved with [`save_png`], [`save_ppm`], [`save_hdr`] and [`save_pfm`].
+
+// The allows are for lints the original ray tracer code was written without,
+// and for the matrix builders only the tests use
+mod bsdf;
+mod checkpoint;
+#[allow(clippy::redundant_field_names)]
+mod color;
+mod deflate;
+mod environment;
+mod hdr;
+mod image;
+mod integrator;
+mod lighting;
+#[allow(dead_code, clippy::redundant_field_names)]
+mod matrix2;
+#[allow(
+    dead_code,
+    clippy::assign_op_pattern,
+    clippy::identity_op,
+    clippy::manual_is_multiple_of,
+    clippy::redundant_field_names
+)]
+mod matrix3;
+#[allow(
+    clippy::assign_op_pattern,
+    clippy::identity_op,
+    clippy::manual_is_multiple_of,
+    clippy::needless_range_loop,
+    clippy::redundant_field_names
+)]
+mod matrix4;
+mod microfacet;
+#[allow(mismatched_lifetime_syntaxes, clippy::needless_borrow)]
+mod object;
+#[allow(clippy::needless_borrow)]
+mod pattern;
+mod pfm;
+#[allow(clippy::redundant_field_names)]
+mod plane;
+mod png;
+mod ppm;
+#[allow(mismatched_lifetime_syntaxes, clippy::redundant_field_names)]
+mod ray;
+mod render;
+mod sampling;
+mod scene;
+#[allow(clippy::approx_constant)]
+mod sphere;
+mod tonemap;
+#[allow(clippy::needless_borrow)]
+mod transform;
+#[allow(
+    clippy::bool_assert_comparison,
+    clippy::needless_borrow,
+    clippy::redundant_field_names
+)]
+mod tuple;
+#[allow(
+    mismatched_lifetime_syntaxes,
+    clippy::bool_assert_comparison,
+    clippy::redundant_field_names
+)]
+mod world;
+mod yaml;
+
+pub use checkpoint::{Checkpoint, CheckpointError};
+pub use color::{Canvas, Color};
+pub use environment::EnvironmentMap;
+pub use hdr::save_hdr;
+pub use image::ImageError;
+pub use integrator::Integrator;
+pub use lighting::{
+    LightLinks, Material, MaterialBuilder, PointLight, PointLightBuilder, ShadingModel,
+};
+pub use matrix4::{Matrix4, Matrix4Builder, Matrix4Builder2, Matrix4Builder3};
+pub use object::{Object, ObjectBuilder, ObjectType, Visibility};
+pub use pattern::Pattern;
+pub use pfm::save_pfm;
+pub use png::{read_png, save_png, write_png, BitDepth, ColorType, PngOptions};
+pub use ppm::{save_ppm, PpmFormat};
+pub use render::{
+    Budget, CancelToken, Progress, Region, RenderControl, RenderOutput, SnapshotInterval,
+};
+pub use scene::{load_scene, parse_scene, Position, Scene, SceneError};
+pub use tonemap::{Exponent, Gamma, OutputTransform, ToneMap};
+pub use tuple::Tuple;
+pub use world::{Camera, CameraBuilder, World};
+
+#[cfg(test)]
+#[macro_use]
+extern crate approx;
@@ -1,9 +1,89 @@
-use crate::{color::Color, object::Object, pattern::Pattern, tuple::Tuple};
+use std::{f64::consts::PI, sync::Arc};
 
-#[derive(Copy, Clone)]
+use crate::{color::Color, microfacet::Microfacet, object::Object, pattern::Pattern, tuple::Tuple};
+
+#[derive(Clone)]
 pub struct PointLight {
     pub position: Tuple,
     pub intensity: Color,
+    pub links: LightLinks,
+}
+
+impl PointLight {
+    /// A white light at `position` that illuminates every object.
+    pub fn builder(position: Tuple) -> PointLightBuilder {
+        PointLightBuilder {
+            light: PointLight {
+                position,
+                intensity: Color::WHITE,
+                links: LightLinks::ALL,
+            },
+        }
+    }
+
+    /// Whether the light contributes to the shading of `object`.
+    pub fn illuminates(&self, object: &Object) -> bool {
+        self.links.includes(object)
+    }
+}
+
+pub struct PointLightBuilder {
+    light: PointLight,
+}
+
+impl PointLightBuilder {
+    pub fn intensity(mut self, intensity: Color) -> PointLightBuilder {
+        self.light.intensity = intensity;
+        self
+    }
+
+    /// Links the light to the object with this name or tag, on top of any
+    /// already included. Once anything is included, only those objects are lit.
+    pub fn include(mut self, label: &str) -> PointLightBuilder {
+        self.light.links.include.push(label.into());
+        self
+    }
+
+    pub fn exclude(mut self, label: &str) -> PointLightBuilder {
+        self.light.links.exclude.push(label.into());
+        self
+    }
+
+    pub fn build(self) -> PointLight {
+        self.light
+    }
+}
+
+/// Restricts a light to a subset of the objects in the world. Objects are
+/// matched by name or by any of their tags. An empty `include` list links
+/// every object; `exclude` takes precedence over `include`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LightLinks {
+    pub include: Vec<Arc<str>>,
+    pub exclude: Vec<Arc<str>>,
+}
+
+impl LightLinks {
+    pub const ALL: LightLinks = LightLinks {
+        include: Vec::new(),
+        exclude: Vec::new(),
+    };
+
+    pub fn includes(&self, object: &Object) -> bool {
+        let matches = |labels: &[Arc<str>]| labels.iter().any(|label| object.has_label(label));
+        (self.include.is_empty() || matches(&self.include)) && !matches(&self.exclude)
+    }
+}
+
+/// How a material reflects light. `Phong` uses the `diffuse`, `specular` and
+/// `shininess` parameters; `BlinnPhong` uses the same parameters with a half
+/// vector highlight; `Microfacet` ignores them in favour of an energy
+/// conserving GGX model driven by the pattern colour.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ShadingModel {
+    Phong,
+    BlinnPhong,
+    Microfacet { metallic: f64, roughness: f64 },
 }
 
 #[derive(Clone, Copy)]
@@ -13,6 +93,18 @@ pub struct Material {
     pub diffuse: f64,
     pub specular: f64,
     pub shininess: f64,
+    pub shading: ShadingModel,
+    /// Light given off by the surface itself, added whether or not it is lit.
+    pub emission: Color,
+    /// Fraction of light let through to shadows behind the object, tinted by
+    /// its pattern colour. Zero is fully opaque.
+    pub transparency: f64,
+    /// Hides the surface from the camera except for the shadows falling on
+    /// it, which are rendered as black with the darkening as alpha, for
+    /// compositing over a photograph. Reflected rays and path tracer bounces
+    /// pass straight through, as the photograph already has the surface's
+    /// reflections and bounced light.
+    pub shadow_catcher: bool,
 }
 
 impl Material {
@@ -21,15 +113,130 @@ impl Material {
     pub const DEFAULT_DIFFUSE: f64 = 0.9;
     pub const DEFAULT_SHININESS: f64 = 200.0;
 
+    /// A Blinn-Phong exponent this many times the Phong `shininess` gives a
+    /// highlight of roughly the same size.
+    pub const BLINN_PHONG_SHININESS_SCALE: f64 = 4.0;
+
     pub const DEFAULT: Material = Material {
         pattern: Pattern::WHITE,
         ambient: Material::DEFAULT_AMBIENT,
         diffuse: Material::DEFAULT_DIFFUSE,
         specular: Material::DEFAULT_SPECULAR,
         shininess: Material::DEFAULT_SHININESS,
+        shading: ShadingModel::Phong,
+        emission: Color::BLACK,
+        transparency: 0.0,
+        shadow_catcher: false,
     };
+
+    /// Starts from `Material::DEFAULT`.
+    pub fn builder() -> MaterialBuilder {
+        MaterialBuilder {
+            material: Material::DEFAULT,
+        }
+    }
+
+    /// The microfacet parameters of this material at a point with the given
+    /// base colour, when it uses that model.
+    pub(crate) fn microfacet(&self, base_color: Color) -> Option<Microfacet> {
+        match self.shading {
+            ShadingModel::Phong | ShadingModel::BlinnPhong => None,
+            ShadingModel::Microfacet {
+                metallic,
+                roughness,
+            } => Some(Microfacet {
+                base_color,
+                metallic,
+                roughness,
+            }),
+        }
+    }
+
+    /// Cosine of the angle that drives the specular highlight, and the
+    /// exponent it is raised to, for the Phong family of models.
+    pub fn specular_term(
+        &self,
+        light_vector: &Tuple,
+        eye_vector: &Tuple,
+        normal: &Tuple,
+    ) -> (f64, f64) {
+        match self.shading {
+            ShadingModel::BlinnPhong => {
+                let half = light_vector.add(eye_vector).normalize();
+                (
+                    half.dot(normal),
+                    self.shininess * Material::BLINN_PHONG_SHININESS_SCALE,
+                )
+            }
+            _ => {
+                let reflect_vector = light_vector.negate().reflect(normal);
+                (reflect_vector.dot(eye_vector), self.shininess)
+            }
+        }
+    }
 }
 
+pub struct MaterialBuilder {
+    material: Material,
+}
+
+impl MaterialBuilder {
+    /// Shorthand for a constant pattern.
+    pub fn color(self, color: Color) -> MaterialBuilder {
+        self.pattern(Pattern::Constant { value: color })
+    }
+
+    pub fn pattern(mut self, pattern: Pattern) -> MaterialBuilder {
+        self.material.pattern = pattern;
+        self
+    }
+
+    pub fn ambient(mut self, ambient: f64) -> MaterialBuilder {
+        self.material.ambient = ambient;
+        self
+    }
+
+    pub fn diffuse(mut self, diffuse: f64) -> MaterialBuilder {
+        self.material.diffuse = diffuse;
+        self
+    }
+
+    pub fn specular(mut self, specular: f64) -> MaterialBuilder {
+        self.material.specular = specular;
+        self
+    }
+
+    pub fn shininess(mut self, shininess: f64) -> MaterialBuilder {
+        self.material.shininess = shininess;
+        self
+    }
+
+    pub fn shading(mut self, shading: ShadingModel) -> MaterialBuilder {
+        self.material.shading = shading;
+        self
+    }
+
+    pub fn emission(mut self, emission: Color) -> MaterialBuilder {
+        self.material.emission = emission;
+        self
+    }
+
+    pub fn transparency(mut self, transparency: f64) -> MaterialBuilder {
+        self.material.transparency = transparency;
+        self
+    }
+
+    pub fn shadow_catcher(mut self, shadow_catcher: bool) -> MaterialBuilder {
+        self.material.shadow_catcher = shadow_catcher;
+        self
+    }
+
+    pub fn build(self) -> Material {
+        self.material
+    }
+}
+
+#[cfg(test)]
 pub fn lighting(
     object: &Object,
     light: &PointLight,
@@ -38,20 +245,85 @@ pub fn lighting(
     normal_vector: &Tuple,
     is_in_shadow: bool,
 ) -> Color {
-    let effective_color = object
+    let light_transmission = if is_in_shadow {
+        Color::BLACK
+    } else {
+        Color::WHITE
+    };
+    lighting_transmitted(
+        object,
+        light,
+        position,
+        eye_vector,
+        normal_vector,
+        &light_transmission,
+    )
+}
+
+/// Like `lighting`, but with the light dimmed and tinted by the fraction of
+/// it that makes it through the objects between the light and the point.
+#[cfg(test)]
+pub fn lighting_transmitted(
+    object: &Object,
+    light: &PointLight,
+    position: &Tuple,
+    eye_vector: &Tuple,
+    normal_vector: &Tuple,
+    light_transmission: &Color,
+) -> Color {
+    let ambient = ambient_lighting(object, light, position);
+
+    object.material.emission.add(&ambient).add(&direct_lighting(
+        object,
+        light,
+        position,
+        eye_vector,
+        normal_vector,
+        light_transmission,
+    ))
+}
+
+/// The ambient term of `light`, which ignores shadows.
+pub fn ambient_lighting(object: &Object, light: &PointLight, position: &Tuple) -> Color {
+    object
         .material
         .pattern
-        .color_at(object, &position)
-        .mul(&light.intensity);
-    let ambient = effective_color.scalar_mul(object.material.ambient);
+        .color_at(object, position)
+        .mul(&light.intensity)
+        .scalar_mul(object.material.ambient)
+}
 
-    if is_in_shadow {
-        return ambient;
+/// The diffuse and specular contribution of `light`, without the ambient term.
+pub fn direct_lighting(
+    object: &Object,
+    light: &PointLight,
+    position: &Tuple,
+    eye_vector: &Tuple,
+    normal_vector: &Tuple,
+    light_transmission: &Color,
+) -> Color {
+    if *light_transmission == Color::BLACK {
+        return Color::BLACK;
     }
 
-    let light_vector = light.position.subtract(&position).normalize();
-    let light_dot_normal = light_vector.dot(&normal_vector);
+    let intensity = light.intensity.mul(light_transmission);
+    let base_color = object.material.pattern.color_at(object, position);
+    let light_vector = light.position.subtract(position).normalize();
+    let light_dot_normal = light_vector.dot(normal_vector);
+
+    // Intensities are scaled by PI so a white Lambertian surface facing the
+    // light reflects the same as a Phong material with `diffuse` of 1.0
+    if let Some(microfacet) = object.material.microfacet(base_color) {
+        if light_dot_normal <= 0.0 {
+            return Color::BLACK;
+        }
+        return microfacet
+            .eval(normal_vector, eye_vector, &light_vector)
+            .mul(&intensity)
+            .scalar_mul(PI * light_dot_normal);
+    }
 
+    let effective_color = base_color.mul(&intensity);
     let mut diffuse = Color::new(0.0, 0.0, 0.0);
     let mut specular = Color::new(0.0, 0.0, 0.0);
 
@@ -60,24 +332,43 @@ pub fn lighting(
             .scalar_mul(object.material.diffuse)
             .scalar_mul(light_dot_normal);
 
-        let reflect_vector = light_vector.negate().reflect(&normal_vector);
-        let reflect_dot_eye = reflect_vector.dot(&eye_vector);
+        let (cos_alpha, shininess) =
+            object
+                .material
+                .specular_term(&light_vector, eye_vector, normal_vector);
 
-        if reflect_dot_eye > 0.0 {
-            let factor = f64::powf(reflect_dot_eye, object.material.shininess);
-            specular = light
-                .intensity
+        if cos_alpha > 0.0 {
+            let factor = f64::powf(cos_alpha, shininess);
+            specular = intensity
                 .scalar_mul(object.material.specular)
                 .scalar_mul(factor);
         }
     }
 
-    ambient.add(&diffuse).add(&specular)
+    diffuse.add(&specular)
+}
+
+/// Diffuse reflection of the light arriving from the environment, which takes
+/// the place of the material's constant ambient term. Microfacet materials
+/// use the diffuse lobe of their BRDF, so metals reflect none.
+pub fn environment_lighting(
+    object: &Object,
+    position: &Tuple,
+    eye_vector: &Tuple,
+    normal_vector: &Tuple,
+    irradiance: &Color,
+) -> Color {
+    let base_color = object.material.pattern.color_at(object, position);
+    let reflectance = match object.material.microfacet(base_color) {
+        Some(microfacet) => microfacet.diffuse_reflectance(normal_vector, eye_vector),
+        None => base_color.scalar_mul(object.material.diffuse),
+    };
+    reflectance.mul(irradiance).scalar_mul(1.0 / PI)
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{lighting, Material, PointLight};
+    use super::{lighting, lighting_transmitted, LightLinks, Material, PointLight, ShadingModel};
     use crate::{
         color::Color,
         matrix4::Matrix4,
@@ -95,6 +386,7 @@ mod tests {
         let light = PointLight {
             position: Tuple::point(0.0, 0.0, -10.0),
             intensity: Color::WHITE,
+            links: LightLinks::ALL,
         };
         assert_abs_diff_eq!(
             lighting(
@@ -116,6 +408,7 @@ mod tests {
         let light = PointLight {
             position: Tuple::point(0.0, 0.0, -10.0),
             intensity: Color::WHITE,
+            links: LightLinks::ALL,
         };
         assert_abs_diff_eq!(
             lighting(
@@ -137,6 +430,7 @@ mod tests {
         let light = PointLight {
             position: Tuple::point(0.0, 0.0, -10.0),
             intensity: Color::WHITE,
+            links: LightLinks::ALL,
         };
         assert_abs_diff_eq!(
             lighting(
@@ -158,6 +452,7 @@ mod tests {
         let light = PointLight {
             position: Tuple::point(0.0, 10.0, -10.0),
             intensity: Color::WHITE,
+            links: LightLinks::ALL,
         };
         assert_abs_diff_eq!(
             lighting(
@@ -179,6 +474,7 @@ mod tests {
         let light = PointLight {
             position: Tuple::point(0.0, 10.0, -10.0),
             intensity: Color::WHITE,
+            links: LightLinks::ALL,
         };
         assert_abs_diff_eq!(
             lighting(
@@ -200,6 +496,7 @@ mod tests {
         let light = PointLight {
             position: Tuple::point(0.0, 0.0, 10.0),
             intensity: Color::WHITE,
+            links: LightLinks::ALL,
         };
         assert_abs_diff_eq!(
             lighting(
@@ -214,6 +511,156 @@ mod tests {
         );
     }
 
+    #[test]
+    fn lighting_emission_ignores_shadow() {
+        let s = Object {
+            material: Material {
+                emission: Color::new(0.5, 0.2, 0.0),
+                ..Material::DEFAULT
+            },
+            ..Object::SPHERE
+        };
+        let eye_vector = Tuple::vector(0.0, 0.0, -1.0);
+        let normal_vector = Tuple::vector(0.0, 0.0, -1.0);
+        let light = PointLight {
+            position: Tuple::point(0.0, 0.0, -10.0),
+            intensity: Color::WHITE,
+            links: LightLinks::ALL,
+        };
+        assert_abs_diff_eq!(
+            lighting(&s, &light, &POINT, &eye_vector, &normal_vector, false),
+            Color::new(2.4, 2.1, 1.9)
+        );
+        assert_abs_diff_eq!(
+            lighting(&s, &light, &POINT, &eye_vector, &normal_vector, true),
+            Color::new(0.6, 0.3, 0.1)
+        );
+    }
+
+    #[test]
+    fn lighting_partially_transmitted_light() {
+        let eye_vector = Tuple::vector(0.0, 0.0, -1.0);
+        let normal_vector = Tuple::vector(0.0, 0.0, -1.0);
+        let light = PointLight {
+            position: Tuple::point(0.0, 0.0, -10.0),
+            intensity: Color::WHITE,
+            links: LightLinks::ALL,
+        };
+        assert_abs_diff_eq!(
+            lighting_transmitted(
+                &Object::SPHERE,
+                &light,
+                &POINT,
+                &eye_vector,
+                &normal_vector,
+                &Color::new(0.5, 0.0, 1.0)
+            ),
+            Color::new(1.0, 0.1, 1.9)
+        );
+    }
+
+    #[test]
+    fn lighting_blinn_phong_eye_in_reflection_path() {
+        let s = Object {
+            material: Material {
+                shading: ShadingModel::BlinnPhong,
+                ..Material::DEFAULT
+            },
+            ..Object::SPHERE
+        };
+        let eye_vector = Tuple::vector(0.0, -f64::sqrt(2.0) / 2.0, -f64::sqrt(2.0) / 2.0);
+        let normal_vector = Tuple::vector(0.0, 0.0, -1.0);
+        let light = PointLight {
+            position: Tuple::point(0.0, 10.0, -10.0),
+            intensity: Color::WHITE,
+            links: LightLinks::ALL,
+        };
+        assert_abs_diff_eq!(
+            lighting(&s, &light, &POINT, &eye_vector, &normal_vector, false),
+            Color::new(1.6364, 1.6364, 1.6364)
+        );
+    }
+
+    #[test]
+    fn lighting_blinn_phong_approximates_phong() {
+        let blinn = Object {
+            material: Material {
+                shading: ShadingModel::BlinnPhong,
+                shininess: 20.0,
+                ..Material::DEFAULT
+            },
+            ..Object::SPHERE
+        };
+        let phong = Object {
+            material: Material {
+                shininess: 20.0,
+                ..Material::DEFAULT
+            },
+            ..Object::SPHERE
+        };
+        let normal_vector = Tuple::vector(0.0, 0.0, -1.0);
+        let light = PointLight {
+            position: Tuple::point(0.0, 0.0, -10.0),
+            intensity: Color::WHITE,
+            links: LightLinks::ALL,
+        };
+
+        for angle in [0.05, 0.1, 0.2, 0.3] {
+            let eye_vector = Tuple::vector(0.0, f64::sin(angle), -f64::cos(angle));
+            let b = lighting(&blinn, &light, &POINT, &eye_vector, &normal_vector, false);
+            let p = lighting(&phong, &light, &POINT, &eye_vector, &normal_vector, false);
+            assert_abs_diff_eq!(b, p, epsilon = 0.05);
+        }
+    }
+
+    #[test]
+    fn lighting_microfacet_dielectric() {
+        let s = Object {
+            material: Material {
+                shading: ShadingModel::Microfacet {
+                    metallic: 0.0,
+                    roughness: 1.0,
+                },
+                ..Material::DEFAULT
+            },
+            ..Object::SPHERE
+        };
+        let eye_vector = Tuple::vector(0.0, 0.0, -1.0);
+        let normal_vector = Tuple::vector(0.0, 0.0, -1.0);
+        let light = PointLight {
+            position: Tuple::point(0.0, 0.0, -10.0),
+            intensity: Color::WHITE,
+            links: LightLinks::ALL,
+        };
+        let c = lighting(&s, &light, &POINT, &eye_vector, &normal_vector, false);
+
+        // A rough dielectric is mostly diffuse, and never brighter than the light
+        assert!(c.red > 0.9 && c.red <= 1.0 + Material::DEFAULT_AMBIENT);
+    }
+
+    #[test]
+    fn lighting_microfacet_metal_has_no_diffuse() {
+        let s = Object {
+            material: Material {
+                shading: ShadingModel::Microfacet {
+                    metallic: 1.0,
+                    roughness: 0.1,
+                },
+                ..Material::DEFAULT
+            },
+            ..Object::SPHERE
+        };
+        let eye_vector = Tuple::vector(0.0, 0.0, -1.0);
+        let normal_vector = Tuple::vector(0.0, 0.0, -1.0);
+        let light = PointLight {
+            position: Tuple::point(0.0, 10.0, -10.0),
+            intensity: Color::WHITE,
+            links: LightLinks::ALL,
+        };
+        let c = lighting(&s, &light, &POINT, &eye_vector, &normal_vector, false);
+        assert_abs_diff_eq!(c, Color::new(0.1, 0.1, 0.1), epsilon = 0.01);
+    }
+
     #[test]
     fn lighting_pattern() {
         let mat = Material {
@@ -226,16 +673,19 @@ mod tests {
             diffuse: 0.0,
             specular: 0.0,
             shininess: Material::DEFAULT_SHININESS,
+            ..Material::DEFAULT
         };
 
         let s = Object {
             object_type: ObjectType::Sphere,
             material: mat,
             transform: Matrix4::IDENTITY,
+            ..Object::SPHERE
         };
         let light = PointLight {
             position: Tuple::point(0.0, 0.0, -10.0),
             intensity: Color::WHITE,
+            links: LightLinks::ALL,
         };
 
         let eye = Tuple::vector(0.0, 0.0, -1.0);
@@ -260,4 +710,76 @@ mod tests {
         assert_abs_diff_eq!(c1, Color::WHITE);
         assert_abs_diff_eq!(c2, Color::BLACK);
     }
+
+    #[test]
+    fn light_links_match_names_and_tags() {
+        let hero = Object {
+            name: Some("hero".into()),
+            tags: vec!["characters".into()],
+            ..Object::SPHERE
+        };
+        let extra = Object {
+            tags: vec!["characters".into(), "background".into()],
+            ..Object::SPHERE
+        };
+        let unnamed = Object::SPHERE;
+
+        assert!(LightLinks::ALL.includes(&hero));
+        assert!(LightLinks::ALL.includes(&unnamed));
+
+        let fill = LightLinks {
+            include: vec!["hero".into()],
+            exclude: Vec::new(),
+        };
+        assert!(fill.includes(&hero));
+        assert!(!fill.includes(&extra));
+        assert!(!fill.includes(&unnamed));
+
+        let key = LightLinks {
+            include: vec!["characters".into()],
+            exclude: vec!["background".into()],
+        };
+        assert!(key.includes(&hero));
+        assert!(!key.includes(&extra));
+    }
+
+    #[test]
+    fn builders_start_from_defaults() {
+        let material = Material::builder()
+            .color(Color::new(1.0, 0.2, 0.2))
+            .diffuse(0.7)
+            .shading(ShadingModel::BlinnPhong)
+            .build();
+        assert_eq!(material.ambient, Material::DEFAULT_AMBIENT);
+        assert_eq!(material.diffuse, 0.7);
+        assert_eq!(material.shading, ShadingModel::BlinnPhong);
+        assert!(matches!(
+            material.pattern,
+            Pattern::Constant { value } if value == Color::new(1.0, 0.2, 0.2)
+        ));
+
+        let hero = Object::builder(ObjectType::Sphere)
+            .material(material)
+            .transform(Matrix4::IDENTITY.translate(0.0, 1.0, 0.0))
+            .name("hero")
+            .tag("characters")
+            .build();
+        assert!(hero.has_label("hero") && hero.has_label("characters"));
+        assert_eq!(hero.transform, Matrix4::IDENTITY.translate(0.0, 1.0, 0.0));
+        assert_eq!(hero.material.diffuse, 0.7);
+
+        let light = PointLight::builder(Tuple::point(0.0, 10.0, 0.0))
+            .include("characters")
+            .exclude("background")
+            .build();
+        assert_eq!(light.intensity, Color::WHITE);
+        assert_eq!(
+            light.links,
+            LightLinks {
+                include: vec!["characters".into()],
+                exclude: vec!["background".into()],
+            }
+        );
+        assert!(light.illuminates(&hero));
+    }
 }
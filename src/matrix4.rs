@@ -91,7 +91,6 @@ impl Matrix4 {
         (r * 4) + c
     }
 
-    #[allow(dead_code)]
     pub fn at(&self, r: usize, c: usize) -> f64 {
         self.values[Matrix4::row_col_index(r, c)]
     }
@@ -134,7 +133,7 @@ impl Matrix4 {
         Matrix4 { values: values }
     }
 
-    pub fn sub_matrix(&self, row_to_remove: usize, col_to_remove: usize) -> Matrix3 {
+    pub(crate) fn sub_matrix(&self, row_to_remove: usize, col_to_remove: usize) -> Matrix3 {
         let mut values = [-1.0; 9];
         let mut r = 0;
         for cur_row in 0..4 {
@@ -175,7 +174,6 @@ impl Matrix4 {
         det
     }
 
-    #[allow(dead_code)]
     pub fn is_invertible(&self) -> bool {
         self.determinant() != 0.0
     }
@@ -0,0 +1,230 @@
+use std::f64::consts::PI;
+
+use crate::{
+    color::Color,
+    sampling::{cosine_sample_hemisphere, direction_around},
+    tuple::Tuple,
+};
+
+/// Reflectance of dielectrics at normal incidence.
+const DIELECTRIC_F0: f64 = 0.04;
+
+/// Roughness is clamped away from zero so the distribution never becomes a
+/// delta function.
+const MIN_ROUGHNESS: f64 = 0.02;
+
+/// Metallic/roughness parameters of a surface point, as authored in most
+/// physically based tools. `roughness` is perceptual, squared to give the GGX
+/// width.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Microfacet {
+    pub base_color: Color,
+    pub metallic: f64,
+    pub roughness: f64,
+}
+
+impl Microfacet {
+    pub fn alpha(&self) -> f64 {
+        let roughness = self.roughness.clamp(MIN_ROUGHNESS, 1.0);
+        roughness * roughness
+    }
+
+    /// Reflectance at normal incidence, tinted by the base colour for metals.
+    pub fn f0(&self) -> Color {
+        let metallic = self.metallic.clamp(0.0, 1.0);
+        Color::new(DIELECTRIC_F0, DIELECTRIC_F0, DIELECTRIC_F0)
+            .scalar_mul(1.0 - metallic)
+            .add(&self.base_color.scalar_mul(metallic))
+    }
+
+    /// BRDF value for light arriving from `wi` and leaving towards `wo`: a
+    /// GGX specular lobe with the Smith geometry term and Schlick's Fresnel,
+    /// plus a Lambertian lobe for the energy that is not reflected.
+    pub fn eval(&self, normal: &Tuple, wo: &Tuple, wi: &Tuple) -> Color {
+        let n_dot_l = normal.dot(wi);
+        let n_dot_v = normal.dot(wo);
+        if n_dot_l <= 0.0 || n_dot_v <= 0.0 {
+            return Color::BLACK;
+        }
+
+        let half = wo.add(wi).normalize();
+        let n_dot_h = normal.dot(&half).max(0.0);
+        let v_dot_h = wo.dot(&half).max(0.0);
+        let alpha = self.alpha();
+
+        let fresnel = fresnel_schlick(&self.f0(), v_dot_h);
+        let d = ggx_distribution(n_dot_h, alpha);
+        let g = smith_g1(n_dot_v, alpha) * smith_g1(n_dot_l, alpha);
+        let specular = fresnel.scalar_mul(d * g / (4.0 * n_dot_v * n_dot_l));
+
+        let kd = Color::WHITE
+            .subtract(&fresnel)
+            .scalar_mul(1.0 - self.metallic.clamp(0.0, 1.0));
+        let diffuse = kd.mul(&self.base_color).scalar_mul(1.0 / PI);
+
+        diffuse.add(&specular)
+    }
+
+    /// Fraction of the light arriving from all directions that the Lambertian
+    /// lobe sends towards `wo`, with the Fresnel term taken at the viewing
+    /// angle. Zero for metals.
+    pub fn diffuse_reflectance(&self, normal: &Tuple, wo: &Tuple) -> Color {
+        let fresnel = fresnel_schlick(&self.f0(), normal.dot(wo).max(0.0));
+        Color::WHITE
+            .subtract(&fresnel)
+            .scalar_mul(1.0 - self.metallic.clamp(0.0, 1.0))
+            .mul(&self.base_color)
+    }
+
+    /// Probability of sampling the specular lobe rather than the diffuse one.
+    fn specular_probability(&self, normal: &Tuple, wo: &Tuple) -> f64 {
+        let specular = fresnel_schlick(&self.f0(), normal.dot(wo).max(0.0)).luminance();
+        let diffuse = (1.0 - self.metallic.clamp(0.0, 1.0)) * self.base_color.luminance();
+        if specular + diffuse <= 0.0 {
+            1.0
+        } else {
+            specular / (specular + diffuse)
+        }
+    }
+
+    pub fn pdf(&self, normal: &Tuple, wo: &Tuple, wi: &Tuple) -> f64 {
+        let n_dot_l = normal.dot(wi);
+        if n_dot_l <= 0.0 {
+            return 0.0;
+        }
+
+        let half = wo.add(wi).normalize();
+        let n_dot_h = normal.dot(&half).max(0.0);
+        let v_dot_h = wo.dot(&half);
+        let specular_pdf = if v_dot_h > 0.0 {
+            ggx_distribution(n_dot_h, self.alpha()) * n_dot_h / (4.0 * v_dot_h)
+        } else {
+            0.0
+        };
+
+        let p = self.specular_probability(normal, wo);
+        p * specular_pdf + (1.0 - p) * n_dot_l / PI
+    }
+
+    /// Picks an incoming direction, either by reflecting `wo` about a half
+    /// vector drawn from the GGX distribution or from the cosine lobe.
+    pub fn sample_direction(&self, normal: &Tuple, wo: &Tuple, u1: f64, u2: f64, u3: f64) -> Tuple {
+        if u1 < self.specular_probability(normal, wo) {
+            let alpha = self.alpha();
+            let cos_theta = ((1.0 - u2) / (1.0 + (alpha * alpha - 1.0) * u2)).sqrt();
+            let half = direction_around(normal, cos_theta, 2.0 * PI * u3);
+            wo.negate().reflect(&half)
+        } else {
+            cosine_sample_hemisphere(normal, u2, u3)
+        }
+    }
+}
+
+pub fn fresnel_schlick(f0: &Color, cos_theta: f64) -> Color {
+    let factor = (1.0 - cos_theta.clamp(0.0, 1.0)).powi(5);
+    f0.add(&Color::WHITE.subtract(f0).scalar_mul(factor))
+}
+
+/// GGX (Trowbridge-Reitz) normal distribution.
+pub fn ggx_distribution(n_dot_h: f64, alpha: f64) -> f64 {
+    let a2 = alpha * alpha;
+    let d = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+    a2 / (PI * d * d)
+}
+
+/// Smith masking term for one direction under the GGX distribution.
+pub fn smith_g1(n_dot_v: f64, alpha: f64) -> f64 {
+    let a2 = alpha * alpha;
+    2.0 * n_dot_v / (n_dot_v + (a2 + (1.0 - a2) * n_dot_v * n_dot_v).sqrt())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+
+    use crate::{color::Color, sampling::Rng, tuple::Tuple};
+
+    use super::{fresnel_schlick, ggx_distribution, smith_g1, Microfacet};
+
+    const NORMAL: Tuple = Tuple::vector(0.0, 1.0, 0.0);
+
+    #[test]
+    fn fresnel_at_normal_and_grazing() {
+        let f0 = Color::new(0.04, 0.04, 0.04);
+        assert_abs_diff_eq!(fresnel_schlick(&f0, 1.0), f0);
+        assert_abs_diff_eq!(fresnel_schlick(&f0, 0.0), Color::WHITE);
+    }
+
+    #[test]
+    fn ggx_distribution_is_normalized() {
+        // Integrate D(h) cos(h) over the hemisphere with a Riemann sum
+        let alpha = 0.3;
+        let steps = 2000;
+        let d_theta = (PI / 2.0) / f64::from(steps);
+        let integral: f64 = (0..steps)
+            .map(|i| {
+                let theta = (f64::from(i) + 0.5) * d_theta;
+                ggx_distribution(theta.cos(), alpha)
+                    * theta.cos()
+                    * theta.sin()
+                    * 2.0
+                    * PI
+                    * d_theta
+            })
+            .sum();
+        assert_abs_diff_eq!(integral, 1.0, epsilon = 1e-3);
+    }
+
+    #[test]
+    fn smith_g1_is_one_at_normal_incidence() {
+        assert_abs_diff_eq!(smith_g1(1.0, 0.5), 1.0);
+        assert!(smith_g1(0.1, 0.5) < 1.0);
+    }
+
+    #[test]
+    fn metals_take_f0_from_base_color() {
+        let m = Microfacet {
+            base_color: Color::new(1.0, 0.8, 0.3),
+            metallic: 1.0,
+            roughness: 0.5,
+        };
+        assert_abs_diff_eq!(m.f0(), Color::new(1.0, 0.8, 0.3));
+    }
+
+    #[test]
+    fn reflects_no_more_than_arrives() {
+        let mut rng = Rng::new(5);
+        let wo = Tuple::vector(0.0, 1.0, -1.0).normalize();
+        for (metallic, roughness) in [(0.0, 0.2), (0.0, 0.9), (1.0, 0.3), (0.5, 0.6)] {
+            let m = Microfacet {
+                base_color: Color::WHITE,
+                metallic,
+                roughness,
+            };
+            let samples = 20000;
+            let mut albedo = 0.0;
+            for _ in 0..samples {
+                let wi = m.sample_direction(
+                    &NORMAL,
+                    &wo,
+                    rng.next_f64(),
+                    rng.next_f64(),
+                    rng.next_f64(),
+                );
+                let pdf = m.pdf(&NORMAL, &wo, &wi);
+                if pdf > 0.0 {
+                    albedo += m.eval(&NORMAL, &wo, &wi).green * NORMAL.dot(&wi) / pdf;
+                }
+            }
+            albedo /= f64::from(samples);
+            assert!(
+                albedo <= 1.02,
+                "albedo {} for {} {}",
+                albedo,
+                metallic,
+                roughness
+            );
+            assert!(albedo > 0.5);
+        }
+    }
+}
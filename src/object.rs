@@ -1,5 +1,7 @@
+use std::sync::Arc;
+
 use crate::{
-    lighting::Material, matrix4::Matrix4, plane::plane_object_intersect,
+    lighting::Material, matrix4::Matrix4, plane::plane_object_intersect, ray::RayKind,
     sphere::sphere_object_intersect, tuple::Tuple,
 };
 
@@ -9,11 +11,41 @@ pub enum ObjectType {
     Plane,
 }
 
-#[derive(Clone, Copy)]
+/// Which kinds of ray an object takes part in.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Visibility {
+    pub casts_shadows: bool,
+    pub visible_to_camera: bool,
+    pub visible_in_reflections: bool,
+    pub receives_shadows: bool,
+}
+
+impl Visibility {
+    pub const ALL: Visibility = Visibility {
+        casts_shadows: true,
+        visible_to_camera: true,
+        visible_in_reflections: true,
+        receives_shadows: true,
+    };
+
+    pub(crate) fn sees(&self, kind: RayKind) -> bool {
+        match kind {
+            RayKind::Camera => self.visible_to_camera,
+            RayKind::Shadow => self.casts_shadows,
+            RayKind::Secondary => self.visible_in_reflections,
+        }
+    }
+}
+
+#[derive(Clone)]
 pub struct Object {
     pub object_type: ObjectType,
     pub material: Material,
     pub transform: Matrix4,
+    pub visibility: Visibility,
+    /// Lets lights be linked to the object by name or tag.
+    pub name: Option<Arc<str>>,
+    pub tags: Vec<Arc<str>>,
 }
 
 impl Object {
@@ -21,14 +53,41 @@ impl Object {
         object_type: ObjectType::Sphere,
         material: Material::DEFAULT,
         transform: Matrix4::IDENTITY,
+        visibility: Visibility::ALL,
+        name: None,
+        tags: Vec::new(),
     };
     pub const PLANE: Object = Object {
         object_type: ObjectType::Plane,
         material: Material::DEFAULT,
         transform: Matrix4::IDENTITY,
+        visibility: Visibility::ALL,
+        name: None,
+        tags: Vec::new(),
     };
 
-    pub fn normal_at(&self, world_point: &Tuple) -> Tuple {
+    /// An untransformed object of the given type with the default material.
+    pub fn builder(object_type: ObjectType) -> ObjectBuilder {
+        let object = match object_type {
+            ObjectType::Sphere => Object::SPHERE,
+            ObjectType::Plane => Object::PLANE,
+        };
+        ObjectBuilder { object }
+    }
+
+    /// Whether rays of this kind can hit the object. Shadow catchers are only
+    /// seen by camera and shadow rays.
+    pub(crate) fn is_seen_by(&self, kind: RayKind) -> bool {
+        let catcher = self.material.shadow_catcher && kind == RayKind::Secondary;
+        self.visibility.sees(kind) && !catcher
+    }
+
+    /// Whether `label` is the object's name or one of its tags.
+    pub(crate) fn has_label(&self, label: &str) -> bool {
+        self.name.as_deref() == Some(label) || self.tags.iter().any(|tag| **tag == *label)
+    }
+
+    pub(crate) fn normal_at(&self, world_point: &Tuple) -> Tuple {
         let inv_transform = self.transform.inverse();
 
         let object_point = inv_transform.mul_tuple(&world_point);
@@ -37,7 +96,7 @@ impl Object {
         Tuple::vector(world_normal.x, world_normal.y, world_normal.z).normalize()
     }
 
-    pub fn object_normal(&self, object_point: &Tuple) -> Tuple {
+    pub(crate) fn object_normal(&self, object_point: &Tuple) -> Tuple {
         match self.object_type {
             ObjectType::Sphere => object_point
                 .subtract(&Tuple::point(0.0, 0.0, 0.0))
@@ -46,10 +105,47 @@ impl Object {
         }
     }
 
-    pub fn object_intersect(&self, ray: &crate::ray::Ray) -> crate::ray::Intersections {
+    pub(crate) fn object_intersect(&self, ray: &crate::ray::Ray) -> crate::ray::Intersections {
         match self.object_type {
             ObjectType::Sphere => sphere_object_intersect(self, ray),
             ObjectType::Plane => plane_object_intersect(self, ray),
         }
     }
 }
+
+pub struct ObjectBuilder {
+    object: Object,
+}
+
+impl ObjectBuilder {
+    pub fn material(mut self, material: Material) -> ObjectBuilder {
+        self.object.material = material;
+        self
+    }
+
+    /// See the chainable methods on `Matrix4` for building this in reading
+    /// order.
+    pub fn transform(mut self, transform: Matrix4) -> ObjectBuilder {
+        self.object.transform = transform;
+        self
+    }
+
+    pub fn visibility(mut self, visibility: Visibility) -> ObjectBuilder {
+        self.object.visibility = visibility;
+        self
+    }
+
+    pub fn name(mut self, name: &str) -> ObjectBuilder {
+        self.object.name = Some(name.into());
+        self
+    }
+
+    pub fn tag(mut self, tag: &str) -> ObjectBuilder {
+        self.object.tags.push(tag.into());
+        self
+    }
+
+    pub fn build(self) -> Object {
+        self.object
+    }
+}
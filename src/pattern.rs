@@ -177,7 +177,8 @@ mod tests {
         let s = Object {
             object_type: ObjectType::Sphere,
             material: Material::DEFAULT,
-            transform: scaling(2.0, 2.0, 2.0)
+            transform: scaling(2.0, 2.0, 2.0),
+            ..Object::SPHERE
         };
         assert_abs_diff_eq!(
             p.color_at(&s, &Tuple::point(1.5, 0.0, 0.0)),
@@ -209,7 +210,8 @@ mod tests {
         let s = Object {
             object_type: ObjectType::Sphere,
             material: Material::DEFAULT,
-            transform: scaling(2.0, 2.0, 2.0)
+            transform: scaling(2.0, 2.0, 2.0),
+            ..Object::SPHERE
         };
 
         assert_abs_diff_eq!(
@@ -0,0 +1,55 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use crate::color::{Canvas, Color};
+
+/// Writes the canvas as a colour Portable FloatMap, with little-endian 32-bit
+/// samples and no clamping. PFM stores rows bottom to top. Alpha is dropped,
+/// so transparent pixels come out black.
+pub fn write_pfm<W: Write>(canvas: &Canvas, writer: &mut W) -> io::Result<()> {
+    // A negative scale marks the samples as little-endian
+    write!(writer, "PF\n{} {}\n-1.0\n", canvas.width, canvas.height)?;
+    for y in (0..canvas.height).rev() {
+        for x in 0..canvas.width {
+            let color = canvas.pixel_at(x, y).unwrap_or(&Color::BLACK);
+            for channel in [color.red, color.green, color.blue] {
+                writer.write_all(&(channel as f32).to_le_bytes())?;
+            }
+        }
+    }
+    writer.flush()
+}
+
+/// Writes the canvas to a `.pfm` file at `path`.
+pub fn save_pfm<P: AsRef<Path>>(canvas: &Canvas, path: P) -> io::Result<()> {
+    write_pfm(canvas, &mut BufWriter::new(File::create(path)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::color::{Canvas, Color};
+
+    use super::write_pfm;
+
+    #[test]
+    fn pfm_layout() {
+        let mut canvas = Canvas::new(1, 2);
+        canvas.write_pixel(0, 0, &Color::new(2.5, 0.0, -1.0));
+        canvas.write_pixel(0, 1, &Color::new(0.25, 1.0, 100.0));
+
+        let mut pfm = Vec::new();
+        write_pfm(&canvas, &mut pfm).unwrap();
+
+        let header = b"PF\n1 2\n-1.0\n";
+        assert!(pfm.starts_with(header));
+        let samples: Vec<f32> = pfm[header.len()..]
+            .chunks(4)
+            .map(|bytes| f32::from_le_bytes(bytes.try_into().unwrap()))
+            .collect();
+        // The bottom row comes first
+        assert_eq!(samples, vec![0.25, 1.0, 100.0, 2.5, 0.0, -1.0]);
+    }
+}
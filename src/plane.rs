@@ -48,13 +48,14 @@ mod tests {
 
     #[test]
     fn plane_intersections() {
+        let plane = Object::PLANE;
         let r = Ray::new(Tuple::point(0.0, 1.0, 0.0), Tuple::vector(0.0, -1.0, 0.0));
-        let xs = Object::PLANE.object_intersect(&r);
+        let xs = plane.object_intersect(&r);
         assert_eq!(xs.values.len(), 1);
         assert_abs_diff_eq!(xs.values.first().unwrap().t, 1.0);
 
         let r2 = Ray::new(Tuple::point(0.0, -1.0, 0.0), Tuple::vector(0.0, 1.0, 0.0));
-        let xs2 = Object::PLANE.object_intersect(&r2);
+        let xs2 = plane.object_intersect(&r2);
         assert_eq!(xs2.values.len(), 1);
         assert_abs_diff_eq!(xs2.values.first().unwrap().t, 1.0);
     }
@@ -0,0 +1,744 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Read, Write},
+    path::Path,
+};
+
+use crate::{
+    color::{Canvas, Color},
+    deflate::{zlib_compress, zlib_decompress},
+    image::ImageError,
+    tonemap::OutputTransform,
+};
+
+const FORMAT: &str = "PNG";
+
+pub const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+
+/// Most bytes a deflate stream can expand to per byte: runs of 258 bytes
+/// coded in two bits each.
+const MAX_DEFLATE_RATIO: usize = 1032;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BitDepth {
+    Eight,
+    Sixteen,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ColorType {
+    Rgb,
+    /// Colour with straight alpha, taken from the canvas's alpha channel.
+    Rgba,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PngOptions {
+    pub bit_depth: BitDepth,
+    pub color_type: ColorType,
+    /// Applied to the colour channels, but not alpha, before quantising.
+    pub transform: OutputTransform,
+}
+
+impl PngOptions {
+    pub const DEFAULT: PngOptions = PngOptions {
+        bit_depth: BitDepth::Eight,
+        color_type: ColorType::Rgb,
+        transform: OutputTransform::DEFAULT,
+    };
+
+    fn bytes_per_pixel(&self) -> usize {
+        let channels = match self.color_type {
+            ColorType::Rgb => 3,
+            ColorType::Rgba => 4,
+        };
+        match self.bit_depth {
+            BitDepth::Eight => channels,
+            BitDepth::Sixteen => channels * 2,
+        }
+    }
+}
+
+const CRC_TABLE: [u32; 256] = crc_table();
+
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut n = 0;
+    while n < 256 {
+        let mut c = n as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 != 0 {
+                0xEDB8_8320 ^ (c >> 1)
+            } else {
+                c >> 1
+            };
+            k += 1;
+        }
+        table[n] = c;
+        n += 1;
+    }
+    table
+}
+
+/// CRC-32 as used by PNG chunks, over the concatenation of `parts`.
+pub fn crc32(parts: &[&[u8]]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for part in parts {
+        for byte in *part {
+            crc = CRC_TABLE[((crc ^ u32::from(*byte)) & 0xFF) as usize] ^ (crc >> 8);
+        }
+    }
+    crc ^ 0xFFFF_FFFF
+}
+
+fn write_chunk<W: Write>(writer: &mut W, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    writer.write_all(&(data.len() as u32).to_be_bytes())?;
+    writer.write_all(kind)?;
+    writer.write_all(data)?;
+    writer.write_all(&crc32(&[kind, data]).to_be_bytes())
+}
+
+/// Unfiltered scanline of the canvas in the chosen sample format.
+fn scanline(canvas: &Canvas, y: i32, options: &PngOptions) -> Vec<u8> {
+    let mut line = Vec::with_capacity(canvas.width as usize * options.bytes_per_pixel());
+    for x in 0..canvas.width {
+        let (color, alpha) = match options.color_type {
+            ColorType::Rgb => (*canvas.pixel_at(x, y).unwrap(), None),
+            ColorType::Rgba => (
+                canvas.unpremultiplied_pixel_at(x, y).unwrap(),
+                canvas.alpha_at(x, y),
+            ),
+        };
+        let color = options.transform.apply(&color);
+        let samples = [color.red, color.green, color.blue];
+        for sample in samples.iter().chain(alpha.iter()) {
+            match options.bit_depth {
+                BitDepth::Eight => line.push((sample * 255.0).round().clamp(0.0, 255.0) as u8),
+                BitDepth::Sixteen => {
+                    let value = (sample * 65535.0).round().clamp(0.0, 65535.0) as u16;
+                    line.extend_from_slice(&value.to_be_bytes());
+                }
+            }
+        }
+    }
+    line
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = i16::from(a) + i16::from(b) - i16::from(c);
+    let (pa, pb, pc) = (
+        (p - i16::from(a)).abs(),
+        (p - i16::from(b)).abs(),
+        (p - i16::from(c)).abs(),
+    );
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+/// Applies `filter` to `line`, given the unfiltered line above it.
+fn filter_line(filter: u8, line: &[u8], previous: &[u8], bpp: usize) -> Vec<u8> {
+    (0..line.len())
+        .map(|i| {
+            let a = if i >= bpp { line[i - bpp] } else { 0 };
+            let b = previous[i];
+            let c = if i >= bpp { previous[i - bpp] } else { 0 };
+            let predicted = match filter {
+                0 => 0,
+                1 => a,
+                2 => b,
+                3 => ((u16::from(a) + u16::from(b)) / 2) as u8,
+                _ => paeth(a, b, c),
+            };
+            line[i].wrapping_sub(predicted)
+        })
+        .collect()
+}
+
+/// Filtered image data: each line uses whichever filter gives the smallest
+/// sum of absolute differences, which usually compresses best.
+fn filtered_data(canvas: &Canvas, options: &PngOptions) -> Vec<u8> {
+    let bpp = options.bytes_per_pixel();
+    let mut previous = vec![0u8; canvas.width as usize * bpp];
+    let mut data = Vec::new();
+
+    for y in 0..canvas.height {
+        let line = scanline(canvas, y, options);
+        let (filter, filtered) = (0..5u8)
+            .map(|filter| (filter, filter_line(filter, &line, &previous, bpp)))
+            .min_by_key(|(_, filtered)| {
+                filtered
+                    .iter()
+                    .map(|byte| u64::from((*byte as i8).unsigned_abs()))
+                    .sum::<u64>()
+            })
+            .unwrap();
+        data.push(filter);
+        data.extend(filtered);
+        previous = line;
+    }
+    data
+}
+
+/// Encodes the canvas as a PNG image.
+pub fn write_png<W: Write>(
+    canvas: &Canvas,
+    writer: &mut W,
+    options: &PngOptions,
+) -> io::Result<()> {
+    writer.write_all(&SIGNATURE)?;
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&(canvas.width as u32).to_be_bytes());
+    header.extend_from_slice(&(canvas.height as u32).to_be_bytes());
+    header.push(match options.bit_depth {
+        BitDepth::Eight => 8,
+        BitDepth::Sixteen => 16,
+    });
+    header.push(match options.color_type {
+        ColorType::Rgb => 2,
+        ColorType::Rgba => 6,
+    });
+    header.extend_from_slice(&[0, 0, 0]); // deflate, adaptive filtering, no interlace
+    write_chunk(writer, b"IHDR", &header)?;
+
+    write_chunk(
+        writer,
+        b"IDAT",
+        &zlib_compress(&filtered_data(canvas, options)),
+    )?;
+    write_chunk(writer, b"IEND", &[])
+}
+
+/// Writes the canvas to a PNG file at `path`.
+pub fn save_png<P: AsRef<Path>>(canvas: &Canvas, path: P, options: &PngOptions) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    write_png(canvas, &mut writer, options)?;
+    writer.flush()
+}
+
+/// Image header fields needed to decode the pixel data.
+struct Header {
+    width: u32,
+    height: u32,
+    bit_depth: u8,
+    color_type: u8,
+}
+
+impl Header {
+    fn parse(data: &[u8]) -> Result<Header, ImageError> {
+        if data.len() != 13 {
+            return Err(ImageError::malformed(
+                FORMAT,
+                "IHDR chunk has the wrong length",
+            ));
+        }
+        let header = Header {
+            width: u32::from_be_bytes(data[0..4].try_into().unwrap()),
+            height: u32::from_be_bytes(data[4..8].try_into().unwrap()),
+            bit_depth: data[8],
+            color_type: data[9],
+        };
+
+        if header.width == 0
+            || header.height == 0
+            || header.width > i32::MAX as u32
+            || header.height > i32::MAX as u32
+        {
+            return Err(ImageError::malformed(FORMAT, "bad image size"));
+        }
+        if (header.width as i32)
+            .checked_mul(header.height as i32)
+            .is_none()
+        {
+            return Err(ImageError::malformed(
+                FORMAT,
+                format!("image size {}x{} is too large", header.width, header.height),
+            ));
+        }
+        let depths: &[u8] = match header.color_type {
+            0 => &[1, 2, 4, 8, 16],
+            3 => &[1, 2, 4, 8],
+            2 | 4 | 6 => &[8, 16],
+            _ => return Err(ImageError::malformed(FORMAT, "bad colour type")),
+        };
+        if !depths.contains(&header.bit_depth) {
+            return Err(ImageError::malformed(
+                FORMAT,
+                "bad bit depth for colour type",
+            ));
+        }
+        if data[10] != 0 || data[11] != 0 {
+            return Err(ImageError::malformed(
+                FORMAT,
+                "unknown compression or filter method",
+            ));
+        }
+        match data[12] {
+            0 => Ok(header),
+            1 => Err(ImageError::unsupported(FORMAT, "interlaced images")),
+            _ => Err(ImageError::malformed(FORMAT, "unknown interlace method")),
+        }
+    }
+
+    fn channels(&self) -> usize {
+        match self.color_type {
+            2 => 3,
+            4 => 2,
+            6 => 4,
+            _ => 1,
+        }
+    }
+
+    fn bits_per_pixel(&self) -> usize {
+        self.channels() * usize::from(self.bit_depth)
+    }
+
+    fn stride(&self) -> usize {
+        (self.width as usize * self.bits_per_pixel()).div_ceil(8)
+    }
+
+    /// Length of the decompressed image data: every line with its filter
+    /// byte. `None` if it does not fit in memory.
+    fn data_length(&self) -> Option<usize> {
+        let bits = (self.width as usize).checked_mul(self.bits_per_pixel())?;
+        (bits.div_ceil(8) + 1).checked_mul(self.height as usize)
+    }
+}
+
+/// Reverses the per-line filters, giving the packed samples of each line.
+fn unfilter(data: &[u8], header: &Header) -> Result<Vec<u8>, ImageError> {
+    let stride = header.stride();
+    let bpp = header.bits_per_pixel().div_ceil(8);
+    let height = header.height as usize;
+    if header
+        .data_length()
+        .is_none_or(|length| data.len() < length)
+    {
+        return Err(ImageError::malformed(FORMAT, "image data is truncated"));
+    }
+
+    let mut pixels = vec![0u8; stride * height];
+    for y in 0..height {
+        let filter = data[y * (stride + 1)];
+        let line = &data[y * (stride + 1) + 1..(y + 1) * (stride + 1)];
+        let (done, rest) = pixels.split_at_mut(y * stride);
+        let previous = if y > 0 {
+            &done[(y - 1) * stride..]
+        } else {
+            &[][..]
+        };
+        let current = &mut rest[..stride];
+
+        for i in 0..stride {
+            let a = if i >= bpp { current[i - bpp] } else { 0 };
+            let b = previous.get(i).copied().unwrap_or(0);
+            let c = if i >= bpp {
+                previous.get(i - bpp).copied().unwrap_or(0)
+            } else {
+                0
+            };
+            let predicted = match filter {
+                0 => 0,
+                1 => a,
+                2 => b,
+                3 => ((u16::from(a) + u16::from(b)) / 2) as u8,
+                4 => paeth(a, b, c),
+                _ => return Err(ImageError::malformed(FORMAT, "unknown filter type")),
+            };
+            current[i] = line[i].wrapping_add(predicted);
+        }
+    }
+    Ok(pixels)
+}
+
+/// The `index`th sample of a line, as an integer of the image's bit depth.
+fn sample(line: &[u8], index: usize, bit_depth: u8) -> u16 {
+    match bit_depth {
+        16 => u16::from_be_bytes([line[index * 2], line[index * 2 + 1]]),
+        8 => u16::from(line[index]),
+        _ => {
+            let bits = usize::from(bit_depth);
+            let bit = index * bits;
+            let shift = 8 - bits - bit % 8;
+            u16::from(line[bit / 8] >> shift) & ((1 << bits) - 1)
+        }
+    }
+}
+
+/// Reads a non-interlaced PNG image of any bit depth and colour type. Alpha,
+/// from an alpha channel or a `tRNS` chunk, goes into the canvas's alpha
+/// channel with the colours premultiplied by it.
+pub fn read_png<R: Read>(reader: &mut R) -> Result<Canvas, ImageError> {
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data)?;
+    if !data.starts_with(&SIGNATURE) {
+        return Err(ImageError::malformed(FORMAT, "missing PNG signature"));
+    }
+
+    let mut header: Option<Header> = None;
+    let mut palette: Vec<Color> = Vec::new();
+    let mut transparency: Vec<u8> = Vec::new();
+    let mut compressed = Vec::new();
+    let mut rest = &data[SIGNATURE.len()..];
+    loop {
+        if rest.len() < 12 {
+            return Err(ImageError::malformed(FORMAT, "file is truncated"));
+        }
+        let length = u32::from_be_bytes(rest[..4].try_into().unwrap()) as usize;
+        if length.checked_add(12).is_none_or(|end| rest.len() < end) {
+            return Err(ImageError::malformed(FORMAT, "file is truncated"));
+        }
+        let kind = &rest[4..8];
+        let chunk = &rest[8..8 + length];
+        let crc = u32::from_be_bytes(rest[8 + length..12 + length].try_into().unwrap());
+        let name = String::from_utf8_lossy(kind).to_string();
+        if crc != crc32(&[kind, chunk]) {
+            return Err(ImageError::malformed(
+                FORMAT,
+                format!("bad CRC in {} chunk", name),
+            ));
+        }
+        rest = &rest[12 + length..];
+
+        if header.is_none() && kind != b"IHDR" {
+            return Err(ImageError::malformed(FORMAT, "IHDR is not the first chunk"));
+        }
+        match kind {
+            b"IHDR" => header = Some(Header::parse(chunk)?),
+            b"PLTE" => {
+                if !length.is_multiple_of(3) {
+                    return Err(ImageError::malformed(
+                        FORMAT,
+                        "PLTE chunk has the wrong length",
+                    ));
+                }
+                palette = chunk
+                    .chunks(3)
+                    .map(|rgb| {
+                        Color::new(
+                            f64::from(rgb[0]) / 255.0,
+                            f64::from(rgb[1]) / 255.0,
+                            f64::from(rgb[2]) / 255.0,
+                        )
+                    })
+                    .collect();
+            }
+            b"tRNS" => transparency = chunk.to_vec(),
+            b"IDAT" => compressed.extend_from_slice(chunk),
+            b"IEND" => break,
+            // Ancillary chunks have a lower case first letter and may be skipped
+            _ if kind[0].is_ascii_lowercase() => {}
+            _ => {
+                return Err(ImageError::unsupported(
+                    FORMAT,
+                    format!("critical chunk {}", name),
+                ))
+            }
+        }
+    }
+
+    let header = header.ok_or_else(|| ImageError::malformed(FORMAT, "missing IHDR chunk"))?;
+    if header.color_type == 3 && palette.is_empty() {
+        return Err(ImageError::malformed(FORMAT, "missing PLTE chunk"));
+    }
+    // Checked before inflating, which could otherwise run out of memory
+    let inflated_limit = compressed.len().saturating_mul(MAX_DEFLATE_RATIO);
+    if header
+        .data_length()
+        .is_none_or(|length| length > inflated_limit)
+    {
+        return Err(ImageError::malformed(FORMAT, "image data is truncated"));
+    }
+    let raw =
+        zlib_decompress(&compressed).map_err(|message| ImageError::malformed(FORMAT, message))?;
+    let pixels = unfilter(&raw, &header)?;
+
+    let max = f64::from((1u32 << header.bit_depth) - 1);
+    let key = |index: usize| {
+        transparency
+            .get(index * 2..index * 2 + 2)
+            .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+    };
+    let (width, height) = (header.width as i32, header.height as i32);
+    let mut canvas = Canvas::new(width, height);
+    for (y, line) in pixels.chunks(header.stride()).enumerate() {
+        for x in 0..header.width as usize {
+            let s =
+                |channel: usize| sample(line, x * header.channels() + channel, header.bit_depth);
+            let (color, alpha) = match header.color_type {
+                0 => {
+                    let gray = f64::from(s(0)) / max;
+                    let alpha = if key(0) == Some(s(0)) { 0.0 } else { 1.0 };
+                    (Color::new(gray, gray, gray), alpha)
+                }
+                2 => {
+                    let rgb = (s(0), s(1), s(2));
+                    let alpha =
+                        if (key(0), key(1), key(2)) == (Some(rgb.0), Some(rgb.1), Some(rgb.2)) {
+                            0.0
+                        } else {
+                            1.0
+                        };
+                    let color = Color::new(
+                        f64::from(rgb.0) / max,
+                        f64::from(rgb.1) / max,
+                        f64::from(rgb.2) / max,
+                    );
+                    (color, alpha)
+                }
+                3 => {
+                    let index = usize::from(s(0));
+                    let color = *palette.get(index).ok_or_else(|| {
+                        ImageError::malformed(FORMAT, "palette index out of range")
+                    })?;
+                    let alpha = transparency
+                        .get(index)
+                        .map_or(1.0, |a| f64::from(*a) / 255.0);
+                    (color, alpha)
+                }
+                4 => {
+                    let gray = f64::from(s(0)) / max;
+                    (Color::new(gray, gray, gray), f64::from(s(1)) / max)
+                }
+                _ => (
+                    Color::new(
+                        f64::from(s(0)) / max,
+                        f64::from(s(1)) / max,
+                        f64::from(s(2)) / max,
+                    ),
+                    f64::from(s(3)) / max,
+                ),
+            };
+            canvas.write_pixel(x as i32, y as i32, &color.scalar_mul(alpha));
+            canvas.write_alpha(x as i32, y as i32, alpha);
+        }
+    }
+    Ok(canvas)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        color::{Canvas, Color},
+        deflate::zlib_compress,
+        image::ImageError,
+        tonemap::{Gamma, OutputTransform},
+    };
+
+    use super::{crc32, read_png, write_png, BitDepth, ColorType, PngOptions, SIGNATURE};
+
+    #[test]
+    fn crc32_known_value() {
+        assert_eq!(crc32(&[b"IEND"]), 0xAE42_6082);
+        assert_eq!(crc32(&[b"IE", b"ND"]), 0xAE42_6082);
+    }
+
+    /// Splits a PNG file into its chunks, checking each CRC.
+    fn chunks(png: &[u8]) -> Vec<(String, Vec<u8>)> {
+        assert_eq!(png[..8], SIGNATURE);
+        let mut chunks = Vec::new();
+        let mut rest = &png[8..];
+        while !rest.is_empty() {
+            let length = u32::from_be_bytes(rest[..4].try_into().unwrap()) as usize;
+            let kind = &rest[4..8];
+            let data = &rest[8..8 + length];
+            let crc = u32::from_be_bytes(rest[8 + length..12 + length].try_into().unwrap());
+            assert_eq!(crc, crc32(&[kind, data]));
+            chunks.push((String::from_utf8(kind.to_vec()).unwrap(), data.to_vec()));
+            rest = &rest[12 + length..];
+        }
+        chunks
+    }
+
+    #[test]
+    fn png_chunk_layout() {
+        let mut canvas = Canvas::new(3, 2);
+        canvas.write_pixel(1, 1, &Color::new(1.0, 0.5, 0.0));
+
+        let mut png = Vec::new();
+        write_png(&canvas, &mut png, &PngOptions::DEFAULT).unwrap();
+        let chunks = chunks(&png);
+
+        let names: Vec<&str> = chunks.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["IHDR", "IDAT", "IEND"]);
+        assert_eq!(chunks[0].1, [0, 0, 0, 3, 0, 0, 0, 2, 8, 2, 0, 0, 0]);
+        assert!(chunks[2].1.is_empty());
+    }
+
+    #[test]
+    fn png_header_for_16_bit_rgba() {
+        let canvas = Canvas::new(1, 1);
+        let options = PngOptions {
+            bit_depth: BitDepth::Sixteen,
+            color_type: ColorType::Rgba,
+            ..PngOptions::DEFAULT
+        };
+
+        let mut png = Vec::new();
+        write_png(&canvas, &mut png, &options).unwrap();
+        assert_eq!(chunks(&png)[0].1, [0, 0, 0, 1, 0, 0, 0, 1, 16, 6, 0, 0, 0]);
+    }
+
+    #[test]
+    fn png_applies_output_transform() {
+        let mut canvas = Canvas::new(1, 1);
+        canvas.write_pixel(0, 0, &Color::new(0.5, 0.5, 0.5));
+        canvas.write_alpha(0, 0, 0.5);
+        let options = PngOptions {
+            color_type: ColorType::Rgba,
+            transform: OutputTransform {
+                gamma: Gamma::power(2.0).unwrap(),
+                ..OutputTransform::DEFAULT
+            },
+            ..PngOptions::DEFAULT
+        };
+
+        let mut png = Vec::new();
+        write_png(&canvas, &mut png, &options).unwrap();
+        let decoded = read_png(&mut png.as_slice()).unwrap();
+        // The straight colour is encoded but alpha is left alone
+        assert_abs_diff_eq!(decoded.alpha_at(0, 0).unwrap(), 128.0 / 255.0);
+        assert_abs_diff_eq!(
+            decoded.unpremultiplied_pixel_at(0, 0).unwrap(),
+            Color::WHITE
+        );
+    }
+
+    #[test]
+    fn png_round_trip() {
+        let mut canvas = Canvas::new(5, 3);
+        for y in 0..3 {
+            for x in 0..5 {
+                let color = Color::new(f64::from(x) / 4.0, f64::from(y) / 2.0, 0.5);
+                let alpha = f64::from(x + y) / 6.0;
+                canvas.write_pixel(x, y, &color.scalar_mul(alpha));
+                canvas.write_alpha(x, y, alpha);
+            }
+        }
+
+        for bit_depth in [BitDepth::Eight, BitDepth::Sixteen] {
+            for color_type in [ColorType::Rgb, ColorType::Rgba] {
+                let options = PngOptions {
+                    bit_depth,
+                    color_type,
+                    ..PngOptions::DEFAULT
+                };
+                let mut png = Vec::new();
+                write_png(&canvas, &mut png, &options).unwrap();
+                let decoded = read_png(&mut png.as_slice()).unwrap();
+
+                let epsilon = if bit_depth == BitDepth::Eight {
+                    0.01
+                } else {
+                    1e-4
+                };
+                for y in 0..3 {
+                    for x in 0..5 {
+                        let alpha = canvas.alpha_at(x, y).unwrap();
+                        if color_type == ColorType::Rgba {
+                            assert_abs_diff_eq!(
+                                decoded.alpha_at(x, y).unwrap(),
+                                alpha,
+                                epsilon = epsilon
+                            );
+                            assert_abs_diff_eq!(
+                                *decoded.pixel_at(x, y).unwrap(),
+                                *canvas.pixel_at(x, y).unwrap(),
+                                epsilon = epsilon
+                            );
+                        } else {
+                            assert_abs_diff_eq!(decoded.alpha_at(x, y).unwrap(), 1.0);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Builds a PNG file from an IHDR and the unfiltered lines of an image.
+    fn encode(header: &[u8], extra: &[(&[u8; 4], &[u8])], lines: &[&[u8]]) -> Vec<u8> {
+        fn chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+            png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+            png.extend_from_slice(kind);
+            png.extend_from_slice(data);
+            png.extend_from_slice(&crc32(&[kind, data]).to_be_bytes());
+        }
+
+        let mut png = SIGNATURE.to_vec();
+        chunk(&mut png, b"IHDR", header);
+        for (kind, data) in extra {
+            chunk(&mut png, kind, data);
+        }
+        let mut raw = Vec::new();
+        for line in lines {
+            raw.push(0);
+            raw.extend_from_slice(line);
+        }
+        chunk(&mut png, b"IDAT", &zlib_compress(&raw));
+        chunk(&mut png, b"IEND", &[]);
+        png
+    }
+
+    #[test]
+    fn read_palette_png() {
+        // 2-bit indices into a three colour palette, the last one transparent
+        let header = [0, 0, 0, 3, 0, 0, 0, 1, 2, 3, 0, 0, 0];
+        let palette: &[u8] = &[255, 0, 0, 0, 255, 0, 0, 0, 255];
+        let alpha: &[u8] = &[255, 255, 0];
+        let png = encode(
+            &header,
+            &[(b"PLTE", palette), (b"tRNS", alpha)],
+            &[&[0b0001_1000]],
+        );
+
+        let canvas = read_png(&mut png.as_slice()).unwrap();
+        assert_abs_diff_eq!(*canvas.pixel_at(0, 0).unwrap(), Color::new(1.0, 0.0, 0.0));
+        assert_abs_diff_eq!(*canvas.pixel_at(1, 0).unwrap(), Color::new(0.0, 1.0, 0.0));
+        assert_abs_diff_eq!(*canvas.pixel_at(2, 0).unwrap(), Color::BLACK);
+        assert_abs_diff_eq!(canvas.alpha_at(2, 0).unwrap(), 0.0);
+    }
+
+    #[test]
+    fn read_gray_alpha_png() {
+        let header = [0, 0, 0, 2, 0, 0, 0, 1, 8, 4, 0, 0, 0];
+        let png = encode(&header, &[], &[&[255, 128, 51, 255]]);
+
+        let canvas = read_png(&mut png.as_slice()).unwrap();
+        let half = 128.0 / 255.0;
+        assert_abs_diff_eq!(
+            *canvas.pixel_at(0, 0).unwrap(),
+            Color::new(half, half, half)
+        );
+        assert_abs_diff_eq!(canvas.alpha_at(0, 0).unwrap(), half);
+        assert_abs_diff_eq!(*canvas.pixel_at(1, 0).unwrap(), Color::new(0.2, 0.2, 0.2));
+    }
+
+    #[test]
+    fn read_png_errors() {
+        let mut png = Vec::new();
+        write_png(&Canvas::new(2, 2), &mut png, &PngOptions::DEFAULT).unwrap();
+
+        let mut bad_crc = png.clone();
+        bad_crc[20] ^= 1;
+        assert!(matches!(
+            read_png(&mut bad_crc.as_slice()),
+            Err(ImageError::Malformed { .. })
+        ));
+        assert!(matches!(
+            read_png(&mut &png[..png.len() - 8]),
+            Err(ImageError::Malformed { .. })
+        ));
+
+        let interlaced = encode(&[0, 0, 0, 1, 0, 0, 0, 1, 8, 0, 0, 0, 1], &[], &[&[0]]);
+        assert!(matches!(
+            read_png(&mut interlaced.as_slice()),
+            Err(ImageError::Unsupported { .. })
+        ));
+    }
+}
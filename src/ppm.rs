@@ -0,0 +1,377 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Read, Write},
+    path::Path,
+};
+
+use crate::{
+    color::{Canvas, Color},
+    image::ImageError,
+    tonemap::OutputTransform,
+};
+
+const FORMAT: &str = "PPM";
+
+/// Longest line allowed in a plain PPM file.
+const MAX_LINE_LENGTH: usize = 70;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PpmFormat {
+    /// Plain `P3`, with decimal samples.
+    Ascii,
+    /// Raw `P6`, with one byte per sample.
+    Binary,
+}
+
+/// Writes a PPM image pixel by pixel, so the whole file never has to be held
+/// in memory. Rows must be ended with `end_row`.
+pub struct PpmWriter<W: Write> {
+    writer: W,
+    format: PpmFormat,
+    transform: OutputTransform,
+    line_length: usize,
+}
+
+impl<W: Write> PpmWriter<W> {
+    /// Starts an image by writing its header.
+    pub fn new(
+        mut writer: W,
+        width: i32,
+        height: i32,
+        format: PpmFormat,
+    ) -> io::Result<PpmWriter<W>> {
+        let magic = match format {
+            PpmFormat::Ascii => "P3",
+            PpmFormat::Binary => "P6",
+        };
+        write!(writer, "{}\n{} {}\n255\n", magic, width, height)?;
+        Ok(PpmWriter {
+            writer,
+            format,
+            transform: OutputTransform::DEFAULT,
+            line_length: 0,
+        })
+    }
+
+    /// Converts pixels with `transform` as they are written.
+    pub fn with_transform(self, transform: OutputTransform) -> PpmWriter<W> {
+        PpmWriter { transform, ..self }
+    }
+
+    pub fn write_pixel(&mut self, color: &Color) -> io::Result<()> {
+        fn component(f: f64) -> u8 {
+            (f * 255.0).round().clamp(0.0, 255.0) as u8
+        }
+
+        let color = self.transform.apply(color);
+        let components = [
+            component(color.red),
+            component(color.green),
+            component(color.blue),
+        ];
+        match self.format {
+            PpmFormat::Binary => self.writer.write_all(&components),
+            PpmFormat::Ascii => {
+                for component in components {
+                    self.write_ascii_component(component)?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn write_ascii_component(&mut self, component: u8) -> io::Result<()> {
+        let text = component.to_string();
+        if self.line_length == 0 {
+            self.line_length = text.len();
+            write!(self.writer, "{}", text)
+        } else if self.line_length + 1 + text.len() > MAX_LINE_LENGTH {
+            self.line_length = text.len();
+            write!(self.writer, "\n{}", text)
+        } else {
+            self.line_length += 1 + text.len();
+            write!(self.writer, " {}", text)
+        }
+    }
+
+    /// Plain PPM starts every row on a new line.
+    pub fn end_row(&mut self) -> io::Result<()> {
+        if self.format == PpmFormat::Ascii && self.line_length > 0 {
+            self.line_length = 0;
+            self.writer.write_all(b"\n")?;
+        }
+        Ok(())
+    }
+
+    /// Flushes the image and hands back the underlying writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.end_row()?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// Writes the canvas as a PPM image. Each sample is written separately, so
+/// unbuffered writers should be wrapped in a `BufWriter`.
+pub fn write_ppm<W: Write>(canvas: &Canvas, writer: W, format: PpmFormat) -> io::Result<W> {
+    write_ppm_with_transform(canvas, writer, format, OutputTransform::DEFAULT)
+}
+
+/// Writes the canvas as a PPM image, converting its colours with `transform`.
+pub fn write_ppm_with_transform<W: Write>(
+    canvas: &Canvas,
+    writer: W,
+    format: PpmFormat,
+    transform: OutputTransform,
+) -> io::Result<W> {
+    let mut ppm =
+        PpmWriter::new(writer, canvas.width, canvas.height, format)?.with_transform(transform);
+    for y in 0..canvas.height {
+        for x in 0..canvas.width {
+            if let Some(p) = canvas.pixel_at(x, y) {
+                ppm.write_pixel(p)?;
+            }
+        }
+        ppm.end_row()?;
+    }
+    ppm.finish()
+}
+
+/// Writes the canvas to a PPM file at `path`.
+pub fn save_ppm<P: AsRef<Path>>(canvas: &Canvas, path: P, format: PpmFormat) -> io::Result<()> {
+    write_ppm(canvas, BufWriter::new(File::create(path)?), format)?;
+    Ok(())
+}
+
+/// Tokenizer for the whitespace separated fields of a PPM file.
+struct PpmParser<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl PpmParser<'_> {
+    /// Skips whitespace and `#` comments, which run to the end of the line.
+    fn skip_separators(&mut self) {
+        while let Some(byte) = self.data.get(self.position) {
+            if *byte == b'#' {
+                while !matches!(self.data.get(self.position), None | Some(b'\n' | b'\r')) {
+                    self.position += 1;
+                }
+            } else if byte.is_ascii_whitespace() {
+                self.position += 1;
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn number(&mut self, what: &str) -> Result<u32, ImageError> {
+        self.skip_separators();
+        let start = self.position;
+        let mut value = 0u32;
+        while let Some(byte) = self.data.get(self.position).filter(|b| b.is_ascii_digit()) {
+            value = value
+                .checked_mul(10)
+                .and_then(|v| v.checked_add(u32::from(byte - b'0')))
+                .ok_or_else(|| ImageError::malformed(FORMAT, format!("{} is too large", what)))?;
+            self.position += 1;
+        }
+        if self.position == start {
+            return Err(ImageError::malformed(
+                FORMAT,
+                format!("expected {} at byte {}", what, start),
+            ));
+        }
+        Ok(value)
+    }
+}
+
+/// Reads a plain (`P3`) or raw (`P6`) PPM image with any maxval up to 65535.
+/// Samples are scaled by maxval to the range 0.0 to 1.0.
+pub fn read_ppm<R: Read>(reader: &mut R) -> Result<Canvas, ImageError> {
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data)?;
+
+    let binary = match data.get(..2) {
+        Some(b"P3") => false,
+        Some(b"P6") => true,
+        _ => return Err(ImageError::malformed(FORMAT, "missing P3 or P6 signature")),
+    };
+    let mut parser = PpmParser {
+        data: &data,
+        position: 2,
+    };
+
+    let width = parser.number("width")?;
+    let height = parser.number("height")?;
+    let maxval = parser.number("maxval")?;
+    if width == 0 || height == 0 || width > i32::MAX as u32 || height > i32::MAX as u32 {
+        return Err(ImageError::malformed(FORMAT, "bad image size"));
+    }
+    if maxval == 0 || maxval > 65535 {
+        return Err(ImageError::malformed(FORMAT, "maxval must be 1 to 65535"));
+    }
+
+    let (width, height) = (width as i32, height as i32);
+    let count = width
+        .checked_mul(height)
+        .and_then(|pixels| usize::try_from(pixels).ok()?.checked_mul(3))
+        .ok_or_else(|| {
+            ImageError::malformed(
+                FORMAT,
+                format!("image size {}x{} is too large", width, height),
+            )
+        })?;
+    let truncated = || ImageError::malformed(FORMAT, "pixel data is truncated");
+    let samples: Vec<u32> = if binary {
+        // A single whitespace byte separates the header from the raster
+        if !data
+            .get(parser.position)
+            .is_some_and(u8::is_ascii_whitespace)
+        {
+            return Err(ImageError::malformed(
+                FORMAT,
+                "missing whitespace after maxval",
+            ));
+        }
+        let start = parser.position + 1;
+        let bytes_per_sample = if maxval > 255 { 2 } else { 1 };
+        let end = count
+            .checked_mul(bytes_per_sample)
+            .and_then(|length| start.checked_add(length))
+            .ok_or_else(truncated)?;
+        let raster = data.get(start..end).ok_or_else(truncated)?;
+        if bytes_per_sample == 2 {
+            raster
+                .chunks(2)
+                .map(|pair| u32::from(u16::from_be_bytes([pair[0], pair[1]])))
+                .collect()
+        } else {
+            raster.iter().map(|byte| u32::from(*byte)).collect()
+        }
+    } else {
+        // Every sample takes at least a digit and a separator
+        if count > (data.len() - parser.position) / 2 + 1 {
+            return Err(truncated());
+        }
+        (0..count)
+            .map(|_| parser.number("sample"))
+            .collect::<Result<_, _>>()?
+    };
+    if samples.iter().any(|sample| *sample > maxval) {
+        return Err(ImageError::malformed(FORMAT, "sample larger than maxval"));
+    }
+
+    let scale = 1.0 / f64::from(maxval);
+    let mut canvas = Canvas::new(width, height);
+    for (i, rgb) in samples.chunks(3).enumerate() {
+        let color = Color::new(
+            f64::from(rgb[0]) * scale,
+            f64::from(rgb[1]) * scale,
+            f64::from(rgb[2]) * scale,
+        );
+        canvas.write_pixel(i as i32 % width, i as i32 / width, &color);
+    }
+    Ok(canvas)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        color::{Canvas, Color},
+        image::ImageError,
+        tonemap::{Gamma, OutputTransform, ToneMap},
+    };
+
+    use super::{read_ppm, write_ppm, write_ppm_with_transform, PpmFormat, PpmWriter};
+
+    #[test]
+    fn binary_ppm() {
+        let mut c = Canvas::new(2, 1);
+        c.write_pixel(0, 0, &Color::new(1.5, 0.5, 0.0));
+        c.write_pixel(1, 0, &Color::new(-0.5, 0.0, 1.0));
+
+        let ppm = write_ppm(&c, Vec::new(), PpmFormat::Binary).unwrap();
+        let mut expected = b"P6\n2 1\n255\n".to_vec();
+        expected.extend_from_slice(&[255, 128, 0, 0, 0, 255]);
+        assert_eq!(ppm, expected);
+    }
+
+    #[test]
+    fn streamed_rows() {
+        let mut ppm = PpmWriter::new(Vec::new(), 1, 2, PpmFormat::Ascii).unwrap();
+        ppm.write_pixel(&Color::WHITE).unwrap();
+        ppm.end_row().unwrap();
+        ppm.write_pixel(&Color::BLACK).unwrap();
+        let ppm = ppm.finish().unwrap();
+        assert_eq!(
+            String::from_utf8(ppm).unwrap(),
+            "P3\n1 2\n255\n255 255 255\n0 0 0\n"
+        );
+    }
+
+    #[test]
+    fn ppm_with_output_transform() {
+        let mut c = Canvas::new(2, 1);
+        c.write_pixel(0, 0, &Color::new(1.0, 3.0, 0.0));
+        c.write_pixel(1, 0, &Color::new(0.25, 0.0, 0.0));
+        let transform = OutputTransform {
+            tone_map: ToneMap::Reinhard,
+            gamma: Gamma::Srgb,
+            ..OutputTransform::DEFAULT
+        };
+
+        let ppm = write_ppm_with_transform(&c, Vec::new(), PpmFormat::Ascii, transform).unwrap();
+        // Reinhard maps 1.0 to 0.5, 3.0 to 0.75 and 0.25 to 0.2, before sRGB
+        assert_eq!(
+            String::from_utf8(ppm).unwrap(),
+            "P3\n2 1\n255\n188 225 0 124 0 0\n"
+        );
+    }
+
+    #[test]
+    fn read_plain_ppm_with_comments() {
+        let data = b"P3\n# a comment\n2 1 # trailing\n15\n15 0 0\n# between samples\n0 15 5\n";
+        let canvas = read_ppm(&mut data.as_slice()).unwrap();
+        assert_eq!((canvas.width, canvas.height), (2, 1));
+        assert_abs_diff_eq!(*canvas.pixel_at(0, 0).unwrap(), Color::new(1.0, 0.0, 0.0));
+        assert_abs_diff_eq!(
+            *canvas.pixel_at(1, 0).unwrap(),
+            Color::new(0.0, 1.0, 1.0 / 3.0)
+        );
+    }
+
+    #[test]
+    fn read_binary_ppm() {
+        let mut c = Canvas::new(2, 2);
+        c.write_pixel(1, 1, &Color::new(1.0, 0.2, 0.0));
+        let ppm = write_ppm(&c, Vec::new(), PpmFormat::Binary).unwrap();
+
+        let canvas = read_ppm(&mut ppm.as_slice()).unwrap();
+        assert_abs_diff_eq!(*canvas.pixel_at(1, 1).unwrap(), Color::new(1.0, 0.2, 0.0));
+        assert_abs_diff_eq!(*canvas.pixel_at(0, 1).unwrap(), Color::BLACK);
+    }
+
+    #[test]
+    fn read_16_bit_binary_ppm() {
+        let mut data = b"P6 1 1 65535\n".to_vec();
+        data.extend_from_slice(&[0xFF, 0xFF, 0x80, 0x00, 0x00, 0x00]);
+        let canvas = read_ppm(&mut data.as_slice()).unwrap();
+        assert_abs_diff_eq!(
+            *canvas.pixel_at(0, 0).unwrap(),
+            Color::new(1.0, 32768.0 / 65535.0, 0.0)
+        );
+    }
+
+    #[test]
+    fn read_ppm_errors() {
+        let malformed =
+            |data: &[u8]| matches!(read_ppm(&mut &data[..]), Err(ImageError::Malformed { .. }));
+        assert!(malformed(b"P5\n1 1\n255\n0"));
+        assert!(malformed(b"P3\n1 x\n255\n0 0 0"));
+        assert!(malformed(b"P3\n1 1\n255\n0 0"));
+        assert!(malformed(b"P3\n1 1\n255\n0 0 256"));
+        assert!(malformed(b"P3\n1 1\n0\n0 0 0"));
+        assert!(malformed(b"P6\n2 1\n255\n\x01\x02\x03"));
+    }
+}
@@ -5,16 +5,47 @@ use crate::{
 };
 use approx::abs_diff_eq;
 
+/// What a ray is used for, which decides the objects it can see.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RayKind {
+    /// Primary rays leaving the camera.
+    Camera,
+    /// Rays towards a light, testing whether a point is in shadow.
+    Shadow,
+    /// Reflected and refracted rays, and bounces of the path tracer.
+    Secondary,
+}
+
 pub struct Ray {
     pub origin: Tuple,
     pub direction: Tuple,
+    pub kind: RayKind,
 }
 
 impl Ray {
+    /// A camera ray. Other rays are made with `Ray::shadow` and
+    /// `Ray::secondary`, so objects hidden from them are skipped.
     pub fn new(origin: Tuple, direction: Tuple) -> Ray {
         Ray {
             origin: origin,
             direction: direction,
+            kind: RayKind::Camera,
+        }
+    }
+
+    pub fn shadow(origin: Tuple, direction: Tuple) -> Ray {
+        Ray::new(origin, direction).with_kind(RayKind::Shadow)
+    }
+
+    pub fn secondary(origin: Tuple, direction: Tuple) -> Ray {
+        Ray::new(origin, direction).with_kind(RayKind::Secondary)
+    }
+
+    pub fn with_kind(&self, kind: RayKind) -> Ray {
+        Ray {
+            origin: self.origin,
+            direction: self.direction,
+            kind,
         }
     }
 
@@ -26,6 +57,7 @@ impl Ray {
         Ray {
             origin: m.mul_tuple(&self.origin),
             direction: m.mul_tuple(&self.direction),
+            kind: self.kind,
         }
     }
 }
@@ -79,7 +111,7 @@ mod tests {
         object::{Object, ObjectType},
     };
 
-    use super::{Intersection, Intersections, Ray};
+    use super::{Intersection, Intersections, Ray, RayKind};
 
     #[test]
     fn ray_point_distance() {
@@ -94,7 +126,8 @@ mod tests {
     #[test]
     fn sphere_intersect_two_points() {
         let ray = Ray::new(Tuple::point(0.0, 0.0, -5.0), Tuple::vector(0.0, 0.0, 1.0));
-        let xs = Intersections::intersect(&Object::SPHERE, &ray);
+        let sphere = Object::SPHERE;
+        let xs = Intersections::intersect(&sphere, &ray);
         assert_eq!(xs.values.len(), 2);
         assert_abs_diff_eq!(xs.values[0].t, 4.0);
         assert_abs_diff_eq!(xs.values[1].t, 6.0);
@@ -103,7 +136,8 @@ mod tests {
     #[test]
     fn sphere_intersect_one_point() {
         let ray = Ray::new(Tuple::point(0.0, 1.0, -5.0), Tuple::vector(0.0, 0.0, 1.0));
-        let xs = Intersections::intersect(&Object::SPHERE, &ray);
+        let sphere = Object::SPHERE;
+        let xs = Intersections::intersect(&sphere, &ray);
         assert_eq!(xs.values.len(), 2);
         assert_abs_diff_eq!(xs.values[0].t, 5.0);
         assert_abs_diff_eq!(xs.values[1].t, 5.0);
@@ -112,14 +146,16 @@ mod tests {
     #[test]
     fn sphere_intersect_miss() {
         let ray = Ray::new(Tuple::point(0.0, 2.0, -5.0), Tuple::vector(0.0, 0.0, 1.0));
-        let xs = Intersections::intersect(&Object::SPHERE, &ray);
+        let sphere = Object::SPHERE;
+        let xs = Intersections::intersect(&sphere, &ray);
         assert_eq!(xs.values.len(), 0);
     }
 
     #[test]
     fn sphere_intersect_inside() {
         let ray = Ray::new(Tuple::point(0.0, 0.0, 0.0), Tuple::vector(0.0, 0.0, 1.0));
-        let xs = Intersections::intersect(&Object::SPHERE, &ray);
+        let sphere = Object::SPHERE;
+        let xs = Intersections::intersect(&sphere, &ray);
         assert_eq!(xs.values.len(), 2);
         assert_abs_diff_eq!(xs.values[0].t, -1.0);
         assert_abs_diff_eq!(xs.values[1].t, 1.0);
@@ -128,7 +164,8 @@ mod tests {
     #[test]
     fn sphere_intersect_behind() {
         let ray = Ray::new(Tuple::point(0.0, 0.0, 5.0), Tuple::vector(0.0, 0.0, 1.0));
-        let xs = Intersections::intersect(&Object::SPHERE, &ray);
+        let sphere = Object::SPHERE;
+        let xs = Intersections::intersect(&sphere, &ray);
         assert_eq!(xs.values.len(), 2);
         assert_abs_diff_eq!(xs.values[0].t, -6.0);
         assert_abs_diff_eq!(xs.values[1].t, -4.0);
@@ -220,6 +257,23 @@ mod tests {
         assert_abs_diff_eq!(ray3.direction, Tuple::vector(0.0, 3.0, 0.0));
     }
 
+    #[test]
+    fn ray_transform_keeps_kind() {
+        let ray = Ray::new(Tuple::point(1.0, 2.0, 3.0), Tuple::vector(0.0, 1.0, 0.0));
+        assert_eq!(ray.kind, RayKind::Camera);
+
+        let shadow = Ray::shadow(ray.origin, ray.direction);
+        assert_eq!(shadow.kind, RayKind::Shadow);
+        assert_eq!(
+            shadow.transform(&transform::scaling(2.0, 2.0, 2.0)).kind,
+            RayKind::Shadow
+        );
+        assert_eq!(
+            Ray::secondary(ray.origin, ray.direction).kind,
+            RayKind::Secondary
+        );
+    }
+
     #[test]
     fn ray_sphere_scaled() {
         let r = Ray::new(Tuple::point(0.0, 0.0, -5.0), Tuple::vector(0.0, 0.0, 1.0));
@@ -227,6 +281,7 @@ mod tests {
             object_type: ObjectType::Sphere,
             transform: transform::scaling(2.0, 2.0, 2.0),
             material: Material::DEFAULT,
+            ..Object::SPHERE
         };
         let xs = Intersections::intersect(&s, &r);
         assert_eq!(xs.values.len(), 2);
@@ -241,6 +296,7 @@ mod tests {
             object_type: ObjectType::Sphere,
             transform: transform::translation(5.0, 0.0, 0.0),
             material: Material::DEFAULT,
+            ..Object::SPHERE
         };
         let xs = Intersections::intersect(&s, &r);
         assert_eq!(xs.values.len(), 0);
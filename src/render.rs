@@ -0,0 +1,1341 @@
+//! Driving a camera over the image: the image is split into tiles that
+//! worker threads take in turn, adding samples to a floating-point
+//! accumulator and reporting progress, handing out snapshots and checking
+//! for cancellation between tiles.
+
+use std::{
+    collections::VecDeque,
+    iter,
+    ops::Range,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex, MutexGuard, TryLockError,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    checkpoint::{Checkpoint, CheckpointError},
+    color::{Canvas, Color},
+    world::{Camera, World},
+};
+
+/// A rectangle of pixels rendered as one unit of work.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct Tile {
+    pub(crate) x: i32,
+    pub(crate) y: i32,
+    pub(crate) width: i32,
+    pub(crate) height: i32,
+}
+
+impl Tile {
+    pub(crate) const SIZE: i32 = 32;
+
+    /// The pixels of the tile, row by row.
+    pub(crate) fn pixels(&self) -> impl Iterator<Item = (i32, i32)> + '_ {
+        (self.y..self.y + self.height)
+            .flat_map(move |y| (self.x..self.x + self.width).map(move |x| (x, y)))
+    }
+}
+
+/// A rectangle of the image to render, leaving out the rest. Pixels keep
+/// their position in the full image, so they come out exactly as in a full
+/// render.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Region {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl Region {
+    /// The whole of a `width` by `height` image.
+    pub fn full(width: i32, height: i32) -> Region {
+        Region {
+            x: 0,
+            y: 0,
+            width,
+            height,
+        }
+    }
+
+    /// The part of the region inside a `width` by `height` image, which may
+    /// be empty.
+    pub fn clip(&self, width: i32, height: i32) -> Region {
+        let left = self.x.clamp(0, width);
+        let top = self.y.clamp(0, height);
+        let right = self.x.saturating_add(self.width).clamp(left, width);
+        let bottom = self.y.saturating_add(self.height).clamp(top, height);
+        Region {
+            x: left,
+            y: top,
+            width: right - left,
+            height: bottom - top,
+        }
+    }
+
+    /// The pixels of `canvas` in the region, as a canvas of the region's size.
+    pub fn crop(&self, canvas: &Canvas) -> Canvas {
+        let mut cropped = Canvas::new(self.width, self.height);
+        for (x, y) in self.tiles().iter().flat_map(Tile::pixels) {
+            let (color, alpha) = (canvas.pixel_at(x, y), canvas.alpha_at(x, y));
+            cropped.write_pixel(x - self.x, y - self.y, color.unwrap_or(&Color::BLACK));
+            cropped.write_alpha(x - self.x, y - self.y, alpha.unwrap_or(1.0));
+        }
+        cropped
+    }
+
+    /// Splits the region into tiles of at most `Tile::SIZE` pixels a side,
+    /// from the top left along each row of tiles.
+    pub(crate) fn tiles(&self) -> Vec<Tile> {
+        let (right, bottom) = (self.x + self.width, self.y + self.height);
+        let mut tiles = Vec::new();
+        for y in (self.y..bottom).step_by(Tile::SIZE as usize) {
+            for x in (self.x..right).step_by(Tile::SIZE as usize) {
+                tiles.push(Tile {
+                    x,
+                    y,
+                    width: Tile::SIZE.min(right - x),
+                    height: Tile::SIZE.min(bottom - y),
+                });
+            }
+        }
+        tiles
+    }
+}
+
+/// How far a render has got, passed to the progress callback after every
+/// tile. A progressive render goes over every tile once per pass, and each
+/// of those counts as a tile completed, as do tiles already done in a
+/// resumed checkpoint.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Progress {
+    pub tiles_completed: usize,
+    pub tiles_total: usize,
+    /// Camera samples traced so far over all pixels, and how many will be,
+    /// not counting those resumed from a checkpoint.
+    pub samples_completed: u64,
+    pub samples_total: u64,
+    pub elapsed: Duration,
+    /// The time budget of the render, if it has one.
+    pub time_limit: Option<Duration>,
+}
+
+impl Progress {
+    /// Fraction of the samples traced, or of the time budget used if that is
+    /// further along, from 0 to 1.
+    pub fn fraction(&self) -> f64 {
+        let samples = if self.samples_total == 0 {
+            1.0
+        } else {
+            self.samples_completed as f64 / self.samples_total as f64
+        };
+        match self.time_limit {
+            Some(limit) if !limit.is_zero() => samples
+                .max(self.elapsed.as_secs_f64() / limit.as_secs_f64())
+                .min(1.0),
+            Some(_) => 1.0,
+            None => samples,
+        }
+    }
+
+    /// Estimated time left, assuming the remaining samples take as long on
+    /// average as the traced ones, and at most what is left of the time
+    /// budget. `None` until a tile has completed.
+    pub fn eta(&self) -> Option<Duration> {
+        if self.samples_completed == 0 {
+            return None;
+        }
+        let remaining = self.samples_total.saturating_sub(self.samples_completed);
+        let eta = self
+            .elapsed
+            .mul_f64(remaining as f64 / self.samples_completed as f64);
+        Some(match self.time_limit {
+            Some(limit) => eta.min(limit.saturating_sub(self.elapsed)),
+            None => eta,
+        })
+    }
+}
+
+/// Stops a render from another thread, or from the progress callback. Clones
+/// share the same flag.
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancelToken {
+    pub fn new() -> CancelToken {
+        CancelToken::default()
+    }
+
+    /// Asks the render to stop. Tiles already being traced are finished.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+/// Running totals of the samples of one pixel.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct PixelTotal {
+    pub(crate) color: Color,
+    pub(crate) alpha: f64,
+    /// Sum of the squared luminance of the samples, to estimate their
+    /// variance.
+    pub(crate) luminance_squares: f64,
+    pub(crate) samples: u32,
+}
+
+impl PixelTotal {
+    pub(crate) const ZERO: PixelTotal = PixelTotal {
+        color: Color::BLACK,
+        alpha: 0.0,
+        luminance_squares: 0.0,
+        samples: 0,
+    };
+
+    /// The totals with one more sample added.
+    pub(crate) fn add(&self, (color, alpha): (Color, f64)) -> PixelTotal {
+        PixelTotal {
+            color: self.color.add(&color),
+            alpha: self.alpha + alpha,
+            luminance_squares: self.luminance_squares + color.luminance().powi(2),
+            samples: self.samples + 1,
+        }
+    }
+
+    /// Estimated variance of the mean luminance of the samples, or `None`
+    /// with fewer than two samples.
+    pub(crate) fn variance(&self) -> Option<f64> {
+        if self.samples < 2 {
+            return None;
+        }
+        let n = f64::from(self.samples);
+        let mean = self.color.luminance() / n;
+        let sample_variance = (self.luminance_squares / n - mean * mean) * n / (n - 1.0);
+        Some(sample_variance.max(0.0) / n)
+    }
+}
+
+/// Running totals of the samples of every pixel, kept in floating point so
+/// that more can be added at any time and the average taken in between.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Accumulator {
+    width: i32,
+    height: i32,
+    totals: Vec<PixelTotal>,
+}
+
+impl Accumulator {
+    pub(crate) fn new(width: i32, height: i32) -> Accumulator {
+        Accumulator {
+            width,
+            height,
+            totals: vec![PixelTotal::ZERO; (width * height) as usize],
+        }
+    }
+
+    pub(crate) fn width(&self) -> i32 {
+        self.width
+    }
+
+    pub(crate) fn height(&self) -> i32 {
+        self.height
+    }
+
+    fn index(&self, x: i32, y: i32) -> usize {
+        (y * self.width + x) as usize
+    }
+
+    /// Number of samples taken of a pixel so far.
+    pub(crate) fn samples_at(&self, x: i32, y: i32) -> u32 {
+        self.total_at(x, y).samples
+    }
+
+    pub(crate) fn total_at(&self, x: i32, y: i32) -> PixelTotal {
+        self.totals[self.index(x, y)]
+    }
+
+    /// Replaces the totals of a pixel, after adding samples to them.
+    pub(crate) fn set_total(&mut self, x: i32, y: i32, total: PixelTotal) {
+        let i = self.index(x, y);
+        self.totals[i] = total;
+    }
+
+    /// Number of samples taken so far over the pixels of a region.
+    pub(crate) fn samples_taken(&self, region: &Region) -> u64 {
+        region
+            .tiles()
+            .iter()
+            .flat_map(Tile::pixels)
+            .map(|(x, y)| u64::from(self.samples_at(x, y)))
+            .sum()
+    }
+
+    /// Root mean square over the pixels of a region of the standard error of
+    /// their luminance: roughly how far a pixel is likely to be from where
+    /// it would settle with unlimited samples. Infinite until every pixel has
+    /// at least two samples.
+    pub(crate) fn noise(&self, region: &Region) -> f64 {
+        let pixels = (region.width * region.height) as usize;
+        let variances: Option<Vec<f64>> = region
+            .tiles()
+            .iter()
+            .flat_map(Tile::pixels)
+            .map(|(x, y)| self.total_at(x, y).variance())
+            .collect();
+        match variances {
+            Some(variances) if pixels > 0 => (variances.iter().sum::<f64>() / pixels as f64).sqrt(),
+            Some(_) => 0.0,
+            None => f64::INFINITY,
+        }
+    }
+
+    /// The average of every pixel's samples. Pixels without any are black.
+    pub(crate) fn to_canvas(&self) -> Canvas {
+        let mut canvas = Canvas::new(self.width, self.height);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let total = self.total_at(x, y);
+                if total.samples > 0 {
+                    let scale = 1.0 / f64::from(total.samples);
+                    canvas.write_pixel(x, y, &total.color.scalar_mul(scale));
+                    canvas.write_alpha(x, y, total.alpha * scale);
+                }
+            }
+        }
+        canvas
+    }
+}
+
+/// How often a render hands its state so far to the snapshot or checkpoint
+/// callback.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SnapshotInterval {
+    /// After every this many passes of a progressive render, except the last.
+    Passes(u32),
+    /// As soon as a tile completes this long after the start or the previous
+    /// snapshot.
+    Time(Duration),
+}
+
+impl SnapshotInterval {
+    fn after_tile(&self, last: Instant) -> bool {
+        matches!(self, SnapshotInterval::Time(interval) if last.elapsed() >= *interval)
+    }
+
+    /// Whether it is time after pass number `pass`, counting from one.
+    fn after_pass(&self, pass: usize, passes: usize) -> bool {
+        matches!(self, SnapshotInterval::Passes(every)
+            if pass < passes && pass.is_multiple_of((*every).max(1) as usize))
+    }
+}
+
+/// Limits for a render that stops adding samples as soon as one of them is
+/// met, rather than only once every pixel has the camera's samples per pixel.
+/// That still caps the render, so set it high.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Budget {
+    /// Wall-clock time to render for. The first pass, with one sample per
+    /// pixel, is always finished, as are tiles being traced when time runs
+    /// out.
+    pub time: Option<Duration>,
+    /// Target for `Accumulator::noise` over the rendered region, checked
+    /// after every pass.
+    pub noise: Option<f64>,
+}
+
+type ProgressCallback<'a> = dyn Fn(&Progress) + Sync + 'a;
+type SnapshotCallback<'a> = dyn Fn(&Canvas) + Sync + 'a;
+type CheckpointCallback<'a> = dyn Fn(&Checkpoint) + Sync + 'a;
+
+/// Progress reporting, snapshots, checkpoints and cancellation for
+/// `Camera::render_with`.
+#[derive(Default)]
+pub struct RenderControl<'a> {
+    progress: Option<Box<ProgressCallback<'a>>>,
+    snapshot: Option<(SnapshotInterval, Box<SnapshotCallback<'a>>)>,
+    checkpoint: Option<(SnapshotInterval, Box<CheckpointCallback<'a>>)>,
+    resume: Option<Checkpoint>,
+    region: Option<Region>,
+    budget: Option<Budget>,
+    cancel: CancelToken,
+    progressive: bool,
+}
+
+impl<'a> RenderControl<'a> {
+    pub fn new() -> RenderControl<'a> {
+        RenderControl::default()
+    }
+
+    /// Calls `callback` after every completed tile. Calls are never
+    /// concurrent, and `tiles_completed` goes up by one each time.
+    pub fn on_progress(self, callback: impl Fn(&Progress) + Sync + 'a) -> RenderControl<'a> {
+        RenderControl {
+            progress: Some(Box::new(callback)),
+            ..self
+        }
+    }
+
+    /// Calls `callback` with the image so far at the given interval, for
+    /// example to write it to disk. Pixels are averaged over however many
+    /// samples they have had, and those not reached yet are black.
+    pub fn on_snapshot(
+        self,
+        interval: SnapshotInterval,
+        callback: impl Fn(&Canvas) + Sync + 'a,
+    ) -> RenderControl<'a> {
+        RenderControl {
+            snapshot: Some((interval, Box::new(callback))),
+            ..self
+        }
+    }
+
+    /// Calls `callback` with the state of the render at the given interval,
+    /// for example to save it with `Checkpoint::save`.
+    pub fn on_checkpoint(
+        self,
+        interval: SnapshotInterval,
+        callback: impl Fn(&Checkpoint) + Sync + 'a,
+    ) -> RenderControl<'a> {
+        RenderControl {
+            checkpoint: Some((interval, Box::new(callback))),
+            ..self
+        }
+    }
+
+    /// Carries on from a checkpoint, skipping the samples already in it. The
+    /// checkpoint must be of the same scene and camera; `render_with` fails
+    /// if its size or number of samples differ.
+    pub fn resume(self, checkpoint: Checkpoint) -> RenderControl<'a> {
+        RenderControl {
+            resume: Some(checkpoint),
+            ..self
+        }
+    }
+
+    /// Traces only the pixels in `region`, leaving the rest of the image
+    /// black. Parts of the region outside the image are ignored.
+    pub fn region(self, region: Region) -> RenderControl<'a> {
+        RenderControl {
+            region: Some(region),
+            ..self
+        }
+    }
+
+    /// Renders progressively until the budget is met. The output reports
+    /// how many samples were taken and how noisy the image is.
+    pub fn budget(self, budget: Budget) -> RenderControl<'a> {
+        RenderControl {
+            budget: Some(budget),
+            progressive: true,
+            ..self
+        }
+    }
+
+    pub fn cancel_token(self, cancel: CancelToken) -> RenderControl<'a> {
+        RenderControl { cancel, ..self }
+    }
+
+    /// Renders in passes over the whole image, the first with one sample per
+    /// pixel and each after that doubling the number so far, so a usable
+    /// image is available early. The final image is the same either way.
+    pub fn progressive(self, progressive: bool) -> RenderControl<'a> {
+        RenderControl {
+            progressive,
+            ..self
+        }
+    }
+}
+
+/// The image from `Camera::render_with`. When the render was cancelled, the
+/// pixels have only the samples traced before it stopped, and those never
+/// reached are black; the checkpoint can be saved to finish it later.
+pub struct RenderOutput {
+    pub canvas: Canvas,
+    pub cancelled: bool,
+    pub checkpoint: Checkpoint,
+    /// Average number of samples over the pixels of the rendered region,
+    /// which may differ between pixels if the render stopped part way
+    /// through a pass.
+    pub samples_per_pixel: f64,
+    /// `Accumulator::noise` over the rendered region.
+    pub noise: f64,
+}
+
+/// The samples traced in each pass: all of them at once, or one and then
+/// doubling for a progressive render.
+fn passes(samples_per_pixel: u32, progressive: bool) -> Vec<Range<u32>> {
+    if !progressive {
+        return iter::once(0..samples_per_pixel).collect();
+    }
+    let mut passes = Vec::new();
+    let mut start = 0;
+    while start < samples_per_pixel {
+        let end = (start * 2).clamp(1, samples_per_pixel);
+        passes.push(start..end);
+        start = end;
+    }
+    passes
+}
+
+/// What a callback is due to be called with.
+enum Event {
+    Progress(Progress),
+    Snapshot(Canvas),
+    Checkpoint(Checkpoint),
+}
+
+struct RenderState {
+    accumulator: Accumulator,
+    tiles_completed: usize,
+    samples_completed: u64,
+    last_snapshot: Instant,
+    last_checkpoint: Instant,
+    /// Callback calls waiting for `Job::deliver`, in order.
+    events: VecDeque<Event>,
+}
+
+/// A render in progress, shared by the worker threads.
+struct Job<'a> {
+    camera: &'a Camera,
+    world: &'a World,
+    control: &'a RenderControl<'a>,
+    tiles: Vec<Tile>,
+    tiles_total: usize,
+    samples_total: u64,
+    start: Instant,
+    /// When the time budget runs out.
+    deadline: Option<Instant>,
+    state: Mutex<RenderState>,
+    /// Held by the thread calling back, so that calls are never concurrent.
+    /// Other threads leave their events to it and carry on rendering.
+    calling: Mutex<()>,
+}
+
+impl Job<'_> {
+    fn lock(&self) -> MutexGuard<'_, RenderState> {
+        self.state.lock().expect("render thread panicked")
+    }
+
+    /// Adds samples to every pixel, tile by tile, up to `samples.end`. Pixels
+    /// that already have some of them, from a checkpoint, only get the rest.
+    fn run_pass(&self, samples: &Range<u32>) {
+        // Threads take the next unrendered tile until none are left
+        let next_tile = AtomicUsize::new(0);
+        let render_tiles = || {
+            while !self.stopping(samples) {
+                let Some(&tile) = self.tiles.get(next_tile.fetch_add(1, Ordering::Relaxed)) else {
+                    break;
+                };
+                let totals: Vec<PixelTotal> = {
+                    let state = self.lock();
+                    tile.pixels()
+                        .map(|(x, y)| state.accumulator.total_at(x, y))
+                        .collect()
+                };
+                let traced: u64 = totals
+                    .iter()
+                    .map(|total| u64::from(samples.end.saturating_sub(total.samples)))
+                    .sum();
+                let totals: Vec<PixelTotal> = tile
+                    .pixels()
+                    .zip(totals)
+                    .map(|((x, y), total)| {
+                        (total.samples..samples.end).fold(total, |total, sample| {
+                            total.add(self.camera.sample_pixel(self.world, x, y, sample))
+                        })
+                    })
+                    .collect();
+
+                let mut state = self.lock();
+                for ((x, y), total) in tile.pixels().zip(totals) {
+                    state.accumulator.set_total(x, y, total);
+                }
+                state.tiles_completed += 1;
+                state.samples_completed += traced;
+                self.report(&mut state);
+                drop(state);
+                self.deliver();
+            }
+        };
+
+        if self.camera.threads() == 1 {
+            render_tiles();
+        } else {
+            thread::scope(|scope| {
+                for _ in 0..self.camera.threads() {
+                    scope.spawn(render_tiles);
+                }
+            });
+        }
+        self.deliver();
+    }
+
+    /// Whether to stop taking tiles: once cancelled, or out of time after
+    /// the first pass.
+    fn stopping(&self, samples: &Range<u32>) -> bool {
+        self.control.cancel.is_cancelled() || (samples.start > 0 && self.out_of_time())
+    }
+
+    fn out_of_time(&self) -> bool {
+        self.deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+    }
+
+    /// Queues the callbacks due after a tile.
+    fn report(&self, state: &mut RenderState) {
+        if self.control.progress.is_some() {
+            state.events.push_back(Event::Progress(Progress {
+                tiles_completed: state.tiles_completed,
+                tiles_total: self.tiles_total,
+                samples_completed: state.samples_completed,
+                samples_total: self.samples_total,
+                elapsed: self.start.elapsed(),
+                time_limit: self.control.budget.and_then(|budget| budget.time),
+            }));
+        }
+        if let Some((interval, _)) = &self.control.snapshot {
+            if interval.after_tile(state.last_snapshot) {
+                self.snapshot(state);
+            }
+        }
+        if let Some((interval, _)) = &self.control.checkpoint {
+            if interval.after_tile(state.last_checkpoint) {
+                self.checkpoint(state);
+            }
+        }
+    }
+
+    fn snapshot(&self, state: &mut RenderState) {
+        if self.control.snapshot.is_some() {
+            let canvas = state.accumulator.to_canvas();
+            state.events.push_back(Event::Snapshot(canvas));
+            state.last_snapshot = Instant::now();
+        }
+    }
+
+    fn checkpoint(&self, state: &mut RenderState) {
+        if self.control.checkpoint.is_some() {
+            state.events.push_back(Event::Checkpoint(Checkpoint {
+                samples_per_pixel: self.camera.samples_per_pixel(),
+                accumulator: state.accumulator.clone(),
+            }));
+            state.last_checkpoint = Instant::now();
+        }
+    }
+
+    /// Calls back with the queued events, unless another thread already is.
+    /// Must be called without the state locked, which is only taken to
+    /// dequeue each event.
+    fn deliver(&self) {
+        loop {
+            let calling = match self.calling.try_lock() {
+                Ok(calling) => calling,
+                Err(TryLockError::WouldBlock) => return,
+                Err(TryLockError::Poisoned(_)) => panic!("render callback panicked"),
+            };
+            while let Some(event) = self.next_event() {
+                self.call_back(event);
+            }
+            drop(calling);
+            // Another thread may have queued events and left them to this
+            // one after the queue was found empty
+            if self.lock().events.is_empty() {
+                return;
+            }
+        }
+    }
+
+    fn next_event(&self) -> Option<Event> {
+        self.lock().events.pop_front()
+    }
+
+    fn call_back(&self, event: Event) {
+        match event {
+            Event::Progress(progress) => {
+                if let Some(callback) = &self.control.progress {
+                    callback(&progress);
+                }
+            }
+            Event::Snapshot(canvas) => {
+                if let Some((_, callback)) = &self.control.snapshot {
+                    callback(&canvas);
+                }
+            }
+            Event::Checkpoint(checkpoint) => {
+                if let Some((_, callback)) = &self.control.checkpoint {
+                    callback(&checkpoint);
+                }
+            }
+        }
+    }
+}
+
+impl Camera {
+    pub fn render(&self, world: &World) -> Canvas {
+        self.render_from(world, &RenderControl::new(), self.accumulator())
+            .canvas
+    }
+
+    /// Renders just `region` of the image, as a canvas of that size. Parts of
+    /// the region outside the image are left out.
+    pub fn render_region(&self, world: &World, region: Region) -> Canvas {
+        let region = region.clip(self.hsize(), self.vsize());
+        let control = RenderControl::new().region(region);
+        region.crop(&self.render_from(world, &control, self.accumulator()).canvas)
+    }
+
+    /// Re-renders `region` of an image previously rendered by this camera,
+    /// leaving the rest of it as it was.
+    pub fn render_region_into(&self, world: &World, region: Region, canvas: &mut Canvas) {
+        let region = region.clip(
+            canvas.width.min(self.hsize()),
+            canvas.height.min(self.vsize()),
+        );
+        let control = RenderControl::new().region(region);
+        let full = self.render_from(world, &control, self.accumulator()).canvas;
+        for (x, y) in region.tiles().iter().flat_map(Tile::pixels) {
+            canvas.write_pixel(x, y, full.pixel_at(x, y).unwrap_or(&Color::BLACK));
+            canvas.write_alpha(x, y, full.alpha_at(x, y).unwrap_or(1.0));
+        }
+    }
+
+    /// Fails only when resuming a checkpoint of a different size or number
+    /// of samples.
+    pub fn render_with(
+        &self,
+        world: &World,
+        control: &RenderControl,
+    ) -> Result<RenderOutput, CheckpointError> {
+        let accumulator = match &control.resume {
+            Some(checkpoint) => {
+                checkpoint.check(self)?;
+                checkpoint.accumulator.clone()
+            }
+            None => self.accumulator(),
+        };
+        Ok(self.render_from(world, control, accumulator))
+    }
+
+    /// An accumulator for this camera's image, without any samples.
+    fn accumulator(&self) -> Accumulator {
+        Accumulator::new(self.hsize(), self.vsize())
+    }
+
+    /// Renders on top of the samples already in `accumulator`.
+    fn render_from(
+        &self,
+        world: &World,
+        control: &RenderControl,
+        accumulator: Accumulator,
+    ) -> RenderOutput {
+        let region = control
+            .region
+            .unwrap_or(Region::full(self.hsize(), self.vsize()))
+            .clip(self.hsize(), self.vsize());
+        let tiles = region.tiles();
+        let passes = passes(self.samples_per_pixel(), control.progressive);
+        let pixels = (region.width * region.height) as u64;
+        let budget = control.budget.unwrap_or_default();
+        let start = Instant::now();
+        let job = Job {
+            camera: self,
+            world,
+            control,
+            tiles_total: tiles.len() * passes.len(),
+            tiles,
+            samples_total: (pixels * u64::from(self.samples_per_pixel()))
+                .saturating_sub(accumulator.samples_taken(&region)),
+            start,
+            deadline: budget.time.map(|time| start + time),
+            state: Mutex::new(RenderState {
+                accumulator,
+                tiles_completed: 0,
+                samples_completed: 0,
+                last_snapshot: start,
+                last_checkpoint: start,
+                events: VecDeque::new(),
+            }),
+            calling: Mutex::new(()),
+        };
+
+        for (pass, samples) in (1..).zip(&passes) {
+            job.run_pass(samples);
+            if control.cancel.is_cancelled() || job.out_of_time() {
+                break;
+            }
+            let mut state = job.lock();
+            if budget
+                .noise
+                .is_some_and(|target| state.accumulator.noise(&region) <= target)
+            {
+                break;
+            }
+            if let Some((interval, _)) = &control.snapshot {
+                if interval.after_pass(pass, passes.len()) {
+                    job.snapshot(&mut state);
+                }
+            }
+            if let Some((interval, _)) = &control.checkpoint {
+                if interval.after_pass(pass, passes.len()) {
+                    job.checkpoint(&mut state);
+                }
+            }
+            drop(state);
+            job.deliver();
+        }
+
+        let state = job.state.into_inner().expect("render thread panicked");
+        let samples_taken = state.accumulator.samples_taken(&region);
+        RenderOutput {
+            canvas: state.accumulator.to_canvas(),
+            cancelled: state.tiles_completed < job.tiles_total && control.cancel.is_cancelled(),
+            samples_per_pixel: samples_taken as f64 / pixels.max(1) as f64,
+            noise: state.accumulator.noise(&region),
+            checkpoint: Checkpoint {
+                samples_per_pixel: self.samples_per_pixel(),
+                accumulator: state.accumulator,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        f64::consts::PI,
+        iter,
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Mutex,
+        },
+        thread,
+        time::Duration,
+    };
+
+    use crate::{
+        checkpoint::{Checkpoint, CheckpointError},
+        color::{Canvas, Color},
+        integrator::Integrator,
+        lighting::{Material, PointLight},
+        matrix4::Matrix4,
+        object::{Object, ObjectType},
+        tuple::Tuple,
+        world::{Camera, World},
+    };
+
+    use super::{
+        passes, Accumulator, Budget, CancelToken, PixelTotal, Progress, Region, RenderControl,
+        SnapshotInterval, Tile,
+    };
+
+    fn assert_same_image(a: &Canvas, b: &Canvas) {
+        for y in 0..a.height {
+            for x in 0..a.width {
+                assert_eq!(a.pixel_at(x, y), b.pixel_at(x, y));
+                assert_eq!(a.alpha_at(x, y), b.alpha_at(x, y));
+            }
+        }
+    }
+
+    fn world() -> World {
+        let sphere = Object::builder(ObjectType::Sphere)
+            .material(Material::builder().color(Color::new(0.8, 1.0, 0.6)).build())
+            .transform(Matrix4::IDENTITY.scale(3.0, 3.0, 3.0))
+            .build();
+        World::new(
+            PointLight::builder(Tuple::point(-10.0, 10.0, -10.0)).build(),
+            vec![sphere],
+        )
+    }
+
+    fn camera(hsize: i32, vsize: i32) -> Camera {
+        Camera::builder(hsize, vsize)
+            .field_of_view(PI / 2.0)
+            .look_at(
+                Tuple::point(0.0, 0.0, -5.0),
+                Tuple::point(0.0, 0.0, 0.0),
+                Tuple::vector(0.0, 1.0, 0.0),
+            )
+            .build()
+    }
+
+    #[test]
+    fn tiles_cover_the_image_once() {
+        let tiles = Region::full(70, 40).tiles();
+        assert_eq!(tiles.len(), 6);
+        assert_eq!(
+            tiles[2],
+            Tile {
+                x: 64,
+                y: 0,
+                width: 6,
+                height: 32
+            }
+        );
+        let mut covered = vec![0; 70 * 40];
+        for (x, y) in tiles.iter().flat_map(Tile::pixels) {
+            covered[(y * 70 + x) as usize] += 1;
+        }
+        assert!(covered.iter().all(|&count| count == 1));
+    }
+
+    #[test]
+    fn progress_fraction_and_eta() {
+        let progress = Progress {
+            tiles_completed: 2,
+            tiles_total: 4,
+            samples_completed: 100,
+            samples_total: 400,
+            elapsed: Duration::from_secs(2),
+            time_limit: None,
+        };
+        assert_eq!(progress.fraction(), 0.25);
+        assert_eq!(progress.eta(), Some(Duration::from_secs(6)));
+        assert_eq!(
+            Progress {
+                tiles_completed: 0,
+                samples_completed: 0,
+                ..progress
+            }
+            .eta(),
+            None
+        );
+
+        // A time budget caps the estimate
+        let limited = Progress {
+            time_limit: Some(Duration::from_secs(4)),
+            ..progress
+        };
+        assert_eq!(limited.fraction(), 0.5);
+        assert_eq!(limited.eta(), Some(Duration::from_secs(2)));
+    }
+
+    #[test]
+    fn progress_is_reported_after_every_tile() {
+        let camera = camera(70, 40).with_threads(3);
+        let reports = Mutex::new(Vec::new());
+        let control = RenderControl::new()
+            .on_progress(|progress| reports.lock().unwrap().push(progress.tiles_completed));
+        let output = camera.render_with(&world(), &control).unwrap();
+
+        assert!(!output.cancelled);
+        assert_eq!(*reports.lock().unwrap(), vec![1, 2, 3, 4, 5, 6]);
+    }
+
+    #[test]
+    fn cancelling_returns_the_tiles_rendered_so_far() {
+        let camera = camera(70, 40).with_integrator(Integrator::PathTracer { max_depth: 2 });
+        let world = world();
+        let full = camera.render(&world);
+
+        let token = CancelToken::new();
+        let control = RenderControl::new()
+            .cancel_token(token.clone())
+            .on_progress(|progress| {
+                if progress.tiles_completed == 2 {
+                    token.cancel();
+                }
+            });
+        let output = camera.render_with(&world, &control).unwrap();
+        assert!(output.cancelled);
+
+        // The first two tiles match the full render, the rest were skipped
+        for (x, y) in Region::full(70, 40).tiles()[..2]
+            .iter()
+            .flat_map(Tile::pixels)
+        {
+            assert_eq!(output.canvas.pixel_at(x, y), full.pixel_at(x, y));
+        }
+        assert_ne!(*full.pixel_at(35, 35).unwrap(), Color::BLACK);
+        assert_eq!(*output.canvas.pixel_at(35, 35).unwrap(), Color::BLACK);
+    }
+
+    #[test]
+    fn cancelled_before_starting() {
+        let token = CancelToken::new();
+        token.cancel();
+        let output = camera(8, 8)
+            .render_with(&world(), &RenderControl::new().cancel_token(token))
+            .unwrap();
+        assert!(output.cancelled);
+        assert_eq!(*output.canvas.pixel_at(4, 4).unwrap(), Color::BLACK);
+    }
+
+    #[test]
+    fn render_with_matches_render() {
+        let camera = camera(40, 35).with_threads(2);
+        let world = world();
+        let output = camera.render_with(&world, &RenderControl::new()).unwrap();
+        let canvas = camera.render(&world);
+        assert!(!output.cancelled);
+        for y in 0..35 {
+            for x in 0..40 {
+                assert_eq!(output.canvas.pixel_at(x, y), canvas.pixel_at(x, y));
+                assert_eq!(output.canvas.alpha_at(x, y), canvas.alpha_at(x, y));
+            }
+        }
+    }
+
+    #[test]
+    fn accumulator_averages_samples() {
+        let mut accumulator = Accumulator::new(2, 1);
+        let total = PixelTotal {
+            color: Color::new(1.0, 2.0, 0.5),
+            alpha: 1.5,
+            luminance_squares: 0.0,
+            samples: 2,
+        };
+        accumulator.set_total(0, 0, total);
+        assert_eq!(accumulator.samples_at(0, 0), 2);
+        assert_eq!(accumulator.samples_at(1, 0), 0);
+
+        let canvas = accumulator.to_canvas();
+        assert_eq!(*canvas.pixel_at(0, 0).unwrap(), Color::new(0.5, 1.0, 0.25));
+        assert_eq!(canvas.alpha_at(0, 0), Some(0.75));
+        assert_eq!(*canvas.pixel_at(1, 0).unwrap(), Color::BLACK);
+    }
+
+    #[test]
+    fn progressive_passes_double_the_samples() {
+        assert_eq!(passes(6, false), iter::once(0..6).collect::<Vec<_>>());
+        assert_eq!(passes(1, true), iter::once(0..1).collect::<Vec<_>>());
+        assert_eq!(passes(6, true), vec![0..1, 1..2, 2..4, 4..6]);
+        assert_eq!(passes(8, true), vec![0..1, 1..2, 2..4, 4..8]);
+    }
+
+    #[test]
+    fn progressive_render_matches_single_pass() {
+        let camera = camera(40, 35)
+            .with_integrator(Integrator::PathTracer { max_depth: 2 })
+            .with_samples_per_pixel(6)
+            .with_threads(2);
+        let world = world();
+        let canvas = camera.render(&world);
+
+        let reports = Mutex::new(Vec::new());
+        let control = RenderControl::new()
+            .progressive(true)
+            .on_progress(|progress| reports.lock().unwrap().push(*progress));
+        let output = camera.render_with(&world, &control).unwrap();
+        assert!(!output.cancelled);
+        for y in 0..35 {
+            for x in 0..40 {
+                assert_eq!(output.canvas.pixel_at(x, y), canvas.pixel_at(x, y));
+                assert_eq!(output.canvas.alpha_at(x, y), canvas.alpha_at(x, y));
+            }
+        }
+
+        // Four passes over four tiles
+        let reports = reports.lock().unwrap();
+        assert_eq!(reports.len(), 16);
+        let last = reports.last().unwrap();
+        assert_eq!((last.tiles_completed, last.tiles_total), (16, 16));
+        assert_eq!(last.samples_completed, 40 * 35 * 6);
+        assert_eq!(last.fraction(), 1.0);
+    }
+
+    #[test]
+    fn snapshots_between_passes() {
+        let camera = camera(40, 35).with_samples_per_pixel(8);
+        let snapshots = Mutex::new(Vec::new());
+        let control = RenderControl::new().progressive(true).on_snapshot(
+            SnapshotInterval::Passes(1),
+            |canvas| {
+                snapshots
+                    .lock()
+                    .unwrap()
+                    .push(*canvas.pixel_at(20, 17).unwrap())
+            },
+        );
+        let output = camera.render_with(&world(), &control).unwrap();
+
+        // Not after the last pass, which is the final image
+        let snapshots = snapshots.lock().unwrap();
+        assert_eq!(snapshots.len(), 3);
+        assert!(snapshots.iter().all(|&c| c != Color::BLACK));
+        assert_ne!(snapshots[0], *output.canvas.pixel_at(20, 17).unwrap());
+    }
+
+    #[test]
+    fn timed_snapshots_after_tiles() {
+        let camera = camera(70, 40).with_samples_per_pixel(2);
+        let count = Mutex::new(0);
+        let control = RenderControl::new()
+            .progressive(true)
+            .on_snapshot(SnapshotInterval::Time(Duration::ZERO), |_| {
+                *count.lock().unwrap() += 1
+            });
+        camera.render_with(&world(), &control).unwrap();
+        assert_eq!(*count.lock().unwrap(), 12);
+    }
+
+    #[test]
+    fn slow_callbacks_do_not_hold_up_rendering() {
+        let camera = camera(70, 40).with_threads(3);
+        let delay = Duration::from_millis(500);
+        let slept = AtomicBool::new(false);
+        let reports = Mutex::new(Vec::new());
+        let control = RenderControl::new()
+            .on_progress(|progress| reports.lock().unwrap().push(*progress))
+            .on_snapshot(SnapshotInterval::Time(Duration::ZERO), |_| {
+                if !slept.swap(true, Ordering::Relaxed) {
+                    thread::sleep(delay);
+                }
+            });
+        camera.render_with(&world(), &control).unwrap();
+
+        // Every tile was finished while the first snapshot was being taken
+        let reports = reports.lock().unwrap();
+        assert_eq!(reports.len(), 6);
+        assert!(reports.iter().all(|progress| progress.elapsed < delay));
+    }
+
+    #[test]
+    fn resuming_a_checkpoint_gives_the_same_image() {
+        let camera = camera(70, 40)
+            .with_integrator(Integrator::PathTracer { max_depth: 2 })
+            .with_samples_per_pixel(4);
+        let world = world();
+        let full = camera.render(&world);
+
+        // Stop a progressive render part way through its second pass
+        let token = CancelToken::new();
+        let control = RenderControl::new()
+            .progressive(true)
+            .cancel_token(token.clone())
+            .on_progress(|progress| {
+                if progress.tiles_completed == 8 {
+                    token.cancel();
+                }
+            });
+        let stopped = camera.render_with(&world, &control).unwrap();
+        assert!(stopped.cancelled);
+        assert_eq!(
+            stopped
+                .checkpoint
+                .accumulator
+                .samples_taken(&Region::full(70, 40)),
+            70 * 40 + 2 * 32 * 32
+        );
+
+        let mut bytes = Vec::new();
+        stopped.checkpoint.write(&mut bytes).unwrap();
+        let checkpoint = Checkpoint::read(&mut bytes.as_slice()).unwrap();
+
+        let reports = Mutex::new(Vec::new());
+        let control = RenderControl::new()
+            .progressive(true)
+            .resume(checkpoint)
+            .on_progress(|progress| reports.lock().unwrap().push(*progress));
+        let resumed = camera.render_with(&world, &control).unwrap();
+        assert!(!resumed.cancelled);
+        assert_same_image(&resumed.canvas, &full);
+        let last = *reports.lock().unwrap().last().unwrap();
+        assert_eq!(last.tiles_completed, 18);
+        assert_eq!(last.samples_completed, last.samples_total);
+        assert_eq!(last.samples_total, 70 * 40 * 4 - (70 * 40 + 2 * 32 * 32));
+
+        // Neither do the passes or the threads
+        let control = RenderControl::new().resume(stopped.checkpoint);
+        let resumed = camera
+            .with_threads(3)
+            .render_with(&world, &control)
+            .unwrap();
+        assert_same_image(&resumed.canvas, &full);
+    }
+
+    #[test]
+    fn checkpoints_between_passes() {
+        let camera = camera(40, 35).with_samples_per_pixel(4);
+        let checkpoints = Mutex::new(Vec::new());
+        let control = RenderControl::new().progressive(true).on_checkpoint(
+            SnapshotInterval::Passes(1),
+            |checkpoint| {
+                checkpoints
+                    .lock()
+                    .unwrap()
+                    .push(checkpoint.accumulator.samples_at(39, 34))
+            },
+        );
+        let output = camera.render_with(&world(), &control).unwrap();
+        assert_eq!(*checkpoints.lock().unwrap(), vec![1, 2]);
+        assert_eq!(output.checkpoint.accumulator.samples_at(39, 34), 4);
+        assert_eq!(output.checkpoint.samples_per_pixel, 4);
+    }
+
+    #[test]
+    fn resuming_a_different_render() {
+        let checkpoint = Checkpoint {
+            samples_per_pixel: 1,
+            accumulator: Accumulator::new(4, 4),
+        };
+        let control = RenderControl::new().resume(checkpoint);
+        assert!(matches!(
+            camera(8, 8).render_with(&world(), &control),
+            Err(CheckpointError::Mismatch(_))
+        ));
+    }
+
+    #[test]
+    fn regions_are_clipped_and_tiled() {
+        let region = Region {
+            x: -10,
+            y: 30,
+            width: 50,
+            height: 100,
+        };
+        assert_eq!(
+            region.clip(70, 40),
+            Region {
+                x: 0,
+                y: 30,
+                width: 40,
+                height: 10
+            }
+        );
+        assert_eq!(region.clip(70, 20).height, 0);
+        assert!(region.clip(70, 20).tiles().is_empty());
+
+        let tiles = Region {
+            x: 5,
+            y: 3,
+            width: 40,
+            height: 10,
+        }
+        .tiles();
+        assert_eq!(
+            tiles,
+            vec![
+                Tile {
+                    x: 5,
+                    y: 3,
+                    width: 32,
+                    height: 10
+                },
+                Tile {
+                    x: 37,
+                    y: 3,
+                    width: 8,
+                    height: 10
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn regions_match_the_full_render() {
+        let camera = camera(70, 40)
+            .with_integrator(Integrator::PathTracer { max_depth: 2 })
+            .with_samples_per_pixel(2);
+        let world = world();
+        let full = camera.render(&world);
+        let region = Region {
+            x: 20,
+            y: 10,
+            width: 40,
+            height: 25,
+        };
+
+        let cropped = camera.render_region(&world, region);
+        assert_eq!((cropped.width, cropped.height), (40, 25));
+        for y in 0..25 {
+            for x in 0..40 {
+                assert_eq!(cropped.pixel_at(x, y), full.pixel_at(x + 20, y + 10));
+            }
+        }
+
+        // Only the region is traced and replaced
+        let reports = Mutex::new(Vec::new());
+        let control = RenderControl::new()
+            .region(region)
+            .on_progress(|progress| reports.lock().unwrap().push(*progress));
+        let output = camera.render_with(&world, &control).unwrap();
+        let last = *reports.lock().unwrap().last().unwrap();
+        assert_eq!((last.tiles_completed, last.tiles_total), (2, 2));
+        assert_eq!(last.samples_total, 40 * 25 * 2);
+        assert_eq!(*output.canvas.pixel_at(35, 5).unwrap(), Color::BLACK);
+        assert_eq!(output.canvas.pixel_at(35, 20), full.pixel_at(35, 20));
+
+        let mut patched = Canvas::new(70, 40);
+        patched.write_pixel(0, 0, &Color::WHITE);
+        camera.render_region_into(&world, region, &mut patched);
+        assert_eq!(*patched.pixel_at(0, 0).unwrap(), Color::WHITE);
+        assert_eq!(patched.pixel_at(59, 34), full.pixel_at(59, 34));
+        assert_eq!(*patched.pixel_at(60, 35).unwrap(), Color::BLACK);
+    }
+
+    #[test]
+    fn noise_from_sample_variance() {
+        let total = PixelTotal::ZERO.add((Color::WHITE, 1.0));
+        assert_eq!(total.variance(), None);
+        let total = total.add((Color::BLACK, 1.0));
+        assert_abs_diff_eq!(total.variance().unwrap(), 0.25);
+
+        let mut accumulator = Accumulator::new(2, 1);
+        let region = Region::full(2, 1);
+        accumulator.set_total(0, 0, total);
+        assert_eq!(accumulator.noise(&region), f64::INFINITY);
+        accumulator.set_total(1, 0, total.add((Color::WHITE, 1.0)));
+        assert_abs_diff_eq!(
+            accumulator.noise(&region),
+            ((0.25 + 1.0 / 9.0) / 2.0_f64).sqrt()
+        );
+    }
+
+    #[test]
+    fn budget_stops_at_the_noise_target() {
+        let camera = camera(40, 35)
+            .with_integrator(Integrator::PathTracer { max_depth: 2 })
+            .with_samples_per_pixel(256)
+            .with_threads(2);
+        let budget = Budget {
+            noise: Some(0.25),
+            ..Budget::default()
+        };
+        let output = camera
+            .render_with(&world(), &RenderControl::new().budget(budget))
+            .unwrap();
+
+        // Stops after the first pass whose noise is below the target, the
+        // fifth, at 16 samples per pixel
+        assert!(!output.cancelled);
+        assert!(output.noise <= 0.25);
+        assert_eq!(output.samples_per_pixel, 16.0);
+        assert_eq!(
+            output.noise,
+            output.checkpoint.accumulator.noise(&Region::full(40, 35))
+        );
+    }
+
+    #[test]
+    fn budget_stops_when_time_runs_out() {
+        let camera = camera(40, 35).with_samples_per_pixel(1 << 16);
+        let budget = Budget {
+            time: Some(Duration::ZERO),
+            ..Budget::default()
+        };
+        let reports = Mutex::new(Vec::new());
+        let control = RenderControl::new()
+            .budget(budget)
+            .on_progress(|progress| reports.lock().unwrap().push(*progress));
+        let output = camera.render_with(&world(), &control).unwrap();
+
+        // The first pass is always finished
+        assert!(!output.cancelled);
+        assert_eq!(output.samples_per_pixel, 1.0);
+        assert_eq!(output.noise, f64::INFINITY);
+        assert_ne!(*output.canvas.pixel_at(20, 17).unwrap(), Color::BLACK);
+        let last = *reports.lock().unwrap().last().unwrap();
+        assert_eq!(last.fraction(), 1.0);
+        assert_eq!(last.eta(), Some(Duration::ZERO));
+    }
+}
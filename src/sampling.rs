@@ -0,0 +1,196 @@
+use std::f64::consts::PI;
+
+use crate::tuple::Tuple;
+
+/// Small deterministic random number generator (SplitMix64). The whole state
+/// is a single `u64`, so renders can be reproduced from a seed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Rng {
+    pub state: u64,
+}
+
+impl Rng {
+    pub const fn new(seed: u64) -> Rng {
+        Rng { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform value in `[0, 1)`.
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// Mixes a pixel position and sample index into a seed, so each sample of a
+/// render draws the same random numbers regardless of the order it runs in.
+pub fn sample_seed(x: i32, y: i32, sample: u32) -> u64 {
+    let pixel = Rng::new((x as u32 as u64) << 32 | (y as u32 as u64)).next_u64();
+    Rng::new(pixel ^ u64::from(sample).wrapping_mul(0xD1B5_4A32_D192_ED03)).next_u64()
+}
+
+/// Two unit vectors perpendicular to `normal` and to each other.
+pub fn tangent_frame(normal: &Tuple) -> (Tuple, Tuple) {
+    let helper = if normal.x.abs() > 0.9 {
+        Tuple::vector(0.0, 1.0, 0.0)
+    } else {
+        Tuple::vector(1.0, 0.0, 0.0)
+    };
+    let tangent = helper.cross(normal).normalize();
+    let bitangent = normal.cross(&tangent);
+    (tangent, bitangent)
+}
+
+/// Direction in the hemisphere around `axis`, `cos_theta` away from it.
+pub fn direction_around(axis: &Tuple, cos_theta: f64, phi: f64) -> Tuple {
+    let (tangent, bitangent) = tangent_frame(axis);
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    tangent
+        .scalar_mul(sin_theta * phi.cos())
+        .add(&bitangent.scalar_mul(sin_theta * phi.sin()))
+        .add(&axis.scalar_mul(cos_theta))
+        .normalize()
+}
+
+/// Cosine-weighted direction in the hemisphere around `normal`, whose density
+/// is `cos_theta / PI`.
+pub fn cosine_sample_hemisphere(normal: &Tuple, u1: f64, u2: f64) -> Tuple {
+    direction_around(normal, (1.0 - u1).sqrt(), 2.0 * PI * u2)
+}
+
+/// Piecewise-constant distribution over `[0, 1)`, sampled by inverting its CDF.
+pub struct Distribution1D {
+    func: Vec<f64>,
+    cdf: Vec<f64>,
+    pub integral: f64,
+}
+
+impl Distribution1D {
+    pub fn new(func: Vec<f64>) -> Distribution1D {
+        let n = func.len();
+        let mut cdf = vec![0.0; n + 1];
+        for i in 0..n {
+            cdf[i + 1] = cdf[i] + func[i].abs() / n as f64;
+        }
+        let integral = cdf[n];
+
+        for (i, value) in cdf.iter_mut().enumerate().skip(1) {
+            *value = if integral == 0.0 {
+                i as f64 / n as f64
+            } else {
+                *value / integral
+            };
+        }
+
+        Distribution1D {
+            func,
+            cdf,
+            integral,
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        self.func.len()
+    }
+
+    /// Maps `u` to a value in `[0, 1)`, returning it with its density and the
+    /// index of the segment it fell in.
+    pub fn sample_continuous(&self, u: f64) -> (f64, f64, usize) {
+        let offset = self
+            .cdf
+            .partition_point(|c| *c <= u)
+            .saturating_sub(1)
+            .min(self.count() - 1);
+
+        let segment = self.cdf[offset + 1] - self.cdf[offset];
+        let du = if segment > 0.0 {
+            (u - self.cdf[offset]) / segment
+        } else {
+            0.0
+        };
+        let pdf = self.pdf(offset);
+
+        ((offset as f64 + du) / self.count() as f64, pdf, offset)
+    }
+
+    pub fn pdf(&self, index: usize) -> f64 {
+        if self.integral > 0.0 {
+            self.func[index].abs() / self.integral
+        } else {
+            1.0
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tuple::Tuple;
+
+    use super::{cosine_sample_hemisphere, sample_seed, Distribution1D, Rng};
+
+    #[test]
+    fn rng_is_deterministic() {
+        let mut a = Rng::new(42);
+        let mut b = Rng::new(42);
+        for _ in 0..10 {
+            assert_eq!(a.next_u64(), b.next_u64());
+        }
+    }
+
+    #[test]
+    fn rng_unit_interval() {
+        let mut rng = Rng::new(7);
+        for _ in 0..1000 {
+            let u = rng.next_f64();
+            assert!((0.0..1.0).contains(&u));
+        }
+    }
+
+    #[test]
+    fn sample_seeds_differ() {
+        assert_ne!(sample_seed(0, 0, 0), sample_seed(0, 0, 1));
+        assert_ne!(sample_seed(1, 0, 0), sample_seed(0, 1, 0));
+        assert_eq!(sample_seed(3, 4, 5), sample_seed(3, 4, 5));
+    }
+
+    #[test]
+    fn cosine_samples_stay_in_hemisphere() {
+        let normal = Tuple::vector(1.0, 1.0, 0.0).normalize();
+        let mut rng = Rng::new(1);
+        for _ in 0..100 {
+            let d = cosine_sample_hemisphere(&normal, rng.next_f64(), rng.next_f64());
+            assert_abs_diff_eq!(d.magnitude(), 1.0);
+            assert!(d.dot(&normal) >= 0.0);
+        }
+    }
+
+    #[test]
+    fn distribution_favours_heavier_segments() {
+        let d = Distribution1D::new(vec![1.0, 3.0]);
+        assert_abs_diff_eq!(d.integral, 2.0);
+
+        let (x, pdf, index) = d.sample_continuous(0.1);
+        assert_eq!(index, 0);
+        assert_abs_diff_eq!(pdf, 0.5);
+        assert_abs_diff_eq!(x, 0.2);
+
+        let (x, pdf, index) = d.sample_continuous(0.625);
+        assert_eq!(index, 1);
+        assert_abs_diff_eq!(pdf, 1.5);
+        assert_abs_diff_eq!(x, 0.75);
+    }
+
+    #[test]
+    fn distribution_all_zero_is_uniform() {
+        let d = Distribution1D::new(vec![0.0, 0.0, 0.0, 0.0]);
+        let (x, _, index) = d.sample_continuous(0.6);
+        assert_eq!(index, 2);
+        assert_abs_diff_eq!(x, 0.6);
+    }
+}
@@ -0,0 +1,1179 @@
+//! Loads scenes from text files in the style of the Ray Tracer Challenge
+//! YAML scenes: a list of `add` entries for the camera, lights, objects and
+//! an optional environment.
+//!
+//! ```yaml
+//! - add: camera
+//!   width: 400
+//!   height: 200
+//!   field-of-view: 1.047     # radians
+//!   from: [0, 1.5, -5]
+//!   to: [0, 1, 0]
+//!   up: [0, 1, 0]
+//!
+//! - add: light
+//!   at: [-10, 10, -10]
+//!   intensity: [1, 1, 1]
+//!
+//! - add: sphere
+//!   material:
+//!     color: [1, 0.2, 0.2]
+//!     diffuse: 0.7
+//!   transform:               # applied in the order listed
+//!     - [scale, 0.5, 0.5, 0.5]
+//!     - [translate, 0, 0.5, 0]
+//! ```
+//!
+//! `define` entries name a `value` that can be used in place of a material,
+//! pattern, colour, shading model, visibility or transform list, or spliced
+//! into a transform list. A mapping definition can `extend` an earlier one,
+//! overriding some of its keys; materials can also `extend` a definition in
+//! place. `include` entries load another scene file as if its entries were
+//! written in place of the `include`.
+//!
+//! ```yaml
+//! - include: common/lights.yaml
+//!
+//! - define: shiny
+//!   value:
+//!     specular: 0.9
+//!     shininess: 300
+//!
+//! - define: shiny-red
+//!   extend: shiny
+//!   value:
+//!     color: [1, 0, 0]
+//!
+//! - add: sphere
+//!   material:
+//!     extend: shiny-red
+//!     diffuse: 0.5
+//! ```
+
+use std::{
+    error::Error,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use crate::{
+    color::Color,
+    environment::EnvironmentMap,
+    image::load_image,
+    integrator::Integrator,
+    lighting::{LightLinks, Material, PointLight, ShadingModel},
+    matrix4::Matrix4,
+    object::{Object, Visibility},
+    pattern::Pattern,
+    transform::{
+        rotation_x, rotation_y, rotation_z, scaling, shearing, translation, view_transform,
+    },
+    tuple::Tuple,
+    world::{Camera, World},
+    yaml::{self, Entry, Node, Value},
+};
+
+pub use crate::yaml::Position;
+
+/// Why a scene could not be loaded.
+#[derive(Debug)]
+pub enum SceneError {
+    /// The scene file itself could not be read.
+    Io { path: PathBuf, error: io::Error },
+    /// The scene is not valid, at `position` in `file` (if it came from one).
+    Invalid {
+        file: Option<PathBuf>,
+        position: Position,
+        message: String,
+    },
+}
+
+impl SceneError {
+    pub(crate) fn invalid(position: Position, message: impl Into<String>) -> SceneError {
+        SceneError::Invalid {
+            file: None,
+            position,
+            message: message.into(),
+        }
+    }
+
+    /// Records the file an error was found in, unless it already names one.
+    fn in_file(self, path: &Path) -> SceneError {
+        match self {
+            SceneError::Invalid {
+                file: None,
+                position,
+                message,
+            } => SceneError::Invalid {
+                file: Some(path.to_path_buf()),
+                position,
+                message,
+            },
+            error => error,
+        }
+    }
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SceneError::Io { path, error } => write!(f, "{}: {}", path.display(), error),
+            SceneError::Invalid {
+                file: Some(file),
+                position,
+                message,
+            } => write!(
+                f,
+                "{}:{}:{}: {}",
+                file.display(),
+                position.line,
+                position.column,
+                message
+            ),
+            SceneError::Invalid {
+                file: None,
+                position,
+                message,
+            } => write!(f, "{}: {}", position, message),
+        }
+    }
+}
+
+impl Error for SceneError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SceneError::Io { error, .. } => Some(error),
+            SceneError::Invalid { .. } => None,
+        }
+    }
+}
+
+/// A world and the camera to render it with.
+pub struct Scene {
+    pub world: World,
+    pub camera: Camera,
+}
+
+type Result<T> = std::result::Result<T, SceneError>;
+
+fn expected(node: &Node, what: &str) -> SceneError {
+    let found = match &node.value {
+        Value::Scalar(text) => format!("`{}`", text),
+        _ => node.kind().to_string(),
+    };
+    SceneError::invalid(node.position, format!("expected {}, found {}", what, found))
+}
+
+fn scalar<'a>(node: &'a Node, what: &str) -> Result<&'a str> {
+    match &node.value {
+        Value::Scalar(text) => Ok(text),
+        _ => Err(expected(node, what)),
+    }
+}
+
+fn number(node: &Node) -> Result<f64> {
+    scalar(node, "a number")?
+        .parse::<f64>()
+        .ok()
+        .filter(|n| n.is_finite())
+        .ok_or_else(|| expected(node, "a number"))
+}
+
+fn count(node: &Node) -> Result<u32> {
+    scalar(node, "a positive whole number")?
+        .parse::<u32>()
+        .ok()
+        .filter(|n| *n > 0)
+        .ok_or_else(|| expected(node, "a positive whole number"))
+}
+
+/// An image dimension, which `Canvas` indexes with an `i32`.
+fn dimension(node: &Node) -> Result<i32> {
+    i32::try_from(count(node)?).map_err(|_| {
+        SceneError::invalid(
+            node.position,
+            format!("image dimensions are at most {}", i32::MAX),
+        )
+    })
+}
+
+fn boolean(node: &Node) -> Result<bool> {
+    match scalar(node, "true or false")? {
+        "true" => Ok(true),
+        "false" => Ok(false),
+        _ => Err(expected(node, "true or false")),
+    }
+}
+
+fn sequence<'a>(node: &'a Node, what: &str) -> Result<&'a [Node]> {
+    match &node.value {
+        Value::Sequence(items) => Ok(items),
+        _ => Err(expected(node, what)),
+    }
+}
+
+fn mapping<'a>(node: &'a Node, what: &str) -> Result<&'a [Entry]> {
+    match &node.value {
+        Value::Mapping(entries) => Ok(entries),
+        _ => Err(expected(node, what)),
+    }
+}
+
+fn triple(node: &Node) -> Result<[f64; 3]> {
+    match sequence(node, "[x, y, z]")? {
+        [x, y, z] => Ok([number(x)?, number(y)?, number(z)?]),
+        _ => Err(expected(node, "a list of three numbers")),
+    }
+}
+
+fn point(node: &Node) -> Result<Tuple> {
+    let [x, y, z] = triple(node)?;
+    Ok(Tuple::point(x, y, z))
+}
+
+fn vector(node: &Node) -> Result<Tuple> {
+    let [x, y, z] = triple(node)?;
+    Ok(Tuple::vector(x, y, z))
+}
+
+fn color(defs: Definitions, node: &Node) -> Result<Color> {
+    let [r, g, b] = triple(defs.resolve(node)?.0)?;
+    Ok(Color::new(r, g, b))
+}
+
+fn labels(node: &Node) -> Result<Vec<Arc<str>>> {
+    sequence(node, "a list of names")?
+        .iter()
+        .map(|item| Ok(scalar(item, "a name")?.into()))
+        .collect()
+}
+
+fn unknown_key(entry: &Entry, context: &str) -> SceneError {
+    SceneError::invalid(
+        entry.key_position,
+        format!("unknown key `{}` in {}", entry.key, context),
+    )
+}
+
+fn missing_key(node: &Node, key: &str, context: &str) -> SceneError {
+    SceneError::invalid(node.position, format!("{} is missing `{}`", context, key))
+}
+
+/// Named values from `define` entries. Each definition can only refer to
+/// the ones before it, so references can never form a loop.
+#[derive(Clone, Copy)]
+struct Definitions<'a> {
+    entries: &'a [(String, Node)],
+}
+
+impl<'a> Definitions<'a> {
+    /// The definition of `name` and the definitions it can refer to.
+    fn get(self, name: &str) -> Option<(&'a Node, Definitions<'a>)> {
+        let index = self.entries.iter().rposition(|(n, _)| n == name)?;
+        let earlier = Definitions {
+            entries: &self.entries[..index],
+        };
+        Some((&self.entries[index].1, earlier))
+    }
+
+    /// Where a mapping or list is expected, a name stands for its definition.
+    fn resolve<'n>(self, node: &'n Node) -> Result<(&'n Node, Definitions<'n>)>
+    where
+        'a: 'n,
+    {
+        match &node.value {
+            Value::Scalar(name) => self.get(name).ok_or_else(|| {
+                SceneError::invalid(node.position, format!("`{}` is not defined", name))
+            }),
+            _ => Ok((node, self)),
+        }
+    }
+}
+
+/// A chain of transforms, each applied after the ones listed before it. A
+/// name in the list stands for the transforms it was defined as.
+fn transform(defs: Definitions, node: &Node) -> Result<Matrix4> {
+    let (node, defs) = defs.resolve(node)?;
+    let mut matrix = Matrix4::IDENTITY;
+    for item in sequence(node, "a list of transforms")? {
+        if let Value::Scalar(_) = item.value {
+            matrix = transform(defs, item)?.mul_matrix(&matrix);
+            continue;
+        }
+        let parts = sequence(item, "a transform such as [translate, x, y, z]")?;
+        let (name, args) = match parts.split_first() {
+            Some((name, args)) => (scalar(name, "a transform name")?, args),
+            None => return Err(expected(item, "a transform such as [translate, x, y, z]")),
+        };
+        let args = args.iter().map(number).collect::<Result<Vec<f64>>>()?;
+
+        let arity = match name {
+            "translate" | "scale" => 3,
+            "rotate-x" | "rotate-y" | "rotate-z" => 1,
+            "shear" => 6,
+            _ => {
+                return Err(SceneError::invalid(
+                    parts[0].position,
+                    format!("unknown transform `{}`", name),
+                ))
+            }
+        };
+        if args.len() != arity {
+            return Err(SceneError::invalid(
+                item.position,
+                format!("`{}` takes {} numbers, found {}", name, arity, args.len()),
+            ));
+        }
+
+        let step = match name {
+            "translate" => translation(args[0], args[1], args[2]),
+            "scale" => scaling(args[0], args[1], args[2]),
+            "rotate-x" => rotation_x(args[0]),
+            "rotate-y" => rotation_y(args[0]),
+            "rotate-z" => rotation_z(args[0]),
+            _ => shearing(args[0], args[1], args[2], args[3], args[4], args[5]),
+        };
+        matrix = step.mul_matrix(&matrix);
+    }
+    Ok(matrix)
+}
+
+fn pattern(defs: Definitions, node: &Node) -> Result<Pattern> {
+    let (node, defs) = defs.resolve(node)?;
+    let mut kind = None;
+    let mut colors = None;
+    let mut pattern_transform = Matrix4::IDENTITY;
+    for entry in mapping(node, "a pattern")? {
+        match entry.key.as_str() {
+            "type" => kind = Some(&entry.value),
+            "colors" => match sequence(&entry.value, "a list of two colours")? {
+                [a, b] => colors = Some((color(defs, a)?, color(defs, b)?)),
+                _ => return Err(expected(&entry.value, "a list of two colours")),
+            },
+            "transform" => pattern_transform = transform(defs, &entry.value)?,
+            _ => return Err(unknown_key(entry, "pattern")),
+        }
+    }
+
+    let kind = kind.ok_or_else(|| missing_key(node, "type", "pattern"))?;
+    let (a, b) = colors.ok_or_else(|| missing_key(node, "colors", "pattern"))?;
+    let transform = pattern_transform;
+    match scalar(kind, "a pattern type")? {
+        "stripes" => Ok(Pattern::Stripe { a, b, transform }),
+        "gradient" => Ok(Pattern::Gradient { a, b, transform }),
+        "rings" => Ok(Pattern::Ring { a, b, transform }),
+        "checkers" => Ok(Pattern::Checker { a, b, transform }),
+        _ => Err(expected(kind, "stripes, gradient, rings or checkers")),
+    }
+}
+
+/// A built-in shading model, a mapping for one with parameters, or the name
+/// of a definition holding either.
+fn shading(defs: Definitions, node: &Node) -> Result<ShadingModel> {
+    let node = match &node.value {
+        Value::Scalar(name) => match name.as_str() {
+            "phong" => return Ok(ShadingModel::Phong),
+            "blinn-phong" => return Ok(ShadingModel::BlinnPhong),
+            "microfacet" => {
+                return Ok(ShadingModel::Microfacet {
+                    metallic: 0.0,
+                    roughness: 0.5,
+                })
+            }
+            _ => match defs.get(name) {
+                Some((definition, earlier)) => return shading(earlier, definition),
+                None => {
+                    return Err(expected(
+                        node,
+                        "phong, blinn-phong, microfacet or a defined shading model",
+                    ))
+                }
+            },
+        },
+        _ => node,
+    };
+
+    let mut kind = None;
+    let (mut metallic, mut roughness) = (0.0, 0.5);
+    for entry in mapping(node, "a shading model")? {
+        match entry.key.as_str() {
+            "type" => kind = Some(&entry.value),
+            "metallic" => metallic = number(&entry.value)?,
+            "roughness" => roughness = number(&entry.value)?,
+            _ => return Err(unknown_key(entry, "shading")),
+        }
+    }
+    let kind = kind.ok_or_else(|| missing_key(node, "type", "shading"))?;
+    match scalar(kind, "microfacet")? {
+        "microfacet" => Ok(ShadingModel::Microfacet {
+            metallic,
+            roughness,
+        }),
+        _ => Err(expected(kind, "microfacet")),
+    }
+}
+
+fn material(defs: Definitions, node: &Node) -> Result<Material> {
+    let (node, defs) = defs.resolve(node)?;
+    let entries = mapping(node, "a material")?;
+
+    // Overrides apply on top of the material being extended
+    let mut material = match entries.iter().find(|entry| entry.key == "extend") {
+        Some(base) => {
+            scalar(&base.value, "the name of a material")?;
+            material(defs, &base.value)?
+        }
+        None => Material::DEFAULT,
+    };
+    let mut pattern_key: Option<&Entry> = None;
+    for entry in entries {
+        let value = &entry.value;
+        match entry.key.as_str() {
+            "extend" => {}
+            "color" | "pattern" => {
+                if let Some(previous) = pattern_key {
+                    return Err(SceneError::invalid(
+                        entry.key_position,
+                        format!("`{}` cannot be used with `{}`", entry.key, previous.key),
+                    ));
+                }
+                pattern_key = Some(entry);
+                material.pattern = if entry.key == "color" {
+                    Pattern::Constant {
+                        value: color(defs, value)?,
+                    }
+                } else {
+                    pattern(defs, value)?
+                };
+            }
+            "ambient" => material.ambient = number(value)?,
+            "diffuse" => material.diffuse = number(value)?,
+            "specular" => material.specular = number(value)?,
+            "shininess" => material.shininess = number(value)?,
+            "shading" => material.shading = shading(defs, value)?,
+            "emission" => material.emission = color(defs, value)?,
+            "transparency" => material.transparency = number(value)?,
+            "shadow-catcher" => material.shadow_catcher = boolean(value)?,
+            _ => return Err(unknown_key(entry, "material")),
+        }
+    }
+    Ok(material)
+}
+
+fn visibility(defs: Definitions, node: &Node) -> Result<Visibility> {
+    let (node, _) = defs.resolve(node)?;
+    let mut visibility = Visibility::ALL;
+    for entry in mapping(node, "visibility flags")? {
+        let flag = boolean(&entry.value)?;
+        match entry.key.as_str() {
+            "casts-shadows" => visibility.casts_shadows = flag,
+            "visible-to-camera" => visibility.visible_to_camera = flag,
+            "visible-in-reflections" => visibility.visible_in_reflections = flag,
+            "receives-shadows" => visibility.receives_shadows = flag,
+            _ => return Err(unknown_key(entry, "visibility")),
+        }
+    }
+    Ok(visibility)
+}
+
+/// Builds the scene from the entries of scene files.
+struct Loader {
+    camera: Option<Camera>,
+    lights: Vec<PointLight>,
+    objects: Vec<Object>,
+    environment: Option<EnvironmentMap>,
+    definitions: Vec<(String, Node)>,
+    /// Canonical paths of the files being loaded, outermost first.
+    includes: Vec<PathBuf>,
+}
+
+impl Loader {
+    /// Loads the entries of a document, reading files they name relative to
+    /// `directory`.
+    fn document(&mut self, document: &Node, directory: &Path) -> Result<()> {
+        for item in sequence(document, "a list of entries")? {
+            let entries = mapping(item, "an `add`, `define` or `include` entry")?;
+            let (first, rest) = match entries.split_first() {
+                Some(split) => split,
+                None => return Err(expected(item, "an `add`, `define` or `include` entry")),
+            };
+            match first.key.as_str() {
+                "add" => self.add(item, &first.value, rest, directory)?,
+                "define" => self.define(item, &first.value, rest)?,
+                "include" => match rest.first() {
+                    Some(entry) => return Err(unknown_key(entry, "include")),
+                    None => self.include(&first.value, directory)?,
+                },
+                _ => {
+                    return Err(SceneError::invalid(
+                        first.key_position,
+                        format!(
+                            "expected `add`, `define` or `include`, found `{}`",
+                            first.key
+                        ),
+                    ))
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn add(&mut self, item: &Node, kind: &Node, entries: &[Entry], directory: &Path) -> Result<()> {
+        let defs = Definitions {
+            entries: &self.definitions,
+        };
+        match scalar(kind, "what to add")? {
+            "camera" => {
+                if self.camera.is_some() {
+                    return Err(SceneError::invalid(
+                        item.position,
+                        "the scene already has a camera",
+                    ));
+                }
+                self.camera = Some(camera(item, entries)?);
+            }
+            "light" => self.lights.push(light(defs, item, entries)?),
+            "sphere" => self.objects.push(object(defs, Object::SPHERE, entries)?),
+            "plane" => self.objects.push(object(defs, Object::PLANE, entries)?),
+            "environment" => {
+                if self.environment.is_some() {
+                    return Err(SceneError::invalid(
+                        item.position,
+                        "the scene already has an environment",
+                    ));
+                }
+                self.environment = Some(environment(item, entries, directory)?);
+            }
+            _ => {
+                return Err(expected(
+                    kind,
+                    "camera, light, sphere, plane or environment",
+                ))
+            }
+        }
+        Ok(())
+    }
+
+    fn define(&mut self, item: &Node, name: &Node, entries: &[Entry]) -> Result<()> {
+        let name_text = scalar(name, "a name")?;
+        if self.definitions.iter().any(|(n, _)| n == name_text) {
+            return Err(SceneError::invalid(
+                name.position,
+                format!("`{}` is already defined", name_text),
+            ));
+        }
+
+        let mut base = None;
+        let mut value = None;
+        for entry in entries {
+            match entry.key.as_str() {
+                "extend" => base = Some(&entry.value),
+                "value" => value = Some(&entry.value),
+                _ => return Err(unknown_key(entry, "definition")),
+            }
+        }
+        let value = value.ok_or_else(|| missing_key(item, "value", "definition"))?;
+
+        let value = match base {
+            None => value.clone(),
+            Some(base) => {
+                scalar(base, "the name of a definition")?;
+                let defs = Definitions {
+                    entries: &self.definitions,
+                };
+                let mut merged = mapping(defs.resolve(base)?.0, "a mapping to extend")?.to_vec();
+                for entry in mapping(value, "a mapping")? {
+                    match merged.iter_mut().find(|e| e.key == entry.key) {
+                        Some(existing) => *existing = entry.clone(),
+                        None => merged.push(entry.clone()),
+                    }
+                }
+                Node {
+                    value: Value::Mapping(merged),
+                    position: value.position,
+                }
+            }
+        };
+        self.definitions.push((name_text.to_string(), value));
+        Ok(())
+    }
+
+    fn include(&mut self, file: &Node, directory: &Path) -> Result<()> {
+        let path = directory.join(scalar(file, "a file name")?);
+        let unreadable = |error: io::Error| {
+            SceneError::invalid(
+                file.position,
+                format!("cannot read `{}`: {}", path.display(), error),
+            )
+        };
+        let canonical = path.canonicalize().map_err(unreadable)?;
+        if let Some(start) = self.includes.iter().position(|p| *p == canonical) {
+            let cycle: Vec<String> = self.includes[start..]
+                .iter()
+                .chain([&canonical])
+                .map(|p| {
+                    p.file_name()
+                        .unwrap_or_default()
+                        .to_string_lossy()
+                        .to_string()
+                })
+                .collect();
+            return Err(SceneError::invalid(
+                file.position,
+                format!("include cycle: {}", cycle.join(" -> ")),
+            ));
+        }
+        let source = fs::read_to_string(&path).map_err(unreadable)?;
+
+        self.includes.push(canonical);
+        let directory = path.parent().unwrap_or(Path::new("."));
+        let result = yaml::parse(&source).and_then(|document| self.document(&document, directory));
+        self.includes.pop();
+        result.map_err(|error| error.in_file(&path))
+    }
+}
+
+fn environment(item: &Node, entries: &[Entry], directory: &Path) -> Result<EnvironmentMap> {
+    let mut file = None;
+    let mut light_samples = EnvironmentMap::DEFAULT_LIGHT_SAMPLES;
+    for entry in entries {
+        match entry.key.as_str() {
+            "file" => file = Some(&entry.value),
+            "light-samples" => light_samples = count(&entry.value)? as usize,
+            _ => return Err(unknown_key(entry, "environment")),
+        }
+    }
+
+    let file = file.ok_or_else(|| missing_key(item, "file", "environment"))?;
+    let path = directory.join(scalar(file, "a file name")?);
+    let image = load_image(&path).map_err(|error| {
+        SceneError::invalid(
+            file.position,
+            format!("cannot read `{}`: {}", path.display(), error),
+        )
+    })?;
+    Ok(EnvironmentMap::equirectangular(image).with_light_samples(light_samples))
+}
+
+fn camera(item: &Node, entries: &[Entry]) -> Result<Camera> {
+    let (mut width, mut height, mut field_of_view) = (None, None, None);
+    let (mut from, mut to, mut up) = (None, None, None);
+    let mut samples = 1;
+    let mut integrator = None;
+    let mut max_depth = None;
+    let mut transparent_background = false;
+    for entry in entries {
+        let value = &entry.value;
+        match entry.key.as_str() {
+            "width" => width = Some((dimension(value)?, value)),
+            "height" => height = Some((dimension(value)?, value)),
+            "field-of-view" => field_of_view = Some(number(value)?),
+            "from" => from = Some(point(value)?),
+            "to" => to = Some(point(value)?),
+            "up" => up = Some(vector(value)?),
+            "samples" => samples = count(value)?,
+            "integrator" => integrator = Some(value),
+            "max-depth" => max_depth = Some((count(value)?, entry)),
+            "transparent-background" => transparent_background = boolean(value)?,
+            _ => return Err(unknown_key(entry, "camera")),
+        }
+    }
+
+    let (width, _) = width.ok_or_else(|| missing_key(item, "width", "camera"))?;
+    let (height, height_node) = height.ok_or_else(|| missing_key(item, "height", "camera"))?;
+    if width.checked_mul(height).is_none() {
+        return Err(SceneError::invalid(
+            height_node.position,
+            format!("image of {}x{} pixels is too large", width, height),
+        ));
+    }
+    let field_of_view =
+        field_of_view.ok_or_else(|| missing_key(item, "field-of-view", "camera"))?;
+    let transform = view_transform(
+        &from.ok_or_else(|| missing_key(item, "from", "camera"))?,
+        &to.ok_or_else(|| missing_key(item, "to", "camera"))?,
+        &up.ok_or_else(|| missing_key(item, "up", "camera"))?,
+    );
+
+    let integrator = match integrator.map(|node| (scalar(node, "an integrator"), node)) {
+        None => Integrator::Whitted,
+        Some((Ok("whitted"), _)) => Integrator::Whitted,
+        Some((Ok("path-tracer"), _)) => Integrator::PathTracer {
+            max_depth: max_depth.map_or(Integrator::DEFAULT_MAX_DEPTH, |(depth, _)| depth),
+        },
+        Some((_, node)) => return Err(expected(node, "whitted or path-tracer")),
+    };
+    if let (Integrator::Whitted, Some((_, entry))) = (integrator, max_depth) {
+        return Err(SceneError::invalid(
+            entry.key_position,
+            "`max-depth` needs `integrator: path-tracer`",
+        ));
+    }
+
+    Ok(Camera::new(width, height, field_of_view, transform)
+        .with_samples_per_pixel(samples)
+        .with_integrator(integrator)
+        .with_transparent_background(transparent_background))
+}
+
+fn light(defs: Definitions, item: &Node, entries: &[Entry]) -> Result<PointLight> {
+    let mut position = None;
+    let mut intensity = Color::WHITE;
+    let mut links = LightLinks::ALL;
+    for entry in entries {
+        let value = &entry.value;
+        match entry.key.as_str() {
+            "at" => position = Some(point(value)?),
+            "intensity" => intensity = color(defs, value)?,
+            "include" => links.include = labels(value)?,
+            "exclude" => links.exclude = labels(value)?,
+            _ => return Err(unknown_key(entry, "light")),
+        }
+    }
+    Ok(PointLight {
+        position: position.ok_or_else(|| missing_key(item, "at", "light"))?,
+        intensity,
+        links,
+    })
+}
+
+fn object(defs: Definitions, mut object: Object, entries: &[Entry]) -> Result<Object> {
+    for entry in entries {
+        let value = &entry.value;
+        match entry.key.as_str() {
+            "material" => object.material = material(defs, value)?,
+            "transform" => object.transform = transform(defs, value)?,
+            "visibility" => object.visibility = visibility(defs, value)?,
+            "name" => object.name = Some(scalar(value, "a name")?.into()),
+            "tags" => object.tags = labels(value)?,
+            _ => return Err(unknown_key(entry, "object")),
+        }
+    }
+    Ok(object)
+}
+
+/// Builds a scene from the source of the file at the end of `includes`, if
+/// it came from one.
+fn build(source: &str, directory: &Path, includes: Vec<PathBuf>) -> Result<Scene> {
+    let document = yaml::parse(source)?;
+    let mut loader = Loader {
+        camera: None,
+        lights: Vec::new(),
+        objects: Vec::new(),
+        environment: None,
+        definitions: Vec::new(),
+        includes,
+    };
+    loader.document(&document, directory)?;
+
+    let camera = loader
+        .camera
+        .ok_or_else(|| SceneError::invalid(document.position, "the scene has no camera"))?;
+    Ok(Scene {
+        world: World {
+            lights: loader.lights,
+            objects: loader.objects,
+            environment: loader.environment,
+        },
+        camera,
+    })
+}
+
+/// Parses a scene description. Files it names are found relative to the
+/// working directory.
+pub fn parse_scene(source: &str) -> Result<Scene> {
+    build(source, Path::new("."), Vec::new())
+}
+
+/// Loads the scene file at `path`. Files it names are found relative to the
+/// directory it is in.
+pub fn load_scene<P: AsRef<Path>>(path: P) -> Result<Scene> {
+    let path = path.as_ref();
+    let source = fs::read_to_string(path).map_err(|error| SceneError::Io {
+        path: path.to_path_buf(),
+        error,
+    })?;
+    let directory = path.parent().unwrap_or(Path::new("."));
+    let includes = path.canonicalize().into_iter().collect();
+    build(&source, directory, includes).map_err(|error| error.in_file(path))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{f64::consts::PI, fs, path::PathBuf, sync::Arc};
+
+    use crate::{
+        color::Color,
+        lighting::ShadingModel,
+        pattern::Pattern,
+        ray::Ray,
+        transform::{rotation_z, scaling, translation},
+        tuple::Tuple,
+    };
+
+    use super::{load_scene, parse_scene, SceneError};
+
+    const CAMERA: &str = "\
+- add: camera
+  width: 40
+  height: 20
+  field-of-view: 1.0471975512
+  from: [0, 1, -5]
+  to: [0, 1, 0]
+  up: [0, 1, 0]
+";
+
+    #[test]
+    fn parse_full_scene() {
+        let source = format!(
+            "{}{}",
+            CAMERA,
+            "
+- add: light
+  at: [-10, 10, -10]
+  intensity: [0.5, 0.5, 0.5]
+  exclude: [floor]
+
+- add: plane
+  name: floor
+  material:
+    pattern:
+      type: checkers
+      colors:
+        - [1, 1, 1]
+        - [0, 0, 0]
+      transform:
+        - [scale, 0.5, 0.5, 0.5]
+    specular: 0
+  visibility:
+    casts-shadows: false
+
+- add: sphere
+  tags: [hero, shiny]
+  material:
+    color: [1, 0.2, 0.2]
+    shading:
+      type: microfacet
+      roughness: 0.25
+  transform:
+    - [scale, 2, 2, 2]
+    - [translate, 0, 1, 0]
+"
+        );
+        let scene = parse_scene(&source).unwrap();
+        let world = &scene.world;
+
+        assert_eq!(world.lights.len(), 1);
+        let light = &world.lights[0];
+        assert_abs_diff_eq!(light.position, Tuple::point(-10.0, 10.0, -10.0));
+        assert_abs_diff_eq!(light.intensity, Color::new(0.5, 0.5, 0.5));
+        assert_eq!(light.links.exclude, [Arc::from("floor")]);
+
+        assert_eq!(world.objects.len(), 2);
+        let floor = &world.objects[0];
+        assert_eq!(floor.name.as_deref(), Some("floor"));
+        assert!(!floor.visibility.casts_shadows);
+        assert_eq!(floor.material.specular, 0.0);
+        assert!(matches!(floor.material.pattern, Pattern::Checker { .. }));
+
+        let sphere = &world.objects[1];
+        assert!(sphere.has_label("shiny"));
+        assert_eq!(
+            sphere.material.shading,
+            ShadingModel::Microfacet {
+                metallic: 0.0,
+                roughness: 0.25
+            }
+        );
+        // The scale is applied before the translation
+        assert_abs_diff_eq!(
+            sphere.transform,
+            translation(0.0, 1.0, 0.0).mul_matrix(&scaling(2.0, 2.0, 2.0))
+        );
+
+        // The camera looks at the sphere through the centre of the image
+        let canvas = scene.camera.render(world);
+        assert_eq!((canvas.width, canvas.height), (40, 20));
+        let ray = Ray::new(Tuple::point(0.0, 1.0, -5.0), Tuple::vector(0.0, 0.0, 1.0));
+        assert!(world.intersect(&ray).hit().is_some());
+        assert!(canvas.pixel_at(20, 10).unwrap().red > 0.0);
+    }
+
+    #[test]
+    fn camera_settings() {
+        let source =
+            CAMERA.to_string() + "  samples: 4\n  integrator: path-tracer\n  max-depth: 3\n";
+        assert!(parse_scene(&source).is_ok());
+
+        let rotated = format!(
+            "{}- add: sphere\n  transform:\n    - [rotate-y, {}]\n",
+            CAMERA,
+            PI / 2.0
+        );
+        assert!(parse_scene(&rotated).is_ok());
+    }
+
+    #[test]
+    fn camera_size_must_fit_a_canvas() {
+        let error = |width: &str, height: &str| {
+            let source = CAMERA
+                .replace("width: 40", &format!("width: {}", width))
+                .replace("height: 20", &format!("height: {}", height));
+            parse_scene(&source).err().unwrap().to_string()
+        };
+
+        assert_eq!(
+            error("3000000000", "20"),
+            "line 2, column 10: image dimensions are at most 2147483647"
+        );
+        assert_eq!(
+            error("100000", "100000"),
+            "line 3, column 11: image of 100000x100000 pixels is too large"
+        );
+    }
+
+    #[test]
+    fn scene_errors_point_at_the_problem() {
+        let error = |extra: &str| {
+            parse_scene(&format!("{}{}", CAMERA, extra))
+                .err()
+                .unwrap()
+                .to_string()
+        };
+
+        assert_eq!(
+            error("- add: sphere\n  material:\n    colour: [1, 0, 0]\n"),
+            "line 10, column 5: unknown key `colour` in material"
+        );
+        assert_eq!(
+            error("- add: sphere\n  material:\n    diffuse: lots\n"),
+            "line 10, column 14: expected a number, found `lots`"
+        );
+        assert_eq!(
+            error("- add: light\n  at: [1, 2]\n"),
+            "line 9, column 7: expected a list of three numbers, found a list"
+        );
+        assert_eq!(
+            error("- add: cube\n"),
+            "line 8, column 8: expected camera, light, sphere, plane or environment, found `cube`"
+        );
+        assert_eq!(
+            error("- add: sphere\n  transform:\n    - [spin, 1]\n"),
+            "line 10, column 8: unknown transform `spin`"
+        );
+        assert_eq!(
+            error("- add: light\n  intensity: [1, 1, 1]\n"),
+            "line 8, column 3: light is missing `at`"
+        );
+        assert_eq!(
+            error("- add: sphere\n  material:\n    color: [1, 1, 1]\n    pattern:\n      type: rings\n"),
+            "line 11, column 5: `pattern` cannot be used with `color`"
+        );
+        assert_eq!(
+            parse_scene("- add: light\n  at: [0, 0, 0]\n")
+                .err()
+                .unwrap()
+                .to_string(),
+            "line 1, column 1: the scene has no camera"
+        );
+    }
+
+    #[test]
+    fn load_example_scene() {
+        let scene = load_scene("examples/chapter-10.yaml").unwrap();
+        assert_eq!(scene.world.lights.len(), 1);
+        assert_eq!(scene.world.objects.len(), 6);
+
+        match load_scene("examples/missing.yaml") {
+            Err(SceneError::Io { path, .. }) => assert!(path.ends_with("missing.yaml")),
+            _ => panic!("expected an I/O error"),
+        }
+    }
+
+    #[test]
+    fn definitions_and_extension() {
+        let source = CAMERA.to_string()
+            + "
+- define: base
+  value:
+    color: [1, 1, 1]
+    diffuse: 0.7
+    specular: 0.2
+- define: red
+  value: [1, 0, 0]
+- define: red-base
+  extend: base
+  value:
+    color: red
+- define: lift
+  value:
+    - [scale, 2, 2, 2]
+    - [translate, 0, 1, 0]
+- add: sphere
+  material: red-base
+  transform:
+    - [rotate-z, 1]
+    - lift
+- add: sphere
+  material:
+    extend: red-base
+    diffuse: 0.3
+";
+        let scene = parse_scene(&source).unwrap();
+        let (first, second) = (&scene.world.objects[0], &scene.world.objects[1]);
+
+        assert!(matches!(
+            first.material.pattern,
+            Pattern::Constant { value } if value == Color::new(1.0, 0.0, 0.0)
+        ));
+        assert_eq!(first.material.diffuse, 0.7);
+        assert_abs_diff_eq!(
+            first.transform,
+            translation(0.0, 1.0, 0.0)
+                .mul_matrix(&scaling(2.0, 2.0, 2.0))
+                .mul_matrix(&rotation_z(1.0))
+        );
+
+        assert_eq!(second.material.diffuse, 0.3);
+        assert_eq!(second.material.specular, 0.2);
+        assert!(
+            matches!(second.material.pattern, Pattern::Constant { value } if value.green == 0.0)
+        );
+    }
+
+    #[test]
+    fn shading_definitions() {
+        let source = CAMERA.to_string()
+            + "
+- define: highlights
+  value: blinn-phong
+- define: brushed
+  value:
+    type: microfacet
+    metallic: 1
+- define: steel
+  value: brushed
+- add: sphere
+  material:
+    shading: highlights
+- add: sphere
+  material:
+    shading: steel
+";
+        let scene = parse_scene(&source).unwrap();
+        let (first, second) = (&scene.world.objects[0], &scene.world.objects[1]);
+        assert_eq!(first.material.shading, ShadingModel::BlinnPhong);
+        assert_eq!(
+            second.material.shading,
+            ShadingModel::Microfacet {
+                metallic: 1.0,
+                roughness: 0.5
+            }
+        );
+    }
+
+    #[test]
+    fn definition_errors() {
+        let error = |extra: &str| {
+            parse_scene(&format!("{}{}", CAMERA, extra))
+                .err()
+                .unwrap()
+                .to_string()
+        };
+
+        assert_eq!(
+            error("- add: sphere\n  material: missing\n"),
+            "line 9, column 13: `missing` is not defined"
+        );
+        assert_eq!(
+            error("- define: a\n  value: [1, 0, 0]\n- define: a\n  value: [0, 0, 0]\n"),
+            "line 10, column 11: `a` is already defined"
+        );
+        // A definition cannot refer to itself
+        assert_eq!(
+            error("- define: t\n  value:\n    - t\n- add: sphere\n  transform: t\n"),
+            "line 10, column 7: `t` is not defined"
+        );
+        assert_eq!(
+            error("- define: b\n  extend: nothing\n  value:\n    diffuse: 1\n"),
+            "line 9, column 11: `nothing` is not defined"
+        );
+    }
+
+    /// Writes scene files to a fresh directory for the include tests.
+    fn scene_files(test: &str, files: &[(&str, &str)]) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!("rusty-ray-{}", test));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(directory.join("common")).unwrap();
+        for (name, source) in files {
+            fs::write(directory.join(name), source).unwrap();
+        }
+        directory
+    }
+
+    #[test]
+    fn includes() {
+        let directory = scene_files(
+            "includes",
+            &[
+                (
+                    "main.yaml",
+                    &(CAMERA.to_string()
+                        + "- include: common/lights.yaml\n- add: sphere\n  material: glossy\n"),
+                ),
+                (
+                    "common/lights.yaml",
+                    "- include: materials.yaml\n- add: light\n  at: [0, 5, 0]\n",
+                ),
+                (
+                    "common/materials.yaml",
+                    "- define: glossy\n  value:\n    specular: 1\n",
+                ),
+            ],
+        );
+
+        let scene = load_scene(directory.join("main.yaml")).unwrap();
+        assert_eq!(scene.world.lights.len(), 1);
+        assert_eq!(scene.world.objects[0].material.specular, 1.0);
+    }
+
+    #[test]
+    fn include_errors() {
+        let directory = scene_files(
+            "include-errors",
+            &[
+                (
+                    "main.yaml",
+                    &(CAMERA.to_string() + "- include: loop.yaml\n"),
+                ),
+                ("loop.yaml", "- include: again.yaml\n"),
+                ("again.yaml", "\n- include: loop.yaml\n"),
+                ("bad.yaml", "- include: common/broken.yaml\n"),
+                (
+                    "common/broken.yaml",
+                    "- add: sphere\n  material:\n    shine: 1\n",
+                ),
+                ("missing.yaml", "- include: nowhere.yaml\n"),
+            ],
+        );
+        let error = |name: &str| {
+            load_scene(directory.join(name))
+                .err()
+                .unwrap()
+                .to_string()
+                .replace(&directory.display().to_string(), "DIR")
+        };
+
+        assert_eq!(
+            error("main.yaml"),
+            "DIR/again.yaml:2:12: include cycle: loop.yaml -> again.yaml -> loop.yaml"
+        );
+        assert_eq!(
+            error("bad.yaml"),
+            "DIR/common/broken.yaml:3:5: unknown key `shine` in material"
+        );
+        assert!(error("missing.yaml")
+            .starts_with("DIR/missing.yaml:1:12: cannot read `DIR/nowhere.yaml`"));
+    }
+}
@@ -73,6 +73,7 @@ mod tests {
             object_type: ObjectType::Sphere,
             transform: transform::translation(0.0, 1.0, 0.0),
             material: Material::DEFAULT,
+            ..Object::SPHERE
         };
         assert_abs_diff_eq!(
             s1.normal_at(&Tuple::point(0.0, 1.70711, -0.70711)),
@@ -84,6 +85,7 @@ mod tests {
             material: Material::DEFAULT,
             transform: transform::scaling(1.0, 0.5, 1.0)
                 .mul_matrix(&transform::rotation_z(std::f64::consts::PI / 5.0)),
+            ..Object::SPHERE
         };
         assert_abs_diff_eq!(
             s2.normal_at(&Tuple::point(
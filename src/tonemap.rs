@@ -0,0 +1,162 @@
+use crate::color::Color;
+
+/// Compresses linear radiance into the displayable 0.0 to 1.0 range.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ToneMap {
+    /// Cuts off everything above 1.0.
+    Clamp,
+    /// `c / (1 + c)` per channel, which never quite reaches white.
+    Reinhard,
+    /// Narkowicz's fit of the ACES filmic curve, with a soft toe and
+    /// shoulder.
+    Aces,
+}
+
+impl ToneMap {
+    fn apply(&self, value: f64) -> f64 {
+        let value = value.max(0.0);
+        let mapped = match self {
+            ToneMap::Clamp => value,
+            ToneMap::Reinhard => value / (1.0 + value),
+            ToneMap::Aces => {
+                (value * (2.51 * value + 0.03)) / (value * (2.43 * value + 0.59) + 0.14)
+            }
+        };
+        mapped.clamp(0.0, 1.0)
+    }
+}
+
+/// Transfer function from linear values to the values stored in the image.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Gamma {
+    Linear,
+    /// The piecewise sRGB curve, for images shown on ordinary displays.
+    Srgb,
+    /// `value^(1 / gamma)`, built with [`Gamma::power`].
+    Power(Exponent),
+}
+
+/// A gamma that is positive and finite.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Exponent(f64);
+
+impl Exponent {
+    pub fn value(&self) -> f64 {
+        self.0
+    }
+}
+
+impl Gamma {
+    /// `value^(1 / gamma)`, or `None` unless `gamma` is positive and finite.
+    pub fn power(gamma: f64) -> Option<Gamma> {
+        (gamma.is_finite() && gamma > 0.0).then_some(Gamma::Power(Exponent(gamma)))
+    }
+
+    fn encode(&self, value: f64) -> f64 {
+        match self {
+            Gamma::Linear => value,
+            Gamma::Srgb => {
+                if value <= 0.003_130_8 {
+                    value * 12.92
+                } else {
+                    1.055 * value.powf(1.0 / 2.4) - 0.055
+                }
+            }
+            Gamma::Power(gamma) => value.powf(1.0 / gamma.value()),
+        }
+    }
+}
+
+/// Turns rendered radiance into display colours when an image is saved:
+/// scales by the exposure, tone maps, then gamma encodes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct OutputTransform {
+    /// Brightness adjustment in stops; each stop doubles the radiance.
+    pub exposure: f64,
+    pub tone_map: ToneMap,
+    pub gamma: Gamma,
+}
+
+impl OutputTransform {
+    /// Linear values clamped to 1.0, leaving colours as rendered.
+    pub const DEFAULT: OutputTransform = OutputTransform {
+        exposure: 0.0,
+        tone_map: ToneMap::Clamp,
+        gamma: Gamma::Linear,
+    };
+
+    /// Display colour of `color`, with every channel between 0.0 and 1.0.
+    pub fn apply(&self, color: &Color) -> Color {
+        let scale = 2f64.powf(self.exposure);
+        let channel = |value: f64| self.gamma.encode(self.tone_map.apply(value * scale));
+        Color::new(
+            channel(color.red),
+            channel(color.green),
+            channel(color.blue),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::color::Color;
+
+    use super::{Gamma, OutputTransform, ToneMap};
+
+    #[test]
+    fn default_transform_clamps() {
+        let t = OutputTransform::DEFAULT;
+        assert_abs_diff_eq!(
+            t.apply(&Color::new(0.25, 1.5, -0.5)),
+            Color::new(0.25, 1.0, 0.0)
+        );
+    }
+
+    #[test]
+    fn exposure_in_stops() {
+        let t = OutputTransform {
+            exposure: 1.0,
+            ..OutputTransform::DEFAULT
+        };
+        assert_abs_diff_eq!(
+            t.apply(&Color::new(0.25, 0.5, 1.0)),
+            Color::new(0.5, 1.0, 1.0)
+        );
+        let t = OutputTransform {
+            exposure: -2.0,
+            ..OutputTransform::DEFAULT
+        };
+        assert_abs_diff_eq!(t.apply(&Color::WHITE), Color::new(0.25, 0.25, 0.25));
+    }
+
+    #[test]
+    fn tone_map_operators() {
+        assert_abs_diff_eq!(ToneMap::Reinhard.apply(1.0), 0.5);
+        assert_abs_diff_eq!(ToneMap::Reinhard.apply(3.0), 0.75);
+        assert_abs_diff_eq!(ToneMap::Aces.apply(0.0), 0.0);
+        assert_abs_diff_eq!(ToneMap::Aces.apply(1.0), 0.8038, epsilon = 1e-4);
+        assert_abs_diff_eq!(ToneMap::Aces.apply(100.0), 1.0);
+        // Both curves keep increasing with the input
+        for op in [ToneMap::Reinhard, ToneMap::Aces] {
+            assert!(op.apply(0.5) < op.apply(0.6));
+        }
+    }
+
+    #[test]
+    fn gamma_encoding() {
+        assert_abs_diff_eq!(Gamma::Srgb.encode(0.0), 0.0);
+        assert_abs_diff_eq!(Gamma::Srgb.encode(0.001), 0.01292);
+        assert_abs_diff_eq!(Gamma::Srgb.encode(0.5), 0.7354, epsilon = 1e-4);
+        assert_abs_diff_eq!(Gamma::Srgb.encode(1.0), 1.0);
+        assert_abs_diff_eq!(Gamma::power(2.0).unwrap().encode(0.25), 0.5);
+        assert_abs_diff_eq!(Gamma::Linear.encode(0.3), 0.3);
+    }
+
+    #[test]
+    fn gamma_power_must_be_positive_and_finite() {
+        assert!(matches!(Gamma::power(2.2), Some(Gamma::Power(gamma)) if gamma.value() == 2.2));
+        for gamma in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            assert_eq!(Gamma::power(gamma), None);
+        }
+    }
+}
@@ -29,7 +29,6 @@ pub fn rotation_y(rads: f64) -> Matrix4 {
         .row(0.0, 0.0, 0.0, 1.0)
 }
 
-#[allow(dead_code)]
 pub fn rotation_z(rads: f64) -> Matrix4 {
     Matrix4::builder(f64::cos(rads), -f64::sin(rads), 0.0, 0.0)
         .row(f64::sin(rads), f64::cos(rads), 0.0, 0.0)
@@ -37,7 +36,6 @@ pub fn rotation_z(rads: f64) -> Matrix4 {
         .row(0.0, 0.0, 0.0, 1.0)
 }
 
-#[allow(dead_code)]
 pub fn shearing(xy: f64, xz: f64, yx: f64, yz: f64, zx: f64, zy: f64) -> Matrix4 {
     Matrix4::builder(1.0, xy, xz, 0.0)
         .row(yx, 1.0, yz, 0.0)
@@ -45,6 +43,35 @@ pub fn shearing(xy: f64, xz: f64, yx: f64, yz: f64, zx: f64, zy: f64) -> Matrix4
         .row(0.0, 0.0, 0.0, 1.0)
 }
 
+/// Chainable transforms that apply in reading order:
+/// `Matrix4::IDENTITY.rotate_x(a).scale(2.0, 2.0, 2.0).translate(0.0, 1.0, 0.0)`
+/// rotates first and translates last.
+impl Matrix4 {
+    pub fn translate(&self, x: f64, y: f64, z: f64) -> Matrix4 {
+        translation(x, y, z).mul_matrix(self)
+    }
+
+    pub fn scale(&self, x: f64, y: f64, z: f64) -> Matrix4 {
+        scaling(x, y, z).mul_matrix(self)
+    }
+
+    pub fn rotate_x(&self, rads: f64) -> Matrix4 {
+        rotation_x(rads).mul_matrix(self)
+    }
+
+    pub fn rotate_y(&self, rads: f64) -> Matrix4 {
+        rotation_y(rads).mul_matrix(self)
+    }
+
+    pub fn rotate_z(&self, rads: f64) -> Matrix4 {
+        rotation_z(rads).mul_matrix(self)
+    }
+
+    pub fn shear(&self, xy: f64, xz: f64, yx: f64, yz: f64, zx: f64, zy: f64) -> Matrix4 {
+        shearing(xy, xz, yx, yz, zx, zy).mul_matrix(self)
+    }
+}
+
 pub fn view_transform(from: &Tuple, to: &Tuple, up: &Tuple) -> Matrix4 {
     let forward = to.subtract(&from).normalize();
     let left = forward.cross(&up.normalize());
@@ -229,4 +256,24 @@ mod tests {
             .row(0.00000, 0.00000, 0.00000, 1.00000);
         assert_abs_diff_eq!(view_transform(&from, &to, &up), expected);
     }
+
+    #[test]
+    fn chained_transforms_apply_in_reading_order() {
+        let p = Tuple::point(1.0, 0.0, 1.0);
+        let t = Matrix4::IDENTITY
+            .rotate_x(PI / 2.0)
+            .scale(5.0, 5.0, 5.0)
+            .translate(10.0, 5.0, 7.0);
+        assert_abs_diff_eq!(t.mul_tuple(&p), Tuple::point(15.0, 0.0, 7.0));
+
+        let sheared = Matrix4::IDENTITY.shear(1.0, 0.0, 0.0, 0.0, 0.0, 0.0);
+        assert_abs_diff_eq!(
+            sheared.mul_tuple(&Tuple::point(2.0, 3.0, 4.0)),
+            Tuple::point(5.0, 3.0, 4.0)
+        );
+        assert_abs_diff_eq!(
+            Matrix4::IDENTITY.rotate_y(PI / 2.0).rotate_z(PI / 2.0),
+            rotation_z(PI / 2.0).mul_matrix(&rotation_y(PI / 2.0))
+        );
+    }
 }
@@ -1,87 +1,281 @@
+use std::f64::consts::PI;
+
 use crate::{
-    color::{Canvas, Color},
-    lighting::{lighting, PointLight},
+    color::Color,
+    environment::EnvironmentMap,
+    integrator::Integrator,
+    lighting::{ambient_lighting, direct_lighting, environment_lighting, PointLight},
     matrix4::Matrix4,
     object::Object,
     ray::{Intersection, Intersections, Ray},
+    sampling::{sample_seed, Rng},
+    transform::view_transform,
     tuple::Tuple,
 };
 
 pub struct World {
-    pub light: PointLight,
+    pub lights: Vec<PointLight>,
     pub objects: Vec<Object>,
+    pub environment: Option<EnvironmentMap>,
 }
 
-pub struct PreparedComputations<'a> {
-    point: Tuple,
-    over_point: Tuple,
-    eye: Tuple,
-    normal: Tuple,
-    obj: &'a Object,
+pub(crate) struct PreparedComputations<'a> {
+    pub(crate) point: Tuple,
+    pub(crate) over_point: Tuple,
+    pub(crate) eye: Tuple,
+    pub(crate) normal: Tuple,
+    pub(crate) obj: &'a Object,
+    // Only read by the tests
     #[allow(dead_code)]
-    is_inside: bool,
+    pub(crate) is_inside: bool,
 }
 
 impl World {
     pub const EPSILON: f64 = 0.00001;
 
-    pub fn new<'a>(light: PointLight, objects: Vec<Object>) -> World {
-        World { light, objects }
+    pub fn new(light: PointLight, objects: Vec<Object>) -> World {
+        World {
+            lights: vec![light],
+            objects,
+            environment: None,
+        }
     }
 
     pub fn objects(&self, objects: Vec<Object>) -> World {
         World {
-            light: self.light,
+            lights: self.lights.clone(),
             objects,
+            environment: None,
+        }
+    }
+
+    /// Adds another light to the world.
+    pub fn with_light(mut self, light: PointLight) -> World {
+        self.lights.push(light);
+        self
+    }
+
+    pub fn with_environment(self, environment: EnvironmentMap) -> World {
+        World {
+            environment: Some(environment),
+            ..self
         }
     }
 
-    pub fn intersect(&self, ray: &Ray) -> Intersections {
-        let intersections = self.objects.iter().flat_map(|object| {
-            let intersections = Intersections::intersect(object, ray);
-            intersections.values
-        });
+    /// Intersections with every object that can be seen by this kind of ray.
+    pub(crate) fn intersect(&self, ray: &Ray) -> Intersections {
+        let intersections = self
+            .objects
+            .iter()
+            .filter(|object| object.is_seen_by(ray.kind))
+            .flat_map(|object| {
+                let intersections = Intersections::intersect(object, ray);
+                intersections.values
+            });
         let mut values = Vec::from_iter(intersections);
         values.sort_by(|a, b| a.t.partial_cmp(&b.t).unwrap());
         Intersections { values: values }
     }
 
-    pub fn shade_hit(&self, comps: &PreparedComputations) -> Color {
-        let is_in_shadow = self.is_shadowed(comps.over_point);
-        lighting(
-            comps.obj,
-            &self.light,
-            &comps.point,
-            &comps.eye,
-            &comps.normal,
-            is_in_shadow,
-        )
+    /// Shades a hit with every light linked to the object. Without an
+    /// environment each light also adds its ambient term; with one, the
+    /// environment's irradiance replaces them.
+    pub(crate) fn shade_hit(&self, comps: &PreparedComputations) -> Color {
+        let direct = self
+            .lights
+            .iter()
+            .filter(|light| light.illuminates(comps.obj))
+            .map(|light| {
+                let light_transmission = self.light_reaching(light, comps);
+                let direct = direct_lighting(
+                    comps.obj,
+                    light,
+                    &comps.point,
+                    &comps.eye,
+                    &comps.normal,
+                    &light_transmission,
+                );
+                if self.environment.is_some() {
+                    direct
+                } else {
+                    direct.add(&ambient_lighting(comps.obj, light, &comps.point))
+                }
+            })
+            .fold(Color::BLACK, |acc, c| acc.add(&c));
+
+        let indirect = match &self.environment {
+            None => Color::BLACK,
+            Some(environment) => {
+                let irradiance = self.environment_irradiance(environment, comps, true);
+                environment_lighting(
+                    comps.obj,
+                    &comps.point,
+                    &comps.eye,
+                    &comps.normal,
+                    &irradiance,
+                )
+            }
+        };
+
+        comps.obj.material.emission.add(&direct).add(&indirect)
     }
 
-    pub fn color_at(&self, ray: &Ray) -> Color {
-        let intersections = self.intersect(&ray);
+    fn environment_irradiance(
+        &self,
+        environment: &EnvironmentMap,
+        comps: &PreparedComputations,
+        shadows: bool,
+    ) -> Color {
+        let samples = environment.light_samples();
+        if samples.is_empty() {
+            return Color::BLACK;
+        }
 
-        match intersections.hit() {
+        let total = samples
+            .iter()
+            .filter_map(|sample| {
+                let cos_theta = sample.direction.dot(&comps.normal);
+                if cos_theta <= 0.0 {
+                    return None;
+                }
+                let transmission = if shadows && comps.obj.visibility.receives_shadows {
+                    self.transmission(comps.over_point, sample.direction, f64::INFINITY)
+                } else {
+                    Color::WHITE
+                };
+                Some(
+                    sample
+                        .radiance
+                        .mul(&transmission)
+                        .scalar_mul(cos_theta / sample.pdf),
+                )
+            })
+            .fold(Color::BLACK, |acc, c| acc.add(&c));
+
+        total.scalar_mul(1.0 / samples.len() as f64)
+    }
+
+    #[cfg(test)]
+    pub(crate) fn color_at(&self, ray: &Ray) -> Color {
+        let intersections = self.intersect(ray);
+        self.color_for_hit(intersections.hit(), ray)
+    }
+
+    /// Colour seen along `ray`, given its closest hit.
+    pub(crate) fn color_for_hit(&self, hit: Option<&Intersection>, ray: &Ray) -> Color {
+        match hit {
             Some(i) => {
                 let comps = World::prepare_computations(i, ray);
                 self.shade_hit(&comps)
             }
+            None => self.background(&ray.direction),
+        }
+    }
+
+    /// Colour seen by rays that miss every object.
+    pub(crate) fn background(&self, direction: &Tuple) -> Color {
+        match &self.environment {
+            Some(environment) => environment.radiance(direction),
             None => Color::BLACK,
         }
     }
 
-    fn is_shadowed(&self, point: Tuple) -> bool {
-        let v = self.light.position.subtract(&point);
+    /// How much a shadow catcher is darkened at a hit: the fraction of the
+    /// direct and environment light it would receive that is blocked.
+    pub(crate) fn shadow_catcher_alpha(&self, comps: &PreparedComputations) -> f64 {
+        let (mut lit, mut unshadowed) = (0.0, 0.0);
+        for light in self
+            .lights
+            .iter()
+            .filter(|light| light.illuminates(comps.obj))
+        {
+            let direct = |light_transmission: &Color| {
+                direct_lighting(
+                    comps.obj,
+                    light,
+                    &comps.point,
+                    &comps.eye,
+                    &comps.normal,
+                    light_transmission,
+                )
+                .luminance()
+            };
+            lit += direct(&self.light_reaching(light, comps));
+            unshadowed += direct(&Color::WHITE);
+        }
+
+        if let Some(environment) = &self.environment {
+            lit += self
+                .environment_irradiance(environment, comps, true)
+                .luminance();
+            unshadowed += self
+                .environment_irradiance(environment, comps, false)
+                .luminance();
+        }
+
+        if unshadowed <= 0.0 {
+            0.0
+        } else {
+            (1.0 - lit / unshadowed).clamp(0.0, 1.0)
+        }
+    }
+
+    /// Whether no light at all reaches `point`.
+    #[cfg(test)]
+    pub(crate) fn is_shadowed(&self, point: Tuple) -> bool {
+        self.lights
+            .iter()
+            .all(|light| self.light_transmission(light, point) == Color::BLACK)
+    }
+
+    /// Fraction of the light's intensity, per channel, that reaches `point`.
+    pub(crate) fn light_transmission(&self, light: &PointLight, point: Tuple) -> Color {
+        let v = light.position.subtract(&point);
         let distance = v.magnitude();
         let direction = v.normalize();
-        let r = Ray::new(point, direction);
-        let intersections = self.intersect(&r);
-        let h = intersections.hit();
+        self.transmission(point, direction, distance - World::EPSILON)
+    }
+
+    /// Light transmission at a hit, or all of the light for objects that do
+    /// not receive shadows.
+    pub(crate) fn light_reaching(&self, light: &PointLight, comps: &PreparedComputations) -> Color {
+        if comps.obj.visibility.receives_shadows {
+            self.light_transmission(light, comps.over_point)
+        } else {
+            Color::WHITE
+        }
+    }
+
+    /// Product of the transmission of every shadow casting object a shadow
+    /// ray from `point` passes through before `distance`. Each object counts
+    /// once, however many of its surfaces the ray crosses.
+    pub(crate) fn transmission(&self, point: Tuple, direction: Tuple, distance: f64) -> Color {
+        let ray = Ray::shadow(point, direction);
+        let intersections = self.intersect(&ray);
+        let mut passed: Vec<&Object> = Vec::new();
+        let mut transmission = Color::WHITE;
+
+        for i in intersections
+            .values
+            .iter()
+            .filter(|i| i.t >= 0.0 && i.t < distance)
+        {
+            if passed.iter().any(|o| std::ptr::eq(*o, i.obj)) {
+                continue;
+            }
+            passed.push(i.obj);
 
-        h.map(|i| distance - i.t > World::EPSILON) == Some(true)
+            let material = i.obj.material;
+            let tint = material.pattern.color_at(i.obj, &ray.position(i.t));
+            transmission = transmission.mul(&tint.scalar_mul(material.transparency));
+            if transmission == Color::BLACK {
+                break;
+            }
+        }
+        transmission
     }
 
-    fn prepare_computations<'a>(
+    pub(crate) fn prepare_computations<'a>(
         intersection: &Intersection<'a>,
         ray: &Ray,
     ) -> PreparedComputations<'a> {
@@ -111,10 +305,71 @@ impl World {
 pub struct Camera {
     hsize: i32,
     vsize: i32,
+    field_of_view: f64,
+    transform: Matrix4,
     half_width: f64,
     half_height: f64,
     pixel_size: f64,
     inv_transform: Matrix4,
+    samples_per_pixel: u32,
+    integrator: Integrator,
+    transparent_background: bool,
+    threads: usize,
+}
+
+pub struct CameraBuilder {
+    hsize: i32,
+    vsize: i32,
+    field_of_view: f64,
+    transform: Matrix4,
+    samples_per_pixel: u32,
+    integrator: Integrator,
+    transparent_background: bool,
+    threads: usize,
+}
+
+impl CameraBuilder {
+    pub fn field_of_view(mut self, field_of_view: f64) -> CameraBuilder {
+        self.field_of_view = field_of_view;
+        self
+    }
+
+    pub fn transform(mut self, transform: Matrix4) -> CameraBuilder {
+        self.transform = transform;
+        self
+    }
+
+    pub fn look_at(self, from: Tuple, to: Tuple, up: Tuple) -> CameraBuilder {
+        self.transform(view_transform(&from, &to, &up))
+    }
+
+    pub fn samples_per_pixel(mut self, samples_per_pixel: u32) -> CameraBuilder {
+        self.samples_per_pixel = samples_per_pixel;
+        self
+    }
+
+    pub fn integrator(mut self, integrator: Integrator) -> CameraBuilder {
+        self.integrator = integrator;
+        self
+    }
+
+    pub fn transparent_background(mut self, transparent_background: bool) -> CameraBuilder {
+        self.transparent_background = transparent_background;
+        self
+    }
+
+    pub fn threads(mut self, threads: usize) -> CameraBuilder {
+        self.threads = threads;
+        self
+    }
+
+    pub fn build(self) -> Camera {
+        Camera::new(self.hsize, self.vsize, self.field_of_view, self.transform)
+            .with_samples_per_pixel(self.samples_per_pixel)
+            .with_integrator(self.integrator)
+            .with_transparent_background(self.transparent_background)
+            .with_threads(self.threads)
+    }
 }
 
 impl Camera {
@@ -138,16 +393,119 @@ impl Camera {
         Camera {
             hsize,
             vsize,
+            field_of_view,
+            transform,
             half_width,
             half_height,
             pixel_size,
             inv_transform,
+            samples_per_pixel: 1,
+            integrator: Integrator::Whitted,
+            transparent_background: false,
+            threads: 1,
+        }
+    }
+
+    /// A camera at the origin looking down negative z with a 60° field of
+    /// view, one sample per pixel and the Whitted integrator.
+    pub fn builder(hsize: i32, vsize: i32) -> CameraBuilder {
+        CameraBuilder {
+            hsize,
+            vsize,
+            field_of_view: PI / 3.0,
+            transform: Matrix4::IDENTITY,
+            samples_per_pixel: 1,
+            integrator: Integrator::Whitted,
+            transparent_background: false,
+            threads: 1,
+        }
+    }
+
+    pub fn hsize(&self) -> i32 {
+        self.hsize
+    }
+
+    pub fn vsize(&self) -> i32 {
+        self.vsize
+    }
+
+    pub fn integrator(&self) -> Integrator {
+        self.integrator
+    }
+
+    pub fn transparent_background(&self) -> bool {
+        self.transparent_background
+    }
+
+    pub fn samples_per_pixel(&self) -> u32 {
+        self.samples_per_pixel
+    }
+
+    pub fn threads(&self) -> usize {
+        self.threads
+    }
+
+    /// The same view rendered at a different resolution.
+    pub fn with_size(self, hsize: i32, vsize: i32) -> Camera {
+        Camera {
+            samples_per_pixel: self.samples_per_pixel,
+            integrator: self.integrator,
+            transparent_background: self.transparent_background,
+            threads: self.threads,
+            ..Camera::new(hsize, vsize, self.field_of_view, self.transform)
+        }
+    }
+
+    pub fn with_field_of_view(self, field_of_view: f64) -> Camera {
+        Camera {
+            samples_per_pixel: self.samples_per_pixel,
+            integrator: self.integrator,
+            transparent_background: self.transparent_background,
+            threads: self.threads,
+            ..Camera::new(self.hsize, self.vsize, field_of_view, self.transform)
+        }
+    }
+
+    /// Renders rows on this many threads. Every pixel has its own random
+    /// sequence, so the image does not depend on the thread count.
+    pub fn with_threads(self, threads: usize) -> Camera {
+        Camera {
+            threads: threads.max(1),
+            ..self
+        }
+    }
+
+    pub fn with_integrator(self, integrator: Integrator) -> Camera {
+        Camera { integrator, ..self }
+    }
+
+    /// With more than one sample, each is taken at a random position within
+    /// the pixel and the results are averaged.
+    pub fn with_samples_per_pixel(self, samples_per_pixel: u32) -> Camera {
+        Camera {
+            samples_per_pixel: samples_per_pixel.max(1),
+            ..self
+        }
+    }
+
+    /// Renders misses as fully transparent instead of the world's background,
+    /// so the image can be composited over another one.
+    pub fn with_transparent_background(self, transparent_background: bool) -> Camera {
+        Camera {
+            transparent_background,
+            ..self
         }
     }
 
     fn ray_for_pixel(&self, x: i32, y: i32) -> Ray {
-        let x_offset = (f64::from(x) + 0.5) * self.pixel_size;
-        let y_offset = (f64::from(y) + 0.5) * self.pixel_size;
+        self.ray_for_subpixel(x, y, 0.5, 0.5)
+    }
+
+    /// Ray through the point `(dx, dy)` within the pixel, where both offsets
+    /// range over `[0, 1)`.
+    fn ray_for_subpixel(&self, x: i32, y: i32, dx: f64, dy: f64) -> Ray {
+        let x_offset = (f64::from(x) + dx) * self.pixel_size;
+        let y_offset = (f64::from(y) + dy) * self.pixel_size;
 
         let world_x = self.half_width - x_offset;
         let world_y = self.half_height - y_offset;
@@ -161,17 +519,27 @@ impl Camera {
         Ray::new(origin, direction)
     }
 
-    pub fn render(&self, world: &World) -> Canvas {
-        let mut image = Canvas::new(self.hsize, self.vsize);
+    /// Colour and alpha of the numbered sample of a pixel. Each sample has
+    /// its own random sequence, so it comes out the same however the samples
+    /// are split between passes and threads. With one sample per pixel it
+    /// goes through the centre of the pixel.
+    pub(crate) fn sample_pixel(&self, world: &World, x: i32, y: i32, sample: u32) -> (Color, f64) {
+        let mut rng = Rng::new(sample_seed(x, y, sample));
+        let ray = if self.samples_per_pixel == 1 {
+            self.ray_for_pixel(x, y)
+        } else {
+            self.ray_for_subpixel(x, y, rng.next_f64(), rng.next_f64())
+        };
+        self.sample(world, &ray, &mut rng)
+    }
 
-        for y in 0..self.vsize {
-            for x in 0..self.hsize {
-                let ray = self.ray_for_pixel(x, y);
-                let c = world.color_at(&ray);
-                image.write_pixel(x, y, &c);
-            }
+    /// Colour and alpha of one camera ray.
+    fn sample(&self, world: &World, ray: &Ray, rng: &mut Rng) -> (Color, f64) {
+        if self.transparent_background {
+            self.integrator.trace(world, ray, rng)
+        } else {
+            (self.integrator.radiance(world, ray, rng), 1.0)
         }
-        image
     }
 }
 
@@ -180,12 +548,14 @@ mod tests {
     use std::f64::consts::PI;
 
     use crate::{
-        color::Color,
-        lighting::{Material, PointLight},
+        color::{Canvas, Color},
+        environment::EnvironmentMap,
+        integrator::Integrator,
+        lighting::{LightLinks, Material, PointLight, ShadingModel},
         matrix4::Matrix4,
-        object::{Object, ObjectType},
+        object::{Object, ObjectType, Visibility},
         pattern::Pattern,
-        ray::{Intersection, Ray},
+        ray::{Intersection, Ray, RayKind},
         transform::{self, rotation_y, scaling, translation, view_transform},
         tuple::Tuple,
     };
@@ -195,6 +565,7 @@ mod tests {
     const DEFAULT_LIGHT: PointLight = PointLight {
         position: Tuple::point(-10.0, 10.0, -10.0),
         intensity: Color::WHITE,
+        links: LightLinks::ALL,
     };
 
     const DEFAULT_SPHERE_1: Object = Object {
@@ -207,8 +578,12 @@ mod tests {
             diffuse: 0.7,
             specular: 0.2,
             shininess: Material::DEFAULT_SHININESS,
+            ..Material::DEFAULT
         },
         transform: Matrix4::IDENTITY,
+        visibility: Visibility::ALL,
+        name: None,
+        tags: Vec::new(),
     };
 
     const DEFAULT_SPHERE_2: Object = Object {
@@ -220,13 +595,18 @@ mod tests {
             diffuse: Material::DEFAULT_DIFFUSE,
             specular: Material::DEFAULT_SPECULAR,
             shininess: Material::DEFAULT_SHININESS,
+            ..Material::DEFAULT
         },
+        visibility: Visibility::ALL,
+        name: None,
+        tags: Vec::new(),
     };
 
     fn default() -> World {
         World {
-            light: DEFAULT_LIGHT,
+            lights: vec![DEFAULT_LIGHT],
             objects: vec![DEFAULT_SPHERE_1, DEFAULT_SPHERE_2],
+            environment: None,
         }
     }
 
@@ -247,9 +627,10 @@ mod tests {
     #[test]
     fn precompute_intersection() {
         let ray = Ray::new(Tuple::point(0.0, 0.0, -5.0), Tuple::vector(0.0, 0.0, 1.0));
+        let sphere = Object::SPHERE;
         let comps = World::prepare_computations(
             &Intersection {
-                obj: &Object::SPHERE,
+                obj: &sphere,
                 t: 4.0,
             },
             &ray,
@@ -264,9 +645,10 @@ mod tests {
     #[test]
     fn precompute_intersection_inside() {
         let ray = Ray::new(Tuple::point(0.0, 0.0, 0.0), Tuple::vector(0.0, 0.0, 1.0));
+        let sphere = Object::SPHERE;
         let comps = World::prepare_computations(
             &Intersection {
-                obj: &Object::SPHERE,
+                obj: &sphere,
                 t: 1.0,
             },
             &ray,
@@ -285,7 +667,8 @@ mod tests {
         let s = Object {
             object_type: ObjectType::Sphere,
             material: Material::DEFAULT,
-            transform: translation(0.0, 0.0, 1.0)
+            transform: translation(0.0, 0.0, 1.0),
+            ..Object::SPHERE
         };
         let i = Intersection { t: 5.0, obj: &s };
         let comps = World::prepare_computations(&i, &ray);
@@ -315,15 +698,18 @@ mod tests {
     fn shade_intersection_inside() {
         let world = World {
             objects: vec![DEFAULT_SPHERE_1, DEFAULT_SPHERE_2],
-            light: PointLight {
+            lights: vec![PointLight {
                 position: Tuple::point(0.0, 0.25, 0.0),
                 intensity: Color::WHITE,
-            },
+                links: LightLinks::ALL,
+            }],
+            environment: None,
         };
         let ray = Ray::new(Tuple::point(0.0, 0.0, 0.0), Tuple::vector(0.0, 0.0, 1.0));
+        let sphere = Object::SPHERE;
         let comps = World::prepare_computations(
             &Intersection {
-                obj: &Object::SPHERE,
+                obj: &sphere,
                 t: 0.5,
             },
             &ray,
@@ -339,12 +725,14 @@ mod tests {
         let light = PointLight {
             position: Tuple::point(0.0, 0.0, -10.0),
             intensity: Color::WHITE,
+            links: LightLinks::ALL,
         };
 
         let s2 = Object {
             object_type: ObjectType::Sphere,
             material: Material::DEFAULT,
-            transform: translation(0.0, 0.0, 10.0)
+            transform: translation(0.0, 0.0, 10.0),
+            ..Object::SPHERE
         };
         let r = Ray::new(Tuple::point(0.0, 0.0, 5.0), Tuple::vector(0.0, 0.0, 1.0));
 
@@ -382,8 +770,10 @@ mod tests {
                 diffuse: 0.7,
                 specular: 0.2,
                 shininess: Material::DEFAULT_SHININESS,
+                ..Material::DEFAULT
             },
-            transform: Matrix4::IDENTITY
+            transform: Matrix4::IDENTITY,
+            ..Object::SPHERE
         };
 
         let inner = Object {
@@ -395,7 +785,9 @@ mod tests {
                 diffuse: Material::DEFAULT_DIFFUSE,
                 specular: Material::DEFAULT_SPECULAR,
                 shininess: Material::DEFAULT_SHININESS,
+                ..Material::DEFAULT
             },
+            ..Object::SPHERE
         };
 
         let world = World::new(DEFAULT_LIGHT, vec![outer, inner]);
@@ -456,6 +848,174 @@ mod tests {
         assert_abs_diff_eq!(*image.pixel_at(5, 5).unwrap(), expected_color);
     }
 
+    #[test]
+    fn camera_render_samples_per_pixel() {
+        let transform = view_transform(
+            &Tuple::point(0.0, 0.0, -5.0),
+            &Tuple::point(0.0, 0.0, 0.0),
+            &Tuple::vector(0.0, 1.0, 0.0),
+        );
+        let camera = Camera::new(11, 11, PI / 2.0, transform)
+            .with_integrator(Integrator::PathTracer { max_depth: 1 });
+
+        // Only direct light is followed, so the centre matches the Whitted
+        // render without its ambient term
+        let expected_color =
+            Color::new(0.38066, 0.47583, 0.2855).subtract(&Color::new(0.08, 0.1, 0.06));
+        let image = camera.render(&default());
+        assert_abs_diff_eq!(*image.pixel_at(5, 5).unwrap(), expected_color);
+
+        let camera = camera.with_samples_per_pixel(16);
+        let image = camera.render(&default());
+        let again = camera.render(&default());
+        assert_abs_diff_eq!(
+            *image.pixel_at(5, 5).unwrap(),
+            expected_color,
+            epsilon = 0.02
+        );
+        assert_eq!(image.pixel_at(5, 5), again.pixel_at(5, 5));
+    }
+
+    #[test]
+    fn camera_render_threads() {
+        let transform = view_transform(
+            &Tuple::point(0.0, 0.0, -5.0),
+            &Tuple::point(0.0, 0.0, 0.0),
+            &Tuple::vector(0.0, 1.0, 0.0),
+        );
+        let camera = Camera::new(9, 7, PI / 2.0, transform)
+            .with_integrator(Integrator::PathTracer { max_depth: 3 })
+            .with_samples_per_pixel(2);
+        let single = camera.render(&default());
+        let threaded = camera.with_threads(4).render(&default());
+        for y in 0..7 {
+            for x in 0..9 {
+                assert_eq!(single.pixel_at(x, y), threaded.pixel_at(x, y));
+            }
+        }
+    }
+
+    #[test]
+    fn camera_overrides() {
+        let transform = rotation_y(PI / 4.0).mul_matrix(&translation(0.0, -2.0, 5.0));
+        let camera = Camera::new(201, 101, PI / 2.0, transform)
+            .with_samples_per_pixel(4)
+            .with_size(400, 200);
+        assert_eq!((camera.hsize(), camera.vsize()), (400, 200));
+        assert_eq!(camera.samples_per_pixel, 4);
+        assert_abs_diff_eq!(camera.pixel_size, 0.005);
+        let r = camera.ray_for_pixel(200, 100);
+        assert_abs_diff_eq!(r.origin, Tuple::point(0.0, 2.0, -5.0));
+
+        let narrow = camera.with_field_of_view(PI / 4.0);
+        assert!(narrow.pixel_size < 0.005);
+        assert_eq!(narrow.samples_per_pixel, 4);
+    }
+
+    #[test]
+    fn camera_builder() {
+        let from = Tuple::point(0.0, 0.0, 8.0);
+        let to = Tuple::point(0.0, 0.0, 0.0);
+        let up = Tuple::vector(0.0, 1.0, 0.0);
+        let camera = Camera::builder(200, 100)
+            .field_of_view(PI / 2.0)
+            .look_at(from, to, up)
+            .samples_per_pixel(4)
+            .integrator(Integrator::PathTracer { max_depth: 3 })
+            .threads(2)
+            .build();
+        assert_eq!((camera.hsize(), camera.vsize()), (200, 100));
+        assert_abs_diff_eq!(camera.pixel_size, 0.01);
+        assert_eq!(camera.transform, view_transform(&from, &to, &up));
+        assert_eq!(camera.samples_per_pixel, 4);
+        assert_eq!(camera.threads, 2);
+        assert!(!camera.transparent_background());
+
+        let default = Camera::builder(160, 120).build();
+        assert_eq!(default.field_of_view, PI / 3.0);
+        assert_eq!(default.transform, Matrix4::IDENTITY);
+        assert_eq!(default.integrator(), Integrator::Whitted);
+    }
+
+    #[test]
+    fn light_transmission_through_tinted_objects() {
+        let light = PointLight {
+            position: Tuple::point(0.0, 0.0, -10.0),
+            intensity: Color::WHITE,
+            links: LightLinks::ALL,
+        };
+        let glass = Object {
+            object_type: ObjectType::Sphere,
+            material: Material {
+                pattern: Pattern::Constant {
+                    value: Color::new(1.0, 0.5, 0.0),
+                },
+                transparency: 0.8,
+                ..Material::DEFAULT
+            },
+            transform: translation(0.0, 0.0, -5.0),
+            ..Object::SPHERE
+        };
+        let world = World::new(light.clone(), vec![glass]);
+        assert_abs_diff_eq!(
+            world.light_transmission(&light, Tuple::point(0.0, 0.0, 0.0)),
+            Color::new(0.8, 0.4, 0.0)
+        );
+        assert!(!world.is_shadowed(Tuple::point(0.0, 0.0, 0.0)));
+
+        let opaque = Object {
+            transform: translation(0.0, 0.0, -8.0),
+            ..Object::SPHERE
+        };
+        let world = World::new(light.clone(), vec![world.objects[0].clone(), opaque]);
+        assert_abs_diff_eq!(
+            world.light_transmission(&light, Tuple::point(0.0, 0.0, 0.0)),
+            Color::BLACK
+        );
+        assert!(world.is_shadowed(Tuple::point(0.0, 0.0, 0.0)));
+    }
+
+    #[test]
+    fn shade_hit_transparent_shadow() {
+        let light = PointLight {
+            position: Tuple::point(0.0, 0.0, -10.0),
+            intensity: Color::WHITE,
+            links: LightLinks::ALL,
+        };
+        let glass = |z| Object {
+            material: Material {
+                transparency: 0.5,
+                ..Material::DEFAULT
+            },
+            transform: translation(0.0, 0.0, z),
+            ..Object::SPHERE
+        };
+        // Shades the front of `world.objects[index]`, four units along `ray`
+        let shade = |world: &World, index: usize, ray: &Ray| {
+            let intersection = Intersection {
+                t: 4.0,
+                obj: &world.objects[index],
+            };
+            world.shade_hit(&World::prepare_computations(&intersection, ray))
+        };
+
+        // Glass behind an opaque sphere is in its full shadow
+        let world = World::new(light.clone(), vec![glass(10.0), Object::SPHERE]);
+        let r = Ray::new(Tuple::point(0.0, 0.0, 5.0), Tuple::vector(0.0, 0.0, 1.0));
+        assert_abs_diff_eq!(shade(&world, 0, &r), Color::new(0.1, 0.1, 0.1));
+
+        // A sphere behind glass gets half the direct light
+        let r = Ray::new(Tuple::point(0.0, 0.0, -5.0), Tuple::vector(0.0, 0.0, 1.0));
+        let lit = shade(&World::new(light.clone(), vec![Object::SPHERE]), 0, &r);
+        let behind_glass_world = World::new(light, vec![glass(-5.0), Object::SPHERE]);
+        let behind_glass = shade(&behind_glass_world, 1, &r);
+        let ambient = Color::new(0.1, 0.1, 0.1);
+        assert_abs_diff_eq!(
+            behind_glass.subtract(&ambient),
+            lit.subtract(&ambient).scalar_mul(0.5)
+        );
+    }
+
     #[test]
     fn is_shadowed() {
         assert_eq!(default().is_shadowed(Tuple::point(0.0, 10.0, 0.0)), false); // nothing colinear
@@ -467,4 +1027,314 @@ mod tests {
         assert_eq!(default().is_shadowed(Tuple::point(-2.0, 2.0, -2.0)), false);
         // object behind point
     }
+
+    #[test]
+    fn intersect_honours_visibility() {
+        let mut world = default();
+        world.objects[0].visibility.visible_to_camera = false;
+        world.objects[1].visibility.visible_in_reflections = false;
+        let ray = Ray::new(Tuple::point(0.0, 0.0, -5.0), Tuple::vector(0.0, 0.0, 1.0));
+
+        let camera = world.intersect(&ray);
+        assert_eq!(camera.values.len(), 2);
+        assert_abs_diff_eq!(camera.values[0].t, 4.5);
+
+        let secondary = world.intersect(&ray.with_kind(RayKind::Secondary));
+        assert_eq!(secondary.values.len(), 2);
+        assert_abs_diff_eq!(secondary.values[0].t, 4.0);
+
+        let shadow = world.intersect(&ray.with_kind(RayKind::Shadow));
+        assert_eq!(shadow.values.len(), 4);
+    }
+
+    #[test]
+    fn shadow_flags() {
+        let mut world = default();
+        world.objects[0].visibility.casts_shadows = false;
+        world.objects[1].visibility.casts_shadows = false;
+        assert!(!world.is_shadowed(Tuple::point(10.0, -10.0, 10.0)));
+
+        // Invisible to the camera but still casting a shadow
+        let mut world = default();
+        world.objects[0].visibility.visible_to_camera = false;
+        world.objects[1].visibility.visible_to_camera = false;
+        assert!(world.is_shadowed(Tuple::point(10.0, -10.0, 10.0)));
+        let ray = Ray::new(Tuple::point(0.0, 0.0, -5.0), Tuple::vector(0.0, 0.0, 1.0));
+        assert_abs_diff_eq!(world.color_at(&ray), Color::BLACK);
+    }
+
+    #[test]
+    fn shade_hit_without_receiving_shadows() {
+        let light = PointLight {
+            position: Tuple::point(0.0, 0.0, -10.0),
+            intensity: Color::WHITE,
+            links: LightLinks::ALL,
+        };
+        let s1 = Object {
+            visibility: Visibility {
+                receives_shadows: false,
+                ..Visibility::ALL
+            },
+            transform: translation(0.0, 0.0, 10.0),
+            ..Object::SPHERE
+        };
+        let world = World::new(light, vec![Object::SPHERE, s1]);
+        let r = Ray::new(Tuple::point(0.0, 0.0, 5.0), Tuple::vector(0.0, 0.0, 1.0));
+        let intersection = Intersection {
+            t: 4.0,
+            obj: &world.objects[1],
+        };
+        let comps = World::prepare_computations(&intersection, &r);
+
+        assert_abs_diff_eq!(world.shade_hit(&comps), Color::new(1.9, 1.9, 1.9));
+    }
+
+    #[test]
+    fn shade_hit_skips_unlinked_lights() {
+        let key = PointLight {
+            position: Tuple::point(0.0, 0.0, -10.0),
+            intensity: Color::WHITE,
+            links: LightLinks::ALL,
+        };
+        let fill = PointLight {
+            position: Tuple::point(0.0, 0.0, -10.0),
+            intensity: Color::new(0.5, 0.5, 0.5),
+            links: LightLinks {
+                include: vec!["hero".into()],
+                exclude: Vec::new(),
+            },
+        };
+        let hero = Object {
+            name: Some("hero".into()),
+            ..Object::SPHERE
+        };
+        let world = World::new(key, vec![hero, Object::SPHERE]).with_light(fill);
+        let r = Ray::new(Tuple::point(0.0, 0.0, -5.0), Tuple::vector(0.0, 0.0, 1.0));
+
+        let intersection = Intersection {
+            t: 4.0,
+            obj: &world.objects[1],
+        };
+        let comps = World::prepare_computations(&intersection, &r);
+        assert_abs_diff_eq!(world.shade_hit(&comps), Color::new(1.9, 1.9, 1.9));
+
+        let intersection = Intersection {
+            t: 4.0,
+            obj: &world.objects[0],
+        };
+        let comps = World::prepare_computations(&intersection, &r);
+        assert_abs_diff_eq!(world.shade_hit(&comps), Color::new(2.85, 2.85, 2.85));
+    }
+
+    #[test]
+    fn shadow_catcher_alpha() {
+        let light = PointLight {
+            position: Tuple::point(0.0, 10.0, 0.0),
+            intensity: Color::WHITE,
+            links: LightLinks::ALL,
+        };
+        let catcher = Object {
+            material: Material {
+                shadow_catcher: true,
+                ..Material::DEFAULT
+            },
+            ..Object::PLANE
+        };
+        let blocker = Object {
+            transform: translation(0.0, 2.0, 0.0),
+            ..Object::SPHERE
+        };
+        let world = World::new(light, vec![catcher, blocker]);
+        let down = Tuple::vector(0.0, -1.0, 0.0);
+
+        let r = Ray::new(Tuple::point(0.0, 0.5, 0.0), down);
+        let intersection = Intersection {
+            t: 0.5,
+            obj: &world.objects[0],
+        };
+        let comps = World::prepare_computations(&intersection, &r);
+        assert_abs_diff_eq!(world.shadow_catcher_alpha(&comps), 1.0);
+
+        let r = Ray::new(Tuple::point(5.0, 0.5, 0.0), down);
+        let comps = World::prepare_computations(&intersection, &r);
+        assert_abs_diff_eq!(world.shadow_catcher_alpha(&comps), 0.0);
+    }
+
+    #[test]
+    fn shadow_catcher_hidden_from_secondary_rays() {
+        let catcher = Object {
+            material: Material {
+                shadow_catcher: true,
+                ..Material::DEFAULT
+            },
+            ..Object::PLANE
+        };
+        let world = World::new(DEFAULT_LIGHT, vec![catcher]);
+        let ray = Ray::new(Tuple::point(0.0, 1.0, 0.0), Tuple::vector(0.0, -1.0, 0.0));
+
+        assert_eq!(world.intersect(&ray).values.len(), 1);
+        let shadow = world.intersect(&ray.with_kind(RayKind::Shadow));
+        assert_eq!(shadow.values.len(), 1);
+        let secondary = world.intersect(&ray.with_kind(RayKind::Secondary));
+        assert!(secondary.values.is_empty());
+    }
+
+    #[test]
+    fn camera_render_shadow_catcher() {
+        let light = PointLight {
+            position: Tuple::point(0.0, 10.0, 0.0),
+            intensity: Color::WHITE,
+            links: LightLinks::ALL,
+        };
+        let catcher = Object {
+            material: Material {
+                shadow_catcher: true,
+                ..Material::DEFAULT
+            },
+            ..Object::PLANE
+        };
+        let glass = Object {
+            material: Material {
+                transparency: 0.25,
+                ..Material::DEFAULT
+            },
+            transform: translation(0.0, 2.0, 0.0),
+            ..Object::SPHERE
+        };
+        let world = World::new(light, vec![catcher, glass]);
+        let camera = Camera::new(
+            1,
+            1,
+            PI / 100.0,
+            view_transform(
+                &Tuple::point(0.0, 0.5, 0.0),
+                &Tuple::point(0.0, 0.0, 0.0),
+                &Tuple::vector(0.0, 0.0, 1.0),
+            ),
+        )
+        .with_transparent_background(true);
+        let image = camera.render(&world);
+        assert_abs_diff_eq!(*image.pixel_at(0, 0).unwrap(), Color::BLACK);
+        assert_abs_diff_eq!(image.alpha_at(0, 0).unwrap(), 0.75);
+
+        // Over an opaque background the shadow darkens the background instead
+        let world = world.with_environment(constant_environment(Color::new(0.4, 0.4, 0.4)));
+        let shadow = camera.render(&world).alpha_at(0, 0).unwrap();
+        let image = camera.with_transparent_background(false).render(&world);
+        let background = 0.4 * (1.0 - shadow);
+        assert_abs_diff_eq!(
+            *image.pixel_at(0, 0).unwrap(),
+            Color::new(background, background, background)
+        );
+        assert_abs_diff_eq!(image.alpha_at(0, 0).unwrap(), 1.0);
+    }
+
+    #[test]
+    fn camera_render_transparent_background() {
+        let world = default().with_environment(constant_environment(Color::new(0.5, 0.5, 0.5)));
+        let from = Tuple::point(0.0, 0.0, -5.0);
+        let to = Tuple::point(0.0, 0.0, 0.0);
+        let up = Tuple::vector(0.0, 1.0, 0.0);
+        let camera = Camera::new(11, 11, PI / 2.0, view_transform(&from, &to, &up))
+            .with_transparent_background(true);
+        let image = camera.render(&world);
+
+        assert_abs_diff_eq!(image.alpha_at(5, 5).unwrap(), 1.0);
+        assert_abs_diff_eq!(image.alpha_at(0, 0).unwrap(), 0.0);
+        assert_abs_diff_eq!(*image.pixel_at(0, 0).unwrap(), Color::BLACK);
+
+        // Edge pixels are partly covered when supersampled
+        let camera = camera.with_samples_per_pixel(64);
+        let image = camera.render(&world);
+        let edge = (0..11)
+            .map(|x| image.alpha_at(x, 5).unwrap())
+            .find(|alpha| *alpha > 0.0 && *alpha < 1.0);
+        assert!(edge.is_some());
+    }
+
+    fn constant_environment(color: Color) -> EnvironmentMap {
+        let mut image = Canvas::new(16, 8);
+        for y in 0..image.height {
+            for x in 0..image.width {
+                image.write_pixel(x, y, &color);
+            }
+        }
+        EnvironmentMap::equirectangular(image)
+    }
+
+    #[test]
+    fn world_color_at_miss_samples_environment() {
+        let world = default().with_environment(constant_environment(Color::new(0.2, 0.3, 0.4)));
+        let ray = Ray::new(Tuple::point(0.0, 0.0, -5.0), Tuple::vector(0.0, 1.0, 0.0));
+        assert_abs_diff_eq!(world.color_at(&ray), Color::new(0.2, 0.3, 0.4));
+    }
+
+    #[test]
+    fn shade_hit_environment_replaces_ambient() {
+        let light = PointLight {
+            position: Tuple::point(0.0, -10.0, 0.0),
+            intensity: Color::WHITE,
+            links: LightLinks::ALL,
+        };
+        let environment = constant_environment(Color::WHITE).with_light_samples(4096);
+        let world = World::new(light, vec![Object::SPHERE]).with_environment(environment);
+
+        let ray = Ray::new(Tuple::point(0.0, 5.0, 0.0), Tuple::vector(0.0, -1.0, 0.0));
+        let comps = World::prepare_computations(
+            &Intersection {
+                obj: world.objects.first().unwrap(),
+                t: 4.0,
+            },
+            &ray,
+        );
+
+        let diffuse = Material::DEFAULT_DIFFUSE;
+        assert_abs_diff_eq!(
+            world.shade_hit(&comps),
+            Color::new(diffuse, diffuse, diffuse),
+            epsilon = 0.05
+        );
+    }
+
+    #[test]
+    fn shade_hit_environment_microfacet() {
+        let light = PointLight {
+            position: Tuple::point(0.0, -10.0, 0.0),
+            intensity: Color::WHITE,
+            links: LightLinks::ALL,
+        };
+        let environment = || constant_environment(Color::WHITE).with_light_samples(4096);
+        let sphere = |metallic| Object {
+            material: Material {
+                shading: ShadingModel::Microfacet {
+                    metallic,
+                    roughness: 0.5,
+                },
+                ..Material::DEFAULT
+            },
+            ..Object::SPHERE
+        };
+        let ray = Ray::new(Tuple::point(0.0, 5.0, 0.0), Tuple::vector(0.0, -1.0, 0.0));
+        let shade = |world: &World| {
+            world.shade_hit(&World::prepare_computations(
+                &Intersection {
+                    obj: world.objects.first().unwrap(),
+                    t: 4.0,
+                },
+                &ray,
+            ))
+        };
+
+        // Metals have no diffuse lobe, and the Phong `diffuse` is ignored
+        let metal = World::new(light.clone(), vec![sphere(1.0)]).with_environment(environment());
+        assert_abs_diff_eq!(shade(&metal), Color::BLACK);
+
+        // Dielectrics lose the light reflected at normal incidence
+        let plastic = World::new(light, vec![sphere(0.0)]).with_environment(environment());
+        assert_abs_diff_eq!(
+            shade(&plastic),
+            Color::new(0.96, 0.96, 0.96),
+            epsilon = 0.05
+        );
+    }
 }
@@ -0,0 +1,517 @@
+//! The subset of YAML used by scene files: block mappings and sequences laid
+//! out by indentation, `[ ... ]` flow sequences, plain and quoted scalars,
+//! and `#` comments. Every node remembers where it started so that errors
+//! can point at it.
+
+use std::fmt;
+
+use crate::scene::SceneError;
+
+/// One-based line and column in a source file.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}, column {}", self.line, self.column)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Node {
+    pub value: Value,
+    pub position: Position,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Scalar(String),
+    Sequence(Vec<Node>),
+    Mapping(Vec<Entry>),
+}
+
+/// A key and its value in a mapping.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Entry {
+    pub key: String,
+    pub key_position: Position,
+    pub value: Node,
+}
+
+impl Node {
+    /// Describes the kind of node for error messages.
+    pub fn kind(&self) -> &'static str {
+        match self.value {
+            Value::Scalar(_) => "a value",
+            Value::Sequence(_) => "a list",
+            Value::Mapping(_) => "a mapping",
+        }
+    }
+}
+
+/// A non-blank line with its comment removed.
+struct Line<'a> {
+    number: usize,
+    indent: usize,
+    text: &'a str,
+}
+
+impl Line<'_> {
+    fn position(&self) -> Position {
+        Position {
+            line: self.number,
+            column: self.indent + 1,
+        }
+    }
+}
+
+/// Removes a `#` comment, which must start the line or follow whitespace and
+/// may not be inside quotes.
+fn strip_comment(text: &str) -> &str {
+    let mut quote = None;
+    let mut previous = ' ';
+    for (i, c) in text.char_indices() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if c == '"' || c == '\'' => quote = Some(c),
+            None if c == '#' && previous.is_whitespace() => return &text[..i],
+            None => {}
+        }
+        previous = c;
+    }
+    text
+}
+
+fn is_sequence_item(text: &str) -> bool {
+    text == "-" || text.starts_with("- ")
+}
+
+/// Splits `key: value` into its key and the rest of the line after the
+/// colon, which may be empty.
+fn split_key(text: &str) -> Option<(&str, &str)> {
+    if text.starts_with(['[', '"', '\'', '{']) {
+        return None;
+    }
+    let bytes = text.as_bytes();
+    let colon = (0..bytes.len())
+        .find(|i| bytes[*i] == b':' && bytes.get(i + 1).is_none_or(|b| *b == b' '))?;
+    let key = text[..colon].trim_end();
+    if key.is_empty() {
+        None
+    } else {
+        Some((key, &text[colon + 1..]))
+    }
+}
+
+struct Parser<'a> {
+    lines: Vec<Line<'a>>,
+    index: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn block(&mut self, indent: usize) -> Result<Node, SceneError> {
+        if is_sequence_item(self.lines[self.index].text) {
+            self.sequence(indent)
+        } else {
+            self.mapping(indent)
+        }
+    }
+
+    /// The block nested under a line ending in `-` or `key:`.
+    fn nested(&mut self, indent: usize, position: Position) -> Result<Node, SceneError> {
+        match self.lines.get(self.index) {
+            Some(line) if line.indent > indent => self.block(line.indent),
+            _ => Err(SceneError::invalid(position, "missing value")),
+        }
+    }
+
+    fn sequence(&mut self, indent: usize) -> Result<Node, SceneError> {
+        let position = self.lines[self.index].position();
+        let mut items = Vec::new();
+        while let Some(line) = self.lines.get_mut(self.index) {
+            if line.indent < indent || !is_sequence_item(line.text) {
+                break;
+            }
+            if line.indent > indent {
+                return Err(SceneError::invalid(
+                    line.position(),
+                    "unexpected indentation",
+                ));
+            }
+
+            let item_position = line.position();
+            let rest = &line.text[1..];
+            let content = rest.trim_start();
+            if content.is_empty() {
+                self.index += 1;
+                items.push(self.nested(indent, item_position)?);
+            } else {
+                // The item's content is parsed as if it started its own line,
+                // so a mapping can continue on the lines below it
+                line.indent += 1 + rest.len() - content.len();
+                line.text = content;
+                if is_sequence_item(content) || split_key(content).is_some() {
+                    let indent = line.indent;
+                    items.push(self.block(indent)?);
+                } else {
+                    items.push(inline(content, line.position())?);
+                    self.index += 1;
+                }
+            }
+        }
+        Ok(Node {
+            value: Value::Sequence(items),
+            position,
+        })
+    }
+
+    fn mapping(&mut self, indent: usize) -> Result<Node, SceneError> {
+        let position = self.lines[self.index].position();
+        let mut entries: Vec<Entry> = Vec::new();
+        while let Some(line) = self.lines.get(self.index) {
+            if line.indent < indent {
+                break;
+            }
+            if line.indent > indent {
+                return Err(SceneError::invalid(
+                    line.position(),
+                    "unexpected indentation",
+                ));
+            }
+            if is_sequence_item(line.text) {
+                return Err(SceneError::invalid(
+                    line.position(),
+                    "expected `key: value`, found a list item",
+                ));
+            }
+
+            let key_position = line.position();
+            let (key, rest) = split_key(line.text)
+                .ok_or_else(|| SceneError::invalid(key_position, "expected `key: value`"))?;
+            if entries.iter().any(|entry| entry.key == key) {
+                return Err(SceneError::invalid(
+                    key_position,
+                    format!("duplicate key `{}`", key),
+                ));
+            }
+
+            let content = rest.trim_start();
+            let value = if content.is_empty() {
+                self.index += 1;
+                match self.lines.get(self.index) {
+                    Some(next) if next.indent > indent => self.block(next.indent)?,
+                    // A list may sit at the same indentation as its key
+                    Some(next) if next.indent == indent && is_sequence_item(next.text) => {
+                        self.sequence(indent)?
+                    }
+                    _ => {
+                        return Err(SceneError::invalid(
+                            key_position,
+                            format!("missing value for `{}`", key),
+                        ))
+                    }
+                }
+            } else {
+                let column = line.indent + 1 + line.text.len() - content.len();
+                let value = inline(
+                    content,
+                    Position {
+                        line: line.number,
+                        column,
+                    },
+                )?;
+                self.index += 1;
+                value
+            };
+            entries.push(Entry {
+                key: key.to_string(),
+                key_position,
+                value,
+            });
+        }
+        Ok(Node {
+            value: Value::Mapping(entries),
+            position,
+        })
+    }
+}
+
+/// Parser for a value written on a single line.
+struct Inline<'a> {
+    chars: Vec<(usize, char)>,
+    text: &'a str,
+    index: usize,
+    start: Position,
+}
+
+impl Inline<'_> {
+    fn position(&self) -> Position {
+        Position {
+            line: self.start.line,
+            column: self.start.column + self.index,
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.index).map(|(_, c)| *c)
+    }
+
+    fn skip_spaces(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.index += 1;
+        }
+    }
+
+    fn value(&mut self, in_flow: bool) -> Result<Node, SceneError> {
+        self.skip_spaces();
+        let position = self.position();
+        match self.peek() {
+            Some('[') => {
+                self.index += 1;
+                let mut items = Vec::new();
+                loop {
+                    self.skip_spaces();
+                    if self.peek() == Some(']') {
+                        self.index += 1;
+                        break;
+                    }
+                    items.push(self.value(true)?);
+                    self.skip_spaces();
+                    match self.peek() {
+                        Some(',') => self.index += 1,
+                        Some(']') => {}
+                        _ => {
+                            return Err(SceneError::invalid(self.position(), "expected `,` or `]`"))
+                        }
+                    }
+                }
+                Ok(Node {
+                    value: Value::Sequence(items),
+                    position,
+                })
+            }
+            Some('{') => Err(SceneError::invalid(
+                position,
+                "flow mappings are not supported; put each key on its own line",
+            )),
+            Some(quote @ ('"' | '\'')) => {
+                self.index += 1;
+                let mut text = String::new();
+                loop {
+                    match self.peek() {
+                        None => return Err(SceneError::invalid(position, "unterminated string")),
+                        Some(c) if c == quote => break,
+                        Some('\\') if quote == '"' => {
+                            self.index += 1;
+                            text.push(match self.peek() {
+                                Some('n') => '\n',
+                                Some('t') => '\t',
+                                Some(c @ ('"' | '\\')) => c,
+                                _ => {
+                                    return Err(SceneError::invalid(
+                                        self.position(),
+                                        "unknown escape sequence",
+                                    ))
+                                }
+                            });
+                        }
+                        Some(c) => text.push(c),
+                    }
+                    self.index += 1;
+                }
+                self.index += 1;
+                Ok(Node {
+                    value: Value::Scalar(text),
+                    position,
+                })
+            }
+            _ => {
+                let start = self.index;
+                while self
+                    .peek()
+                    .is_some_and(|c| !(in_flow && (c == ',' || c == ']' || c == '[')))
+                {
+                    self.index += 1;
+                }
+                let from = self.chars.get(start).map_or(self.text.len(), |(i, _)| *i);
+                let to = self
+                    .chars
+                    .get(self.index)
+                    .map_or(self.text.len(), |(i, _)| *i);
+                let text = self.text[from..to].trim_end();
+                if text.is_empty() {
+                    return Err(SceneError::invalid(position, "expected a value"));
+                }
+                Ok(Node {
+                    value: Value::Scalar(text.to_string()),
+                    position,
+                })
+            }
+        }
+    }
+}
+
+fn inline(text: &str, start: Position) -> Result<Node, SceneError> {
+    let mut parser = Inline {
+        chars: text.char_indices().collect(),
+        text,
+        index: 0,
+        start,
+    };
+    let node = parser.value(false)?;
+    parser.skip_spaces();
+    match parser.peek() {
+        None => Ok(node),
+        Some(c) => Err(SceneError::invalid(
+            parser.position(),
+            format!("unexpected `{}` after value", c),
+        )),
+    }
+}
+
+/// Parses a whole document. An empty document is an empty sequence.
+pub fn parse(source: &str) -> Result<Node, SceneError> {
+    let mut lines = Vec::new();
+    for (i, raw) in source.lines().enumerate() {
+        let text = strip_comment(raw).trim_end();
+        let content = text.trim_start_matches(' ');
+        let indent = text.len() - content.len();
+        let position = Position {
+            line: i + 1,
+            column: indent + 1,
+        };
+        if content.starts_with('\t') {
+            return Err(SceneError::invalid(
+                position,
+                "tabs cannot be used for indentation",
+            ));
+        }
+        if content.is_empty() || (indent == 0 && content == "---") {
+            continue;
+        }
+        lines.push(Line {
+            number: i + 1,
+            indent,
+            text: content,
+        });
+    }
+
+    let first = match lines.first() {
+        Some(line) => line.indent,
+        None => {
+            return Ok(Node {
+                value: Value::Sequence(Vec::new()),
+                position: Position { line: 1, column: 1 },
+            })
+        }
+    };
+    let mut parser = Parser { lines, index: 0 };
+    let node = parser.block(first)?;
+    match (parser.lines.get(parser.index), &node.value) {
+        (None, _) => Ok(node),
+        (Some(line), Value::Sequence(_)) => Err(SceneError::invalid(
+            line.position(),
+            "expected a `-` list item",
+        )),
+        (Some(line), _) => Err(SceneError::invalid(
+            line.position(),
+            "unexpected indentation",
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, Entry, Node, Position, Value};
+
+    fn scalar(node: &Node) -> &str {
+        match &node.value {
+            Value::Scalar(text) => text,
+            _ => panic!("expected a scalar"),
+        }
+    }
+
+    fn items(node: &Node) -> &[Node] {
+        match &node.value {
+            Value::Sequence(items) => items,
+            _ => panic!("expected a sequence"),
+        }
+    }
+
+    fn entries(node: &Node) -> &[Entry] {
+        match &node.value {
+            Value::Mapping(entries) => entries,
+            _ => panic!("expected a mapping"),
+        }
+    }
+
+    #[test]
+    fn parse_nested_blocks() {
+        let source = "\
+# a scene
+- add: sphere   # inline comment
+  material:
+    color: [1, 0.5, \"a # b\"]
+  transform:
+  - [ scale, 2 ]
+  -
+    - nested
+- plain text: with colon
+";
+        let document = parse(source).unwrap();
+        let top = items(&document);
+        assert_eq!(top.len(), 2);
+
+        let sphere = entries(&top[0]);
+        assert_eq!(sphere[0].key, "add");
+        assert_eq!(scalar(&sphere[0].value), "sphere");
+        assert_eq!(sphere[0].key_position, Position { line: 2, column: 3 });
+        assert_eq!(sphere[0].value.position, Position { line: 2, column: 8 });
+
+        let color = &entries(&sphere[1].value)[0].value;
+        let color_items: Vec<&str> = items(color).iter().map(scalar).collect();
+        assert_eq!(color_items, ["1", "0.5", "a # b"]);
+        assert_eq!(
+            items(color)[1].position,
+            Position {
+                line: 4,
+                column: 16
+            }
+        );
+
+        let transform = items(&sphere[2].value);
+        assert_eq!(transform.len(), 2);
+        assert_eq!(scalar(&items(&transform[0])[1]), "2");
+        assert_eq!(scalar(&items(&transform[1])[0]), "nested");
+
+        assert_eq!(entries(&top[1])[0].key, "plain text");
+        assert_eq!(scalar(&entries(&top[1])[0].value), "with colon");
+    }
+
+    #[test]
+    fn parse_errors_have_positions() {
+        let error = |source: &str| parse(source).unwrap_err().to_string();
+        assert_eq!(
+            error("a: 1\n   b: 2\n"),
+            "line 2, column 4: unexpected indentation"
+        );
+        assert_eq!(error("a: 1\na: 2\n"), "line 2, column 1: duplicate key `a`");
+        assert_eq!(error("a: [1, 2\n"), "line 1, column 9: expected `,` or `]`");
+        assert_eq!(
+            error("a:\nb: 1\n"),
+            "line 1, column 1: missing value for `a`"
+        );
+        assert_eq!(
+            error("- a\nb: 1\n"),
+            "line 2, column 1: expected a `-` list item"
+        );
+        assert_eq!(
+            error("a: \"open\n"),
+            "line 1, column 4: unterminated string"
+        );
+    }
+}
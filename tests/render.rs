@@ -0,0 +1,78 @@
+use std::f64::consts::PI;
+
+use approx::assert_abs_diff_eq;
+use rusty_ray::{
+    Camera, Color, Integrator, Material, Matrix4, Object, ObjectType, PointLight, Tuple, World,
+};
+
+fn camera(hsize: i32, vsize: i32) -> Camera {
+    Camera::builder(hsize, vsize)
+        .field_of_view(PI / 2.0)
+        .look_at(
+            Tuple::point(0.0, 0.0, -5.0),
+            Tuple::point(0.0, 0.0, 0.0),
+            Tuple::vector(0.0, 1.0, 0.0),
+        )
+        .build()
+}
+
+/// The two concentric spheres used throughout the book.
+fn default_world() -> World {
+    let outer = Object::builder(ObjectType::Sphere)
+        .material(
+            Material::builder()
+                .color(Color::new(0.8, 1.0, 0.6))
+                .diffuse(0.7)
+                .specular(0.2)
+                .build(),
+        )
+        .build();
+    let inner = Object::builder(ObjectType::Sphere)
+        .transform(Matrix4::IDENTITY.scale(0.5, 0.5, 0.5))
+        .build();
+    let light = PointLight::builder(Tuple::point(-10.0, 10.0, -10.0)).build();
+    World::new(light, vec![outer, inner])
+}
+
+#[test]
+fn render_default_world() {
+    let canvas = camera(11, 11).render(&default_world());
+    assert_abs_diff_eq!(
+        *canvas.pixel_at(5, 5).unwrap(),
+        Color::new(0.38066, 0.47583, 0.2855),
+        epsilon = 1e-4
+    );
+    // The corners miss everything and show the black background
+    assert_abs_diff_eq!(*canvas.pixel_at(0, 0).unwrap(), Color::BLACK);
+}
+
+#[test]
+fn path_traced_render_is_repeatable_across_threads() {
+    let camera = camera(8, 6)
+        .with_integrator(Integrator::PathTracer { max_depth: 3 })
+        .with_samples_per_pixel(4);
+    let world = default_world();
+
+    let single = camera.render(&world);
+    let threaded = camera.with_threads(3).render(&world);
+    for y in 0..6 {
+        for x in 0..8 {
+            assert_eq!(single.pixel_at(x, y), threaded.pixel_at(x, y));
+        }
+    }
+}
+
+#[test]
+fn matrices_and_tuples() {
+    let transform = Matrix4::IDENTITY.translate(5.0, -3.0, 2.0);
+    let p = Tuple::point(-3.0, 4.0, 5.0);
+    assert_abs_diff_eq!(transform.mul_tuple(&p), Tuple::point(2.0, 1.0, 7.0));
+    assert_abs_diff_eq!(
+        transform.mul_matrix(&transform.inverse()),
+        Matrix4::IDENTITY
+    );
+    assert_abs_diff_eq!(
+        transform.mul_tuple(&Tuple::vector(1.0, 0.0, 0.0)),
+        Tuple::vector(1.0, 0.0, 0.0)
+    );
+}
@@ -0,0 +1,63 @@
+use rusty_ray::{load_scene, parse_scene, read_png, write_png, Color, PngOptions, SceneError};
+
+#[test]
+fn render_example_scene() {
+    let scene = load_scene("examples/chapter-10.yaml").unwrap();
+    let camera = scene.camera.with_size(64, 48);
+    let canvas = camera.render(&scene.world);
+    assert_eq!((canvas.width, canvas.height), (64, 48));
+
+    // The image survives a trip through PNG at 8-bit precision
+    let mut png = Vec::new();
+    write_png(&canvas, &mut png, &PngOptions::DEFAULT).unwrap();
+    let decoded = read_png(&mut png.as_slice()).unwrap();
+    for (x, y) in [(0, 0), (32, 24), (63, 47)] {
+        let expected = canvas.pixel_at(x, y).unwrap();
+        let actual = decoded.pixel_at(x, y).unwrap();
+        for (a, b) in [
+            (actual.red, expected.red),
+            (actual.green, expected.green),
+            (actual.blue, expected.blue),
+        ] {
+            assert!((a - b.clamp(0.0, 1.0)).abs() <= 0.5 / 255.0 + 1e-9);
+        }
+    }
+}
+
+#[test]
+fn scene_errors_report_positions() {
+    let source = "- add: light\n  at: [0, 1, 0]\n  colour: [1, 1, 1]\n";
+    match parse_scene(source) {
+        Err(SceneError::Invalid {
+            position, message, ..
+        }) => {
+            assert_eq!((position.line, position.column), (3, 3));
+            assert_eq!(message, "unknown key `colour` in light");
+        }
+        _ => panic!("expected an invalid scene"),
+    }
+}
+
+#[test]
+fn scene_builds_world() {
+    let source = "
+- add: camera
+  width: 4
+  height: 4
+  field-of-view: 1.0
+  from: [0, 0, -5]
+  to: [0, 0, 0]
+  up: [0, 1, 0]
+- add: light
+  at: [0, 0, -10]
+- add: sphere
+  material:
+    color: [0, 0, 1]
+    ambient: 1
+    diffuse: 0
+    specular: 0
+";
+    let scene = parse_scene(source).unwrap();
+    let canvas = scene.camera.render(&scene.world);
+    assert_eq!(*canvas.pixel_at(2, 2).unwrap(), Color::new(0.0, 0.0, 1.0));
+}